@@ -0,0 +1,358 @@
+use crate::aoi::{Aoi, Coverage};
+use crate::bands::{Band, BandAsset, BandError, BandSet};
+use crate::index::{Rescale, SpectralIndex, Stats};
+use crate::mask::{MaskSummary, SclMask};
+use crate::raster::{self, Bounds, GeoTransform, Raster, Window};
+use crate::stac::Item;
+use crate::utm::Utm;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Bands read to compute `index` over a scene: its inputs and the SCL layer
+pub fn analysis_bands(index: SpectralIndex) -> Vec<Band> {
+    index.bands().iter().copied().chain([Band::Scl]).collect()
+}
+
+/// Spectral index statistics of one scene over the request's AOI
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Analysis {
+    /// EPSG code of the scene's UTM grid
+    pub epsg: u32,
+    /// Envelope of the AOI in the scene's CRS
+    pub bounds: Bounds,
+    /// Rule used to rasterize the AOI onto the window
+    pub coverage: Coverage,
+    /// Area of the AOI polygons, hectares
+    pub area_ha: f64,
+    /// Area of the rasterized AOI (sum of pixel weights times pixel area), hectares
+    pub pixel_area_ha: f64,
+    /// Grid of the analysed window, that of the index's finest band
+    pub transform: GeoTransform,
+    pub width: usize,
+    pub height: usize,
+    /// GeoJSON polygon outlining the analysed pixels, in WGS84
+    pub footprint: Value,
+    pub mask: MaskSummary,
+    pub index: SpectralIndex,
+    /// `None` when no pixel survived masking
+    pub stats: Option<Stats>,
+}
+
+/// Masked index values of one scene over the AOI window, before reduction to statistics
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneIndex {
+    /// EPSG code of the scene's UTM grid
+    pub epsg: u32,
+    pub index: SpectralIndex,
+    /// Envelope of the AOI in the scene's CRS
+    pub bounds: Bounds,
+    /// Index values on the window of the index's finest band, NaN where masked or undefined
+    pub values: Raster<f32>,
+    /// AOI weight of each pixel of `values`, see [`Aoi::rasterize`]
+    pub weights: Vec<f32>,
+    pub mask: MaskSummary,
+}
+
+/// Errors raised when an item's metadata cannot serve the request, before any pixel is read
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneError {
+    /// The item has no `proj:epsg`
+    NoEpsg { item_id: String },
+    /// The item's `proj:epsg` is not a WGS84 UTM zone
+    NotUtm { item_id: String, epsg: u32 },
+    /// The AOI's envelope does not intersect the asset's extent
+    OutsideTile { item_id: String, asset: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::NoEpsg { item_id } => write!(f, "Item {} has no proj:epsg", item_id),
+            SceneError::NotUtm { item_id, epsg } => {
+                write!(f, "Item {} is in EPSG:{}, not a WGS84 UTM zone", item_id, epsg)
+            }
+            SceneError::OutsideTile { item_id, asset } => {
+                write!(f, "AOI does not intersect asset {} of {}", asset, item_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// Whether `error` comes from the scene's metadata, a [`SceneError`] or a [`BandError`],
+/// rather than from fetching or decoding its pixels
+pub fn is_scene_error(error: &anyhow::Error) -> bool {
+    error.is::<SceneError>() || error.is::<BandError>()
+}
+
+/// Reads the AOI window of `bands` from `item` and computes masked `index` statistics
+///
+/// See [`read_scene_index`] for how the window is read and masked.
+pub async fn analyze_scene(
+    item: &Item,
+    bands: &BandSet,
+    index: SpectralIndex,
+    aoi: &Aoi,
+    scl_mask: &SclMask,
+    coverage: Coverage,
+) -> Result<Analysis> {
+    let scene = read_scene_index(item, bands, index, aoi, scl_mask, coverage).await?;
+    Analysis::new(aoi, coverage, scene)
+}
+
+/// Reads the AOI window of `bands` from `item` and computes the masked `index` values
+///
+/// `aoi` is in WGS84 and is projected into the scene's UTM zone. The window of the
+/// index's finest band covering its envelope sets the analysis grid; coarser bands,
+/// such as the 20m SWIR bands next to 10m NIR, are read for the same extent and
+/// resampled onto it by nearest neighbour. The SCL window is used the same way to
+/// mask clouds, shadows and the other classes excluded by `scl_mask`, and the AOI
+/// polygons are rasterized onto the grid according to `coverage`.
+pub async fn read_scene_index(
+    item: &Item,
+    bands: &BandSet,
+    index: SpectralIndex,
+    aoi: &Aoi,
+    scl_mask: &SclMask,
+    coverage: Coverage,
+) -> Result<SceneIndex> {
+    let mut windows = Windows::default();
+    windows.read_index(item, bands, index, aoi, scl_mask, coverage).await
+}
+
+/// Reads several indices of `item` as [`read_scene_index`] does, in the order given
+///
+/// Each band window is read once and shared by every index that uses it on the
+/// same grid, e.g. the red and NIR of SAVI and NDVI.
+pub async fn read_scene_indices(
+    item: &Item,
+    bands: &BandSet,
+    indices: &[SpectralIndex],
+    aoi: &Aoi,
+    scl_mask: &SclMask,
+    coverage: Coverage,
+) -> Result<Vec<SceneIndex>> {
+    let mut windows = Windows::default();
+    let mut scenes = Vec::with_capacity(indices.len());
+    for &index in indices {
+        scenes.push(windows.read_index(item, bands, index, aoi, scl_mask, coverage).await?);
+    }
+    Ok(scenes)
+}
+
+/// Band windows already read from one scene, keyed by band and requested extent
+#[derive(Default)]
+struct Windows {
+    bands: Vec<(Band, Bounds, Raster<u16>)>,
+    scl: Option<(Bounds, Raster<u8>)>,
+}
+
+impl Windows {
+    async fn band(&mut self, asset: &BandAsset, bounds: &Bounds) -> Result<Raster<u16>> {
+        let cached = self.bands.iter().find(|(band, b, _)| *band == asset.band && b == bounds);
+        if let Some((_, _, window)) = cached {
+            return Ok(window.clone());
+        }
+        let window = raster::read_band_window::<u16>(asset, bounds).await?;
+        self.bands.push((asset.band, *bounds, window.clone()));
+        Ok(window)
+    }
+
+    async fn scl(&mut self, asset: &BandAsset, bounds: &Bounds) -> Result<Raster<u8>> {
+        if let Some((b, window)) = &self.scl {
+            if b == bounds {
+                return Ok(window.clone());
+            }
+        }
+        let window = raster::read_band_window::<u8>(asset, bounds).await?;
+        self.scl = Some((*bounds, window.clone()));
+        Ok(window)
+    }
+
+    async fn read_index(
+        &mut self,
+        item: &Item,
+        bands: &BandSet,
+        index: SpectralIndex,
+        aoi: &Aoi,
+        scl_mask: &SclMask,
+        coverage: Coverage,
+    ) -> Result<SceneIndex> {
+        let epsg =
+            item.properties.epsg.ok_or_else(|| SceneError::NoEpsg { item_id: item.id.clone() })?;
+        let utm = Utm::from_epsg(epsg)
+            .map_err(|_| SceneError::NotUtm { item_id: item.id.clone(), epsg })?;
+        let projected = aoi.project(&utm);
+        let bounds = projected.bounds();
+
+        let assets =
+            index.bands().iter().map(|&band| bands.get(band)).collect::<Result<Vec<_>, _>>()?;
+        let finest = assets
+            .iter()
+            .min_by(|a, b| pixel_size(a).total_cmp(&pixel_size(b)))
+            .ok_or_else(|| anyhow::anyhow!("{} has no input bands", index))?;
+        if Window::for_bounds(&GeoTransform(finest.transform), finest.shape, &bounds).is_none() {
+            let asset = finest.key.clone();
+            return Err(SceneError::OutsideTile { item_id: item.id.clone(), asset }.into());
+        }
+        let grid = self.band(finest, &bounds).await?;
+
+        // `None` stands for the grid band itself
+        let mut resampled = Vec::with_capacity(assets.len());
+        for asset in &assets {
+            let window = if asset.band == finest.band {
+                None
+            } else {
+                let window = self.band(asset, &grid.bounds()).await?;
+                Some(window.resample_nearest(grid.width, grid.height, grid.transform))
+            };
+            resampled.push(window);
+        }
+        let scl = self.scl(bands.get(Band::Scl)?, &grid.bounds()).await?;
+
+        let inputs: Vec<_> = resampled
+            .iter()
+            .zip(&assets)
+            .map(|(window, &asset)| (window.as_ref().unwrap_or(&grid), Rescale::from(asset)))
+            .collect();
+        let mut values = index.compute(&inputs)?;
+        let weights =
+            projected.rasterize(values.width, values.height, &values.transform, coverage)?;
+        let mask = scl_mask.apply(&mut values, &scl, &weights);
+
+        Ok(SceneIndex { epsg, index, bounds, values, weights, mask })
+    }
+}
+
+impl Analysis {
+    /// Summarizes the index values of a scene read over `aoi`
+    pub fn new(aoi: &Aoi, coverage: Coverage, scene: SceneIndex) -> Result<Self> {
+        let utm = Utm::from_epsg(scene.epsg)?;
+        let values = &scene.values;
+        let covered = scene.weights.iter().map(|&w| f64::from(w)).sum::<f64>();
+
+        Ok(Self {
+            epsg: scene.epsg,
+            bounds: scene.bounds,
+            coverage,
+            area_ha: aoi.area_hectares(&utm),
+            pixel_area_ha: covered * pixel_area(&values.transform) / 10_000.0,
+            transform: values.transform,
+            width: values.width,
+            height: values.height,
+            footprint: footprint(&utm, values),
+            stats: Stats::weighted(values, &scene.weights),
+            mask: scene.mask,
+            index: scene.index,
+        })
+    }
+}
+
+/// Area of one pixel of the grid at `transform`, in CRS units squared
+pub fn pixel_area(transform: &GeoTransform) -> f64 {
+    let [a, b, _, d, e, _] = transform.0;
+    (a * e - b * d).abs()
+}
+
+/// Pixel width of an asset in CRS units
+fn pixel_size(asset: &BandAsset) -> f64 {
+    asset.transform[0].abs()
+}
+
+/// Outline of `raster`'s pixels as a WGS84 GeoJSON polygon
+pub fn footprint<T: Copy>(utm: &Utm, raster: &Raster<T>) -> Value {
+    let (w, h) = (raster.width as f64, raster.height as f64);
+    let ring: Vec<[f64; 2]> = [(0.0, 0.0), (0.0, h), (w, h), (w, 0.0), (0.0, 0.0)]
+        .into_iter()
+        .map(|(col, row)| {
+            let (x, y) = raster.transform.apply(col, row);
+            let (lon, lat) = utm.inverse(x, y);
+            [lon, lat]
+        })
+        .collect();
+    json!({ "type": "Polygon", "coordinates": [ring] })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn item(epsg: Option<u32>) -> Item {
+        serde_json::from_value(serde_json::json!({
+            "id": "S2B_10SEG_20240627_0_L2A",
+            "properties": { "datetime": "2024-06-27T19:04:13.624000Z", "proj:epsg": epsg },
+            "geometry": null
+        }))
+        .unwrap()
+    }
+
+    fn asset(band: Band) -> BandAsset {
+        BandAsset {
+            band,
+            key: band.asset_key().to_string(),
+            href: format!("https://example.com/{}.tif", band.asset_key()),
+            media_type: "image/tiff; application=geotiff; profile=cloud-optimized".to_string(),
+            shape: [10_980, 10_980],
+            transform: [10.0, 0.0, 499_980.0, 0.0, -10.0, 4_200_000.0],
+            scale: 0.0001,
+            offset: -0.1,
+            nodata: Some(0.0),
+            gsd: None,
+        }
+    }
+
+    fn band_set(bands: &[Band]) -> BandSet {
+        let bands = bands.iter().map(|&band| (band, asset(band))).collect::<BTreeMap<_, _>>();
+        BandSet { item_id: item(None).id, bands }
+    }
+
+    /// Reads NDVI over `bbox`; only metadata errors are expected, raised before any fetch
+    fn read_error(item: Item, bands: BandSet, bbox: [f64; 4]) -> anyhow::Error {
+        let aoi = Aoi::from_bbox(bbox);
+        let mask = SclMask::parse("").unwrap();
+        wstd::runtime::block_on(async move {
+            read_scene_index(&item, &bands, SpectralIndex::Ndvi, &aoi, &mask, Coverage::Center)
+                .await
+                .unwrap_err()
+        })
+    }
+
+    #[test]
+    fn metadata_errors_are_scene_errors() {
+        let bands = || band_set(&analysis_bands(SpectralIndex::Ndvi));
+        let inside = [-123.0, 37.9, -122.99, 37.91];
+
+        let err = read_error(item(None), bands(), inside);
+        assert!(is_scene_error(&err));
+        let item_id = "S2B_10SEG_20240627_0_L2A".to_string();
+        assert_eq!(err.downcast_ref(), Some(&SceneError::NoEpsg { item_id: item_id.clone() }));
+
+        let err = read_error(item(Some(4326)), bands(), inside);
+        assert!(is_scene_error(&err));
+        let not_utm = SceneError::NotUtm { item_id: item_id.clone(), epsg: 4326 };
+        assert_eq!(err.downcast_ref(), Some(&not_utm));
+
+        let err = read_error(item(Some(32610)), bands(), [-121.0, 40.0, -120.99, 40.01]);
+        assert!(is_scene_error(&err));
+        let outside = SceneError::OutsideTile { item_id, asset: "nir".to_string() };
+        assert_eq!(err.downcast_ref(), Some(&outside));
+
+        let err = read_error(item(Some(32610)), band_set(&[Band::Red, Band::Scl]), inside);
+        assert!(is_scene_error(&err));
+        assert!(matches!(err.downcast_ref(), Some(BandError::Missing { band: Band::Nir, .. })));
+    }
+
+    #[test]
+    fn fetch_errors_are_not_scene_errors() {
+        assert!(!is_scene_error(&anyhow::anyhow!("HTTP 503 from https://example.com/nir.tif")));
+        let wrapped = anyhow::Error::from(BandError::Missing {
+            item_id: "S2B_10SEG_20240627_0_L2A".to_string(),
+            band: Band::Nir,
+        });
+        assert!(is_scene_error(&wrapped));
+    }
+}
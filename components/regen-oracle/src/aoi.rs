@@ -0,0 +1,602 @@
+use crate::raster::{Bounds, GeoTransform};
+use crate::utm::Utm;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+/// Longest lon/lat edge, in degrees, projected without inserting intermediate vertices
+const MAX_SEGMENT_DEGREES: f64 = 0.01;
+
+const SQUARE_METRES_PER_HECTARE: f64 = 10_000.0;
+
+/// How partially covered pixels count towards the AOI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Coverage {
+    /// A pixel is in the AOI, with weight 1, when its center is
+    #[default]
+    Center,
+    /// A pixel is weighted by the fraction of its area inside the AOI
+    Area,
+}
+
+impl std::str::FromStr for Coverage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "center" => Ok(Coverage::Center),
+            "area" => Ok(Coverage::Area),
+            other => Err(anyhow::anyhow!("Unknown AOI coverage rule `{}`", other)),
+        }
+    }
+}
+
+/// A polygon with optional holes; rings are stored open, without repeating the first vertex
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub exterior: Vec<[f64; 2]>,
+    pub holes: Vec<Vec<[f64; 2]>>,
+}
+
+impl Polygon {
+    fn rings(&self) -> impl Iterator<Item = &Vec<[f64; 2]>> {
+        std::iter::once(&self.exterior).chain(&self.holes)
+    }
+
+    /// Area of the exterior minus the holes
+    fn area(&self) -> f64 {
+        ring_area(&self.exterior).abs() - self.holes.iter().map(|h| ring_area(h).abs()).sum::<f64>()
+    }
+}
+
+/// Area of interest: one or more polygons, in WGS84 or projected into a scene's CRS
+///
+/// Serialized as a GeoJSON `Polygon` or `MultiPolygon` geometry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Value", into = "Value")]
+pub struct Aoi {
+    pub polygons: Vec<Polygon>,
+}
+
+impl TryFrom<Value> for Aoi {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        Self::from_geojson(&value)
+    }
+}
+
+impl From<Aoi> for Value {
+    fn from(aoi: Aoi) -> Self {
+        aoi.to_geojson()
+    }
+}
+
+impl Aoi {
+    /// The AOI covering a `[min_lon, min_lat, max_lon, max_lat]` bbox
+    pub fn from_bbox(bbox: [f64; 4]) -> Self {
+        let [min_x, min_y, max_x, max_y] = bbox;
+        Self {
+            polygons: vec![Polygon {
+                exterior: vec![[min_x, min_y], [max_x, min_y], [max_x, max_y], [min_x, max_y]],
+                holes: Vec::new(),
+            }],
+        }
+    }
+
+    /// Parses a GeoJSON `Polygon` or `MultiPolygon`, bare or wrapped in a `Feature`
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::from_geojson(&serde_json::from_str(text)?)
+    }
+
+    /// Reads the polygons of a parsed GeoJSON `Polygon`, `MultiPolygon` or `Feature`
+    pub fn from_geojson(value: &Value) -> Result<Self> {
+        let coordinates = || {
+            value.get("coordinates").ok_or_else(|| anyhow::anyhow!("Geometry has no coordinates"))
+        };
+        let polygons = match value.get("type").and_then(Value::as_str) {
+            Some("Feature") => {
+                let geometry = value.get("geometry").filter(|g| !g.is_null());
+                return Self::from_geojson(
+                    geometry.ok_or_else(|| anyhow::anyhow!("Feature has no geometry"))?,
+                );
+            }
+            Some("Polygon") => vec![parse_polygon(coordinates()?)?],
+            Some("MultiPolygon") => {
+                as_array(coordinates()?)?.iter().map(parse_polygon).collect::<Result<Vec<_>>>()?
+            }
+            Some(other) => {
+                return Err(anyhow::anyhow!(
+                    "Expected a Polygon or MultiPolygon geometry, found {}",
+                    other
+                ))
+            }
+            None => return Err(anyhow::anyhow!("Geometry has no type")),
+        };
+        if polygons.is_empty() {
+            return Err(anyhow::anyhow!("MultiPolygon has no polygons"));
+        }
+        Ok(Self { polygons })
+    }
+
+    /// GeoJSON geometry with closed rings
+    pub fn to_geojson(&self) -> Value {
+        let polygon = |p: &Polygon| -> Vec<Vec<[f64; 2]>> {
+            p.rings().map(|ring| ring.iter().chain(ring.first()).copied().collect()).collect()
+        };
+        match self.polygons.as_slice() {
+            [single] => json!({ "type": "Polygon", "coordinates": polygon(single) }),
+            many => json!({
+                "type": "MultiPolygon",
+                "coordinates": many.iter().map(polygon).collect::<Vec<_>>(),
+            }),
+        }
+    }
+
+    /// Envelope as `[min_x, min_y, max_x, max_y]`
+    pub fn bbox(&self) -> [f64; 4] {
+        let b = self.bounds();
+        [b.min_x, b.min_y, b.max_x, b.max_y]
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        let mut bounds = Bounds {
+            min_x: f64::INFINITY,
+            min_y: f64::INFINITY,
+            max_x: f64::NEG_INFINITY,
+            max_y: f64::NEG_INFINITY,
+        };
+        for [x, y] in self.polygons.iter().flat_map(|p| &p.exterior) {
+            bounds.min_x = bounds.min_x.min(*x);
+            bounds.min_y = bounds.min_y.min(*y);
+            bounds.max_x = bounds.max_x.max(*x);
+            bounds.max_y = bounds.max_y.max(*y);
+        }
+        bounds
+    }
+
+    /// Projects a WGS84 AOI into `utm`, densifying long edges so they follow the
+    /// curvature of lon/lat lines
+    pub fn project(&self, utm: &Utm) -> Self {
+        let project_ring = |ring: &Vec<[f64; 2]>| -> Vec<[f64; 2]> {
+            let mut projected = Vec::with_capacity(ring.len());
+            for (i, &[lon0, lat0]) in ring.iter().enumerate() {
+                let [lon1, lat1] = ring[(i + 1) % ring.len()];
+                let steps = ((lon1 - lon0).abs().max((lat1 - lat0).abs()) / MAX_SEGMENT_DEGREES)
+                    .ceil()
+                    .max(1.0) as usize;
+                for step in 0..steps {
+                    let t = step as f64 / steps as f64;
+                    let (x, y) = utm.forward(lon0 + (lon1 - lon0) * t, lat0 + (lat1 - lat0) * t);
+                    projected.push([x, y]);
+                }
+            }
+            projected
+        };
+
+        Self {
+            polygons: self
+                .polygons
+                .iter()
+                .map(|p| Polygon {
+                    exterior: project_ring(&p.exterior),
+                    holes: p.holes.iter().map(project_ring).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Ellipsoidal area in hectares of a WGS84 AOI, measured in `utm`
+    ///
+    /// The planar UTM area of each polygon is divided by the square of the grid
+    /// scale factor at its centroid, which is accurate to a few parts per million
+    /// for parcels a few kilometres across.
+    pub fn area_hectares(&self, utm: &Utm) -> f64 {
+        self.project(utm)
+            .polygons
+            .iter()
+            .map(|p| {
+                let (x, _) = centroid(&p.exterior);
+                p.area() / utm.scale_factor(x).powi(2)
+            })
+            .sum::<f64>()
+            / SQUARE_METRES_PER_HECTARE
+    }
+
+    /// Per-pixel weights of a projected AOI on the `width` x `height` grid at `transform`
+    ///
+    /// Weights are 0 or 1 with [`Coverage::Center`], and the covered fraction of each
+    /// pixel with [`Coverage::Area`]. Each polygon is filled on its own, its holes
+    /// excluded with the even-odd rule, and a pixel is in the AOI when any polygon
+    /// covers it, so overlapping parts count once. In area mode a boundary pixel
+    /// weighs the sum of the polygons' coverage, capped at 1, which is exact for
+    /// parts that only touch.
+    pub fn rasterize(
+        &self,
+        width: usize,
+        height: usize,
+        transform: &GeoTransform,
+        coverage: Coverage,
+    ) -> Result<Vec<f32>> {
+        // work in pixel coordinates, where pixel (col, row) is the unit square at (col, row)
+        let to_pixel = |&[x, y]: &[f64; 2]| {
+            transform
+                .invert(x, y)
+                .map(|(col, row)| [col, row])
+                .ok_or_else(|| anyhow::anyhow!("Degenerate transform {:?}", transform.0))
+        };
+        let polygon_rings: Vec<Vec<Vec<[f64; 2]>>> = self
+            .polygons
+            .iter()
+            .map(|p| p.rings().map(|ring| ring.iter().map(to_pixel).collect()).collect())
+            .collect::<Result<_>>()?;
+
+        let mut weights = vec![0.0f32; width * height];
+        for rings in &polygon_rings {
+            fill_centers(rings, width, height, &mut weights);
+        }
+
+        if coverage == Coverage::Area {
+            let polygons: Vec<Polygon> = self
+                .polygons
+                .iter()
+                .map(|p| -> Result<Polygon> {
+                    Ok(Polygon {
+                        exterior: p.exterior.iter().map(to_pixel).collect::<Result<_>>()?,
+                        holes: p
+                            .holes
+                            .iter()
+                            .map(|h| h.iter().map(to_pixel).collect::<Result<_>>())
+                            .collect::<Result<_>>()?,
+                    })
+                })
+                .collect::<Result<_>>()?;
+
+            // pixels crossed by an edge are partially covered; every other pixel is
+            // entirely inside or outside, which the center rule already decided
+            let rings: Vec<_> = polygon_rings.into_iter().flatten().collect();
+            for (col, row) in boundary_pixels(&rings, width, height) {
+                let square = [
+                    [col as f64, row as f64],
+                    [col as f64 + 1.0, row as f64],
+                    [col as f64 + 1.0, row as f64 + 1.0],
+                    [col as f64, row as f64 + 1.0],
+                ];
+                let covered: f64 = polygons
+                    .iter()
+                    .map(|p| {
+                        ring_area(&clip_to_square(&p.exterior, &square)).abs()
+                            - p.holes
+                                .iter()
+                                .map(|h| ring_area(&clip_to_square(h, &square)).abs())
+                                .sum::<f64>()
+                    })
+                    .sum();
+                weights[row * width + col] = covered.clamp(0.0, 1.0) as f32;
+            }
+        }
+
+        Ok(weights)
+    }
+}
+
+fn as_array(value: &Value) -> Result<&Vec<Value>> {
+    value.as_array().ok_or_else(|| anyhow::anyhow!("Expected an array, found {}", value))
+}
+
+fn parse_polygon(value: &Value) -> Result<Polygon> {
+    let mut rings = as_array(value)?.iter().map(parse_ring);
+    let exterior = rings.next().ok_or_else(|| anyhow::anyhow!("Polygon has no rings"))??;
+    Ok(Polygon { exterior, holes: rings.collect::<Result<_>>()? })
+}
+
+fn parse_ring(value: &Value) -> Result<Vec<[f64; 2]>> {
+    let mut ring = as_array(value)?
+        .iter()
+        .map(|position| match as_array(position)?.as_slice() {
+            [lon, lat, ..] => match (lon.as_f64(), lat.as_f64()) {
+                (Some(lon), Some(lat))
+                    if (-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat) =>
+                {
+                    Ok([lon, lat])
+                }
+                _ => Err(anyhow::anyhow!("Invalid position {}", position)),
+            },
+            _ => Err(anyhow::anyhow!("Invalid position {}", position)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    if ring.len() < 3 {
+        return Err(anyhow::anyhow!("Polygon ring has fewer than 3 distinct positions"));
+    }
+    Ok(ring)
+}
+
+/// Signed shoelace area of an open ring, positive when counter-clockwise in a y-up frame
+fn ring_area(ring: &[[f64; 2]]) -> f64 {
+    let n = ring.len();
+    (0..n)
+        .map(|i| {
+            let ([x0, y0], [x1, y1]) = (ring[i], ring[(i + 1) % n]);
+            x0 * y1 - x1 * y0
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+fn centroid(ring: &[[f64; 2]]) -> (f64, f64) {
+    let n = ring.len() as f64;
+    let (sx, sy) = ring.iter().fold((0.0, 0.0), |(sx, sy), [x, y]| (sx + x, sy + y));
+    (sx / n, sy / n)
+}
+
+/// Sets the weight of every pixel whose center is inside `rings` (even-odd) to 1,
+/// leaving the others as they are
+fn fill_centers(rings: &[Vec<[f64; 2]>], width: usize, height: usize, weights: &mut [f32]) {
+    let mut crossings = Vec::new();
+    for row in 0..height {
+        let y = row as f64 + 0.5;
+        crossings.clear();
+        for ring in rings {
+            for (i, &[x0, y0]) in ring.iter().enumerate() {
+                let [x1, y1] = ring[(i + 1) % ring.len()];
+                if (y0 <= y) != (y1 <= y) {
+                    crossings.push(x0 + (y - y0) * (x1 - x0) / (y1 - y0));
+                }
+            }
+        }
+        crossings.sort_by(f64::total_cmp);
+
+        for span in crossings.chunks_exact(2) {
+            // pixels whose center col + 0.5 lies in [span[0], span[1])
+            let start = (span[0] - 0.5).ceil().clamp(0.0, width as f64) as usize;
+            let end = (span[1] - 0.5).ceil().clamp(0.0, width as f64) as usize;
+            for weight in &mut weights[row * width + start..row * width + end] {
+                *weight = 1.0;
+            }
+        }
+    }
+}
+
+/// Pixels of the grid crossed by any ring edge
+fn boundary_pixels(
+    rings: &[Vec<[f64; 2]>],
+    width: usize,
+    height: usize,
+) -> BTreeSet<(usize, usize)> {
+    let mut pixels = BTreeSet::new();
+    for ring in rings {
+        for (i, &[x0, y0]) in ring.iter().enumerate() {
+            let [x1, y1] = ring[(i + 1) % ring.len()];
+
+            // split the edge wherever it crosses a grid line; each piece then lies
+            // within a single pixel, identified by its midpoint
+            let mut ts = vec![0.0, 1.0];
+            for (a, b) in [(x0, x1), (y0, y1)] {
+                let (lo, hi) = (a.min(b).ceil() as i64, a.max(b).floor() as i64);
+                for line in lo..=hi {
+                    if a != b {
+                        ts.push((line as f64 - a) / (b - a));
+                    }
+                }
+            }
+            ts.sort_by(f64::total_cmp);
+
+            for pair in ts.windows(2) {
+                let t = (pair[0] + pair[1]) / 2.0;
+                let (x, y) = (x0 + (x1 - x0) * t, y0 + (y1 - y0) * t);
+                if x >= 0.0 && y >= 0.0 && x < width as f64 && y < height as f64 {
+                    pixels.insert((x as usize, y as usize));
+                }
+            }
+        }
+    }
+    pixels
+}
+
+/// Sutherland-Hodgman clip of `ring` against the axis-aligned unit `square`
+///
+/// The subject may be concave; the clipped ring can then contain degenerate
+/// edges along the square's sides, which do not affect its area.
+fn clip_to_square(ring: &[[f64; 2]], square: &[[f64; 2]; 4]) -> Vec<[f64; 2]> {
+    let [min_x, min_y] = square[0];
+    let [max_x, max_y] = square[2];
+    let edges: [(usize, f64, bool); 4] =
+        [(0, min_x, true), (0, max_x, false), (1, min_y, true), (1, max_y, false)];
+
+    let mut output = ring.to_vec();
+    for (axis, bound, keep_above) in edges {
+        let input = std::mem::take(&mut output);
+        let inside = |p: &[f64; 2]| if keep_above { p[axis] >= bound } else { p[axis] <= bound };
+        for (i, current) in input.iter().enumerate() {
+            let previous = &input[(i + input.len() - 1) % input.len()];
+            let intersect = || {
+                let t = (bound - previous[axis]) / (current[axis] - previous[axis]);
+                [
+                    previous[0] + (current[0] - previous[0]) * t,
+                    previous[1] + (current[1] - previous[1]) * t,
+                ]
+            };
+            match (inside(previous), inside(current)) {
+                (true, true) => output.push(*current),
+                (true, false) => output.push(intersect()),
+                (false, true) => {
+                    output.push(intersect());
+                    output.push(*current);
+                }
+                (false, false) => {}
+            }
+        }
+        if output.is_empty() {
+            break;
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10x10 grid of unit pixels, with y up in the CRS and rows counted down from y = 10
+    const GRID: GeoTransform = GeoTransform([1.0, 0.0, 0.0, 0.0, -1.0, 10.0]);
+
+    type Ring = Vec<[f64; 2]>;
+
+    fn rect(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Ring {
+        vec![[min_x, min_y], [max_x, min_y], [max_x, max_y], [min_x, max_y]]
+    }
+
+    fn aoi(polygons: Vec<(Ring, Vec<Ring>)>) -> Aoi {
+        Aoi {
+            polygons: polygons
+                .into_iter()
+                .map(|(exterior, holes)| Polygon { exterior, holes })
+                .collect(),
+        }
+    }
+
+    /// `(col, row)` of the pixels with a non-zero weight
+    fn covered(weights: &[f32]) -> Vec<(usize, usize)> {
+        (0..weights.len()).filter(|&i| weights[i] > 0.0).map(|i| (i % 10, i / 10)).collect()
+    }
+
+    #[test]
+    fn rasterizes_square() {
+        let square = aoi(vec![(rect(2.0, 3.0, 5.0, 7.0), Vec::new())]);
+        for coverage in [Coverage::Center, Coverage::Area] {
+            let weights = square.rasterize(10, 10, &GRID, coverage).unwrap();
+            let expected: Vec<_> =
+                (3..7).flat_map(|row| (2..5).map(move |col| (col, row))).collect();
+            assert_eq!(covered(&weights), expected);
+            assert!(weights.iter().all(|&w| w == 0.0 || w == 1.0));
+        }
+    }
+
+    #[test]
+    fn excludes_holes() {
+        let holed = aoi(vec![(rect(0.0, 0.0, 10.0, 10.0), vec![rect(4.0, 4.0, 6.0, 6.0)])]);
+        for coverage in [Coverage::Center, Coverage::Area] {
+            let weights = holed.rasterize(10, 10, &GRID, coverage).unwrap();
+            assert_eq!(weights.iter().sum::<f32>(), 96.0);
+            for (col, row) in [(4, 4), (5, 4), (4, 5), (5, 5)] {
+                assert_eq!(weights[row * 10 + col], 0.0);
+            }
+        }
+
+        // a hole cut halfway through pixels leaves them half covered in area mode
+        let holed = aoi(vec![(rect(0.0, 0.0, 10.0, 10.0), vec![rect(4.5, 4.0, 6.5, 6.0)])]);
+        let weights = holed.rasterize(10, 10, &GRID, Coverage::Area).unwrap();
+        assert_eq!(
+            (weights[4 * 10 + 4], weights[4 * 10 + 5], weights[4 * 10 + 6]),
+            (0.5, 0.0, 0.5)
+        );
+        assert_eq!(weights.iter().sum::<f32>(), 96.0);
+    }
+
+    #[test]
+    fn overlapping_parts_count_once() {
+        let overlapping = aoi(vec![
+            (rect(1.0, 1.0, 5.0, 5.0), Vec::new()),
+            (rect(3.0, 1.0, 7.0, 5.0), Vec::new()),
+        ]);
+        for coverage in [Coverage::Center, Coverage::Area] {
+            let weights = overlapping.rasterize(10, 10, &GRID, coverage).unwrap();
+            let expected: Vec<_> =
+                (5..9).flat_map(|row| (1..7).map(move |col| (col, row))).collect();
+            assert_eq!(covered(&weights), expected);
+            assert!(weights.iter().all(|&w| w == 0.0 || w == 1.0));
+        }
+
+        // one part's hole is still covered by the other part
+        let nested = aoi(vec![
+            (rect(0.0, 0.0, 10.0, 10.0), vec![rect(2.0, 2.0, 8.0, 8.0)]),
+            (rect(4.0, 4.0, 6.0, 6.0), Vec::new()),
+        ]);
+        let weights = nested.rasterize(10, 10, &GRID, Coverage::Center).unwrap();
+        assert_eq!(weights.iter().sum::<f32>(), 64.0 + 4.0);
+    }
+
+    #[test]
+    fn center_and_area_differ_on_boundary_pixels() {
+        // edges through the middle of columns 2 and 5
+        let square = aoi(vec![(rect(2.5, 3.0, 5.5, 7.0), Vec::new())]);
+
+        let center = square.rasterize(10, 10, &GRID, Coverage::Center).unwrap();
+        // a center on the left edge is in, one on the right edge is out
+        assert_eq!(&center[30..40], &[0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+
+        let area = square.rasterize(10, 10, &GRID, Coverage::Area).unwrap();
+        assert_eq!(&area[30..40], &[0.0, 0.0, 0.5, 1.0, 1.0, 0.5, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(area.iter().sum::<f32>(), 12.0);
+
+        // a triangle's area is exact, while its centers only approximate it
+        let triangle = aoi(vec![(vec![[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]], Vec::new())]);
+        let area = triangle.rasterize(10, 10, &GRID, Coverage::Area).unwrap();
+        assert!((area.iter().map(|&w| f64::from(w)).sum::<f64>() - 50.0).abs() < 1e-5);
+        let center = triangle.rasterize(10, 10, &GRID, Coverage::Center).unwrap();
+        assert_eq!(center.iter().sum::<f32>(), 45.0);
+    }
+
+    #[test]
+    fn clips_to_the_grid() {
+        let large = aoi(vec![(rect(-5.0, -5.0, 15.0, 15.0), Vec::new())]);
+        for coverage in [Coverage::Center, Coverage::Area] {
+            let weights = large.rasterize(10, 10, &GRID, coverage).unwrap();
+            assert!(weights.iter().all(|&w| w == 1.0));
+        }
+        let square = aoi(vec![(rect(2.0, 3.0, 5.0, 7.0), Vec::new())]);
+        assert!(square.rasterize(10, 10, &GeoTransform([0.0; 6]), Coverage::Center).is_err());
+    }
+
+    #[test]
+    fn densifies_projected_edges() {
+        let utm = Utm::from_epsg(32610).unwrap();
+        let bbox = Aoi::from_bbox([-122.5, 37.7, -122.3, 37.8]);
+        let projected = bbox.project(&utm);
+        let exterior = &projected.polygons[0].exterior;
+        // 0.2 and 0.1 degree edges in steps of at most 0.01 degrees; rounding in the
+        // step count may add one per edge
+        assert!((2 * 20 + 2 * 10..=2 * 21 + 2 * 11).contains(&exterior.len()));
+
+        // every vertex lies on the bbox's lon/lat outline, and no chord is longer than
+        // 0.01 degrees, about 1.1 km
+        for (i, &[x, y]) in exterior.iter().enumerate() {
+            let (lon, lat) = utm.inverse(x, y);
+            let on_meridian = (lon + 122.5).abs() < 1e-9 || (lon + 122.3).abs() < 1e-9;
+            let on_parallel = (lat - 37.7).abs() < 1e-9 || (lat - 37.8).abs() < 1e-9;
+            assert!(on_meridian || on_parallel, "({}, {}) is off the outline", lon, lat);
+
+            let [nx, ny] = exterior[(i + 1) % exterior.len()];
+            assert!((nx - x).hypot(ny - y) < 1_120.0);
+        }
+    }
+
+    #[test]
+    fn area_in_hectares() {
+        // 0.01 x 0.01 degrees on the equator: 1113.19 m of parallel by 1105.74 m of meridian
+        let cell = Aoi::from_bbox([-123.005, -0.005, -122.995, 0.005]);
+        let hectares = cell.area_hectares(&Utm::from_epsg(32610).unwrap());
+        assert!((hectares - 1113.195 * 1105.743 / 1e4).abs() < 0.01, "{}", hectares);
+
+        // the scale factor correction makes zones agree away from their central meridian
+        let parcel = Aoi::from_bbox([-121.01, 37.7, -120.99, 37.72]);
+        let zone10 = parcel.area_hectares(&Utm::from_epsg(32610).unwrap());
+        let zone11 = parcel.area_hectares(&Utm::from_epsg(32611).unwrap());
+        assert!((zone10 - zone11).abs() / zone10 < 1e-5, "{} vs {}", zone10, zone11);
+
+        let holed = Aoi::parse(
+            r#"{"type":"Polygon","coordinates":[
+                [[-123.01,-0.01],[-122.99,-0.01],[-122.99,0.01],[-123.01,0.01],[-123.01,-0.01]],
+                [[-123.005,-0.005],[-122.995,-0.005],[-122.995,0.005],[-123.005,0.005],
+                 [-123.005,-0.005]]]}"#,
+        )
+        .unwrap();
+        let ratio = holed.area_hectares(&Utm::from_epsg(32610).unwrap()) / hectares;
+        assert!((ratio - 3.0).abs() < 1e-3, "{}", ratio);
+    }
+}
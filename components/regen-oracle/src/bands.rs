@@ -0,0 +1,332 @@
+use crate::stac::{Asset, Item};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Bands and layers the oracle reads from Sentinel-2 L2A items
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Band {
+    /// B02, 490nm, 10m
+    Blue,
+    /// B03, 560nm, 10m
+    Green,
+    /// B04, 665nm, 10m
+    Red,
+    /// B05, 705nm, 20m
+    RedEdge1,
+    /// B08, 842nm, 10m
+    Nir,
+    /// B8A, 865nm, 20m
+    Nir08,
+    /// B11, 1610nm, 20m
+    Swir16,
+    /// B12, 2190nm, 20m
+    Swir22,
+    /// Scene classification map, 20m; not a spectral band, so it has no `eo:bands`
+    Scl,
+}
+
+impl Band {
+    /// `eo:bands[].common_name` identifying the band
+    pub fn common_name(&self) -> &'static str {
+        match self {
+            Band::Blue => "blue",
+            Band::Green => "green",
+            Band::Red => "red",
+            Band::RedEdge1 => "rededge",
+            Band::Nir => "nir",
+            Band::Nir08 => "nir08",
+            Band::Swir16 => "swir16",
+            Band::Swir22 => "swir22",
+            Band::Scl => "scl",
+        }
+    }
+
+    /// Asset key Earth Search uses for the band's COG, used when an asset has no `eo:bands`
+    pub fn asset_key(&self) -> &'static str {
+        match self {
+            Band::Blue => "blue",
+            Band::Green => "green",
+            Band::Red => "red",
+            Band::RedEdge1 => "rededge1",
+            Band::Nir => "nir",
+            Band::Nir08 => "nir08",
+            Band::Swir16 => "swir16",
+            Band::Swir22 => "swir22",
+            Band::Scl => "scl",
+        }
+    }
+
+    /// Whether other bands share the common name, as B05, B06 and B07 all share `rededge`
+    fn shares_common_name(&self) -> bool {
+        matches!(self, Band::RedEdge1)
+    }
+}
+
+impl fmt::Display for Band {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.common_name())
+    }
+}
+
+/// A single-band cloud-optimized GeoTIFF selected from an item's assets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BandAsset {
+    pub band: Band,
+    /// Key of the asset in the item's `assets` map
+    pub key: String,
+    pub href: String,
+    pub media_type: String,
+    /// `proj:shape` as `[rows, cols]`
+    pub shape: [u64; 2],
+    /// `proj:transform`, the first six coefficients of the affine pixel-to-CRS transform
+    pub transform: [f64; 6],
+    /// `raster:bands[0].scale`, 1.0 when absent
+    pub scale: f64,
+    /// `raster:bands[0].offset`, 0.0 when absent
+    pub offset: f64,
+    pub nodata: Option<f64>,
+    pub gsd: Option<f64>,
+}
+
+/// Assets for a set of bands, all taken from the same STAC item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BandSet {
+    pub item_id: String,
+    pub bands: BTreeMap<Band, BandAsset>,
+}
+
+/// Errors raised while selecting band assets from an item
+#[derive(Debug, Clone, PartialEq)]
+pub enum BandError {
+    /// No GeoTIFF asset carries the band
+    Missing { item_id: String, band: Band },
+    /// The asset was found but lacks a field needed to read it
+    Incomplete { item_id: String, band: Band, asset: String, field: &'static str },
+}
+
+impl fmt::Display for BandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BandError::Missing { item_id, band } => {
+                write!(f, "Item {} has no cloud-optimized GeoTIFF asset for band {}", item_id, band)
+            }
+            BandError::Incomplete { item_id, band, asset, field } => write!(
+                f,
+                "Item {} asset {} (band {}) is missing or has an invalid {}",
+                item_id, asset, band, field
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BandError {}
+
+impl BandSet {
+    /// Selects one asset per requested band from `item`
+    ///
+    /// Assets are matched on `eo:bands[].common_name` so that, for example,
+    /// `nir08` is never taken for `nir`. Assets without `eo:bands` match on key, and
+    /// so do the red edge bands, whose common name is shared.
+    /// Only GeoTIFFs are considered, which rules out the `*-jp2` duplicates; if
+    /// several remain, COGs are preferred and ties break on the lowest key.
+    pub fn from_item(item: &Item, bands: &[Band]) -> Result<Self, BandError> {
+        let bands = bands
+            .iter()
+            .map(|&band| {
+                let (key, asset) = select_asset(item, band)
+                    .ok_or_else(|| BandError::Missing { item_id: item.id.clone(), band })?;
+                Ok((band, band_asset(item, band, key, asset)?))
+            })
+            .collect::<Result<_, BandError>>()?;
+
+        Ok(Self { item_id: item.id.clone(), bands })
+    }
+
+    /// Returns the asset for `band`
+    pub fn get(&self, band: Band) -> Result<&BandAsset, BandError> {
+        self.bands
+            .get(&band)
+            .ok_or_else(|| BandError::Missing { item_id: self.item_id.clone(), band })
+    }
+}
+
+fn select_asset(item: &Item, band: Band) -> Option<(&String, &Asset)> {
+    item.assets
+        .iter()
+        .filter(|(key, asset)| match asset.eo_bands.as_slice() {
+            [] => key.as_str() == band.asset_key(),
+            [eo] => {
+                eo.common_name.as_deref() == Some(band.common_name())
+                    && (!band.shares_common_name() || key.as_str() == band.asset_key())
+            }
+            // multi-band composites such as `visual`
+            _ => false,
+        })
+        .filter(|(_, asset)| {
+            asset.media_type.as_deref().is_some_and(|t| t.starts_with("image/tiff"))
+        })
+        // BTreeMap iteration is ordered by key, so `min_by_key` keeps the lowest key on ties
+        .min_by_key(|(_, asset)| !is_cog(asset))
+}
+
+fn is_cog(asset: &Asset) -> bool {
+    asset.media_type.as_deref().is_some_and(|t| t.contains("profile=cloud-optimized"))
+}
+
+fn band_asset(item: &Item, band: Band, key: &str, asset: &Asset) -> Result<BandAsset, BandError> {
+    let incomplete = |field| BandError::Incomplete {
+        item_id: item.id.clone(),
+        band,
+        asset: key.to_string(),
+        field,
+    };
+
+    let shape = match asset.proj_shape.as_deref() {
+        Some(&[rows, cols]) => [rows, cols],
+        _ => return Err(incomplete("proj:shape")),
+    };
+    let transform = match asset.proj_transform.as_deref() {
+        Some(t) if t.len() == 6 || t.len() == 9 => [t[0], t[1], t[2], t[3], t[4], t[5]],
+        _ => return Err(incomplete("proj:transform")),
+    };
+    let raster = asset.raster_bands.first();
+
+    Ok(BandAsset {
+        band,
+        key: key.to_string(),
+        href: asset.href.clone(),
+        media_type: asset.media_type.clone().unwrap_or_default(),
+        shape,
+        transform,
+        scale: raster.and_then(|r| r.scale).unwrap_or(1.0),
+        offset: raster.and_then(|r| r.offset).unwrap_or(0.0),
+        nodata: raster.and_then(|r| r.nodata),
+        gsd: asset.gsd,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COG: &str = "image/tiff; application=geotiff; profile=cloud-optimized";
+
+    /// An Earth Search asset with the fields `band_asset` reads
+    fn asset(band: &str, media_type: &str) -> serde_json::Value {
+        serde_json::json!({
+            "href": format!("https://example.com/{}.tif", band),
+            "type": media_type,
+            "eo:bands": [{ "name": band, "common_name": band }],
+            "proj:shape": [10980, 10980],
+            "proj:transform": [10.0, 0.0, 499980.0, 0.0, -10.0, 4200000.0, 0.0, 0.0, 1.0],
+            "raster:bands": [{ "nodata": 0, "scale": 0.0001, "offset": -0.1 }]
+        })
+    }
+
+    /// A minimal Earth Search Sentinel-2 L2A item, with the `-jp2` duplicates and `nir08`
+    fn item() -> Item {
+        let mut jp2_red = asset("red", "image/jp2");
+        jp2_red["href"] = "s3://sentinel-s2-l2a/B04.jp2".into();
+        let mut jp2_nir = asset("nir", "image/jp2");
+        jp2_nir["href"] = "s3://sentinel-s2-l2a/B08.jp2".into();
+        serde_json::from_value(serde_json::json!({
+            "id": "S2B_10SEG_20240627_0_L2A",
+            "properties": { "datetime": "2024-06-27T19:04:13.624000Z" },
+            "geometry": null,
+            "assets": {
+                "red": asset("red", COG),
+                "red-jp2": jp2_red,
+                "nir": asset("nir", COG),
+                "nir-jp2": jp2_nir,
+                "nir08": asset("nir08", COG),
+                "rededge1": asset("rededge", COG),
+                "rededge2": asset("rededge", COG),
+                "scl": { "href": "https://example.com/SCL.tif", "type": COG,
+                         "proj:shape": [5490, 5490],
+                         "proj:transform": [20.0, 0.0, 499980.0, 0.0, -20.0, 4200000.0] }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn selects_by_common_name() {
+        let set = BandSet::from_item(&item(), &[Band::Red, Band::Nir, Band::Nir08]).unwrap();
+        assert_eq!(set.bands.len(), 3);
+        assert_eq!(set.get(Band::Red).unwrap().key, "red");
+        assert_eq!(set.get(Band::Nir).unwrap().key, "nir");
+        assert_eq!(set.get(Band::Nir08).unwrap().key, "nir08");
+
+        let red = set.get(Band::Red).unwrap();
+        assert_eq!(red.href, "https://example.com/red.tif");
+        assert_eq!(red.shape, [10980, 10980]);
+        assert_eq!(red.transform, [10.0, 0.0, 499980.0, 0.0, -10.0, 4200000.0]);
+        assert_eq!((red.scale, red.offset, red.nodata), (0.0001, -0.1, Some(0.0)));
+    }
+
+    #[test]
+    fn matches_shared_common_names_and_scl_on_key() {
+        let set = BandSet::from_item(&item(), &[Band::RedEdge1, Band::Scl]).unwrap();
+        assert_eq!(set.get(Band::RedEdge1).unwrap().key, "rededge1");
+        let scl = set.get(Band::Scl).unwrap();
+        assert_eq!(scl.key, "scl");
+        assert_eq!((scl.scale, scl.offset, scl.nodata), (1.0, 0.0, None));
+    }
+
+    #[test]
+    fn prefers_cogs_over_plain_geotiffs() {
+        let mut item = item();
+        let mut plain = item.assets["red"].clone();
+        plain.media_type = Some("image/tiff; application=geotiff".to_string());
+        plain.href = "https://example.com/plain.tif".to_string();
+        item.assets.insert("a-red".to_string(), plain);
+        let set = BandSet::from_item(&item, &[Band::Red]).unwrap();
+        assert_eq!(set.get(Band::Red).unwrap().key, "red");
+    }
+
+    #[test]
+    fn missing_band() {
+        let mut item = item();
+        item.assets.remove("nir");
+        let err = BandSet::from_item(&item, &[Band::Red, Band::Nir]).unwrap_err();
+        assert_eq!(
+            err,
+            BandError::Missing { item_id: "S2B_10SEG_20240627_0_L2A".to_string(), band: Band::Nir }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Item S2B_10SEG_20240627_0_L2A has no cloud-optimized GeoTIFF asset for band nir"
+        );
+
+        let set = BandSet::from_item(&item, &[Band::Red]).unwrap();
+        assert!(matches!(set.get(Band::Nir), Err(BandError::Missing { band: Band::Nir, .. })));
+    }
+
+    #[test]
+    fn incomplete_band() {
+        let mut item = item();
+        item.assets.get_mut("red").unwrap().proj_shape = Some(vec![10980]);
+        let err = BandSet::from_item(&item, &[Band::Red]).unwrap_err();
+        assert_eq!(
+            err,
+            BandError::Incomplete {
+                item_id: "S2B_10SEG_20240627_0_L2A".to_string(),
+                band: Band::Red,
+                asset: "red".to_string(),
+                field: "proj:shape",
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Item S2B_10SEG_20240627_0_L2A asset red (band red) is missing or has an invalid \
+             proj:shape"
+        );
+
+        item.assets.get_mut("red").unwrap().proj_shape = Some(vec![10980, 10980]);
+        item.assets.get_mut("red").unwrap().proj_transform = None;
+        let err = BandSet::from_item(&item, &[Band::Red]).unwrap_err();
+        assert!(matches!(err, BandError::Incomplete { field: "proj:transform", .. }));
+    }
+}
@@ -0,0 +1,396 @@
+use crate::analysis;
+use crate::aoi::Coverage;
+use crate::cog_writer::{self, SampleFormat};
+use crate::composite::{Composite, Method, SceneLayers, TileGrid};
+use crate::index::{SpectralIndex, Stats};
+use crate::ipfs;
+use crate::metadata::{self, COMPONENT_VERSION};
+use crate::pinner::Pinner;
+use crate::raster::Raster;
+use crate::stac::SearchRequest;
+use crate::utm::Utm;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Index change below which a pixel counts as unchanged, in each direction
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Thresholds {
+    /// A pixel improved when its delta is above `gain`
+    pub gain: f64,
+    /// A pixel degraded when its delta is below `-loss`
+    pub loss: f64,
+}
+
+/// Scenes and composite of one period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Period {
+    pub search: SearchRequest,
+    /// Items composited, in datetime order
+    pub items: Vec<String>,
+    /// Statistics of the composite over the AOI
+    pub stats: Option<Stats>,
+    /// Statistics of the composite's valid observation count over the AOI
+    pub count: Option<Stats>,
+}
+
+/// Aggregate change between the baseline and monitoring composites
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeSummary {
+    /// Statistics of the per-pixel delta, monitoring minus baseline; `None` when no
+    /// pixel is valid in both composites
+    pub delta: Option<Stats>,
+    /// Weight fraction of the valid delta pixels above the gain threshold
+    pub improved_fraction: f64,
+    /// Weight fraction of the valid delta pixels below the negated loss threshold
+    pub degraded_fraction: f64,
+    pub unchanged_fraction: f64,
+    /// Welch's t statistic of the scene means of both periods, `None` when either
+    /// period has fewer than two scenes with valid pixels or no variance
+    pub t_statistic: Option<f64>,
+    /// Welch–Satterthwaite degrees of freedom of `t_statistic`
+    pub degrees_of_freedom: Option<f64>,
+}
+
+/// Change detection document, uploaded as canonical JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeDetection {
+    pub version: String,
+    pub index: SpectralIndex,
+    pub coverage: Coverage,
+    pub thresholds: Thresholds,
+    pub method: Method,
+    /// Tile grid both periods are composited on
+    pub grid: TileGrid,
+    /// GeoJSON polygon outlining the composite grid, in WGS84
+    pub footprint: Value,
+    pub baseline: Period,
+    pub monitoring: Period,
+    pub summary: ChangeSummary,
+    /// Path of the delta raster within the bundle, set once it is uploaded
+    ///
+    /// A cloud-optimized GeoTIFF on the grid above, nodata where the delta is unknown.
+    pub delta_raster: Option<String>,
+}
+
+/// Result of comparing two periods on a common grid
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub grid: TileGrid,
+    pub baseline: Composite,
+    pub monitoring: Composite,
+    /// Monitoring minus baseline, NaN where either composite is
+    pub delta: Raster<f32>,
+    /// AOI weight of each pixel of the grid
+    pub weights: Vec<f32>,
+    pub summary: ChangeSummary,
+}
+
+/// Composites both periods on `grid` by `method` and compares them
+///
+/// `weights` is the AOI weight of each pixel of `grid`. Both periods must hold
+/// scenes of the grid's tile, in datetime order.
+pub fn compare(
+    method: Method,
+    grid: &TileGrid,
+    weights: Vec<f32>,
+    baseline: &[SceneLayers],
+    monitoring: &[SceneLayers],
+    thresholds: Thresholds,
+) -> Result<Comparison> {
+    if baseline.is_empty() {
+        return Err(anyhow::anyhow!("No baseline scene"));
+    }
+    if monitoring.is_empty() {
+        return Err(anyhow::anyhow!("No monitoring scene"));
+    }
+    let before = Composite::build(method, grid, baseline)?;
+    let after = Composite::build(method, grid, monitoring)?;
+
+    let data: Vec<f32> = before
+        .values
+        .data
+        .iter()
+        .zip(&after.values.data)
+        .map(|(&b, &a)| if b.is_nan() || a.is_nan() { f32::NAN } else { a - b })
+        .collect();
+    let delta = Raster {
+        width: grid.width,
+        height: grid.height,
+        data,
+        transform: grid.transform,
+        nodata: Some(f32::NAN),
+    };
+
+    let (mut improved, mut degraded, mut unchanged, mut total) = (0.0, 0.0, 0.0, 0.0);
+    for (&value, &weight) in delta.data.iter().zip(&weights) {
+        if weight <= 0.0 || value.is_nan() {
+            continue;
+        }
+        let (value, weight) = (f64::from(value), f64::from(weight));
+        total += weight;
+        if value > thresholds.gain {
+            improved += weight;
+        } else if value < -thresholds.loss {
+            degraded += weight;
+        } else {
+            unchanged += weight;
+        }
+    }
+    let fraction = |w: f64| if total > 0.0 { w / total } else { 0.0 };
+
+    let scene_means = |scenes: &[SceneLayers]| -> Vec<f64> {
+        scenes
+            .iter()
+            .filter_map(|scene| Stats::weighted(&scene.index.values, &scene.index.weights))
+            .map(|stats| stats.mean)
+            .collect()
+    };
+    let welch = welch_t(&scene_means(baseline), &scene_means(monitoring));
+
+    Ok(Comparison {
+        grid: grid.clone(),
+        summary: ChangeSummary {
+            delta: Stats::weighted(&delta, &weights),
+            improved_fraction: fraction(improved),
+            degraded_fraction: fraction(degraded),
+            unchanged_fraction: fraction(unchanged),
+            t_statistic: welch.map(|(t, _)| t),
+            degrees_of_freedom: welch.map(|(_, df)| df),
+        },
+        baseline: before,
+        monitoring: after,
+        delta,
+        weights,
+    })
+}
+
+/// Welch's t statistic of `b` against `a` and its degrees of freedom
+fn welch_t(a: &[f64], b: &[f64]) -> Option<(f64, f64)> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let moments = |v: &[f64]| {
+        let n = v.len() as f64;
+        let mean = v.iter().sum::<f64>() / n;
+        // the rounded mean of equal values can differ from them, so constant samples
+        // are given no variance explicitly rather than a residue that inflates `t`
+        let variance = if v.iter().all(|&x| x == v[0]) {
+            0.0
+        } else {
+            v.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0)
+        };
+        (n, mean, variance / n)
+    };
+    let (na, mean_a, se2_a) = moments(a);
+    let (nb, mean_b, se2_b) = moments(b);
+    let se2 = se2_a + se2_b;
+    if se2 <= 0.0 {
+        return None;
+    }
+    let df = se2 * se2 / (se2_a * se2_a / (na - 1.0) + se2_b * se2_b / (nb - 1.0));
+    Some(((mean_b - mean_a) / se2.sqrt(), df))
+}
+
+impl ChangeDetection {
+    pub fn new(
+        index: SpectralIndex,
+        coverage: Coverage,
+        thresholds: Thresholds,
+        baseline: (SearchRequest, Vec<String>),
+        monitoring: (SearchRequest, Vec<String>),
+        comparison: &Comparison,
+    ) -> Result<Self> {
+        let utm = Utm::from_epsg(comparison.grid.epsg)?;
+        let weights = &comparison.weights;
+        let period =
+            |(search, items): (SearchRequest, Vec<String>), composite: &Composite| Period {
+                search,
+                items,
+                stats: Stats::weighted(&composite.values, weights),
+                count: composite.count_stats(weights),
+            };
+
+        Ok(Self {
+            version: COMPONENT_VERSION.to_string(),
+            index,
+            coverage,
+            thresholds,
+            method: comparison.baseline.method,
+            grid: comparison.grid.clone(),
+            footprint: analysis::footprint(&utm, &comparison.delta),
+            baseline: period(baseline, &comparison.baseline),
+            monitoring: period(monitoring, &comparison.monitoring),
+            summary: comparison.summary.clone(),
+            delta_raster: None,
+        })
+    }
+
+    /// Name of the bundle directory: index, last baseline and monitoring items, `change`
+    pub fn bundle_name(&self) -> String {
+        let last = |period: &Period| period.items.last().cloned().unwrap_or_default();
+        format!("{}_{}_{}_change", self.index, last(&self.baseline), last(&self.monitoring))
+    }
+
+    /// Uploads the canonical JSON document and the delta raster it links to, a COG in
+    /// `format`, as one directory
+    ///
+    /// Returns the JSON document's `ipfs://CID/change.json` URI, CID being the directory's.
+    pub async fn upload(
+        &mut self,
+        delta: &Raster<f32>,
+        format: SampleFormat,
+        pinner: &Pinner,
+    ) -> Result<String> {
+        let cog = cog_writer::write_cog(delta, self.grid.epsg, format)?;
+        self.delta_raster = Some("delta.tif".to_string());
+        let json = metadata::canonical_json(self)?;
+        let files = [("change.json", json.as_bytes()), ("delta.tif", cog.as_slice())];
+        let root = ipfs::upload_directory_to_ipfs(&self.bundle_name(), &files, pinner).await?;
+        Ok(ipfs::get_ipfs_url(&root, Some("change.json")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::SceneIndex;
+    use crate::mask::MaskSummary;
+    use crate::raster::{Bounds, GeoTransform};
+    use std::collections::BTreeMap;
+
+    const NAN: f32 = f32::NAN;
+
+    fn grid() -> TileGrid {
+        TileGrid {
+            grid_code: "MGRS-10SEG".to_string(),
+            epsg: 32610,
+            transform: GeoTransform([10.0, 0.0, 500_000.0, 0.0, -10.0, 4_200_000.0]),
+            width: 2,
+            height: 2,
+        }
+    }
+
+    /// A scene on the grid, every pixel inside the AOI
+    fn scene(values: [f32; 4]) -> SceneLayers {
+        let grid = grid();
+        let raster = Raster {
+            width: grid.width,
+            height: grid.height,
+            data: values.to_vec(),
+            transform: grid.transform,
+            nodata: Some(f32::NAN),
+        };
+        SceneLayers {
+            index: SceneIndex {
+                epsg: grid.epsg,
+                index: SpectralIndex::Ndvi,
+                bounds: Bounds {
+                    min_x: 500_000.0,
+                    min_y: 4_199_980.0,
+                    max_x: 500_020.0,
+                    max_y: 4_200_000.0,
+                },
+                values: raster,
+                weights: vec![1.0; 4],
+                mask: MaskSummary {
+                    excluded_classes: Vec::new(),
+                    masked_pixels: 0,
+                    total_pixels: 4,
+                    masked_fraction: 0.0,
+                    class_counts: BTreeMap::new(),
+                },
+            },
+            ndvi: None,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn welch_t_known_value() {
+        // means 2.5 and 4.5, both variances 5/3, so t = 2 / sqrt(5/6) and df = 6
+        let (t, df) = welch_t(&[1.0, 2.0, 3.0, 4.0], &[3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert_close(t, 2.0 / (5.0f64 / 6.0).sqrt());
+        assert_close(df, 6.0);
+
+        // the sign follows `b` against `a`
+        let (t, _) = welch_t(&[3.0, 4.0, 5.0, 6.0], &[1.0, 2.0, 3.0, 4.0]).unwrap();
+        assert_close(t, -2.0 / (5.0f64 / 6.0).sqrt());
+
+        // unequal sizes and variances: se2 = 1/3 + 4/3, df = (5/3)^2 / ((1/3)^2 / 2 + (4/3)^2 / 2)
+        let (t, df) = welch_t(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]).unwrap();
+        assert_close(t, 2.0 / (5.0f64 / 3.0).sqrt());
+        assert_close(df, 25.0 / 8.5);
+    }
+
+    #[test]
+    fn welch_t_undefined() {
+        assert_eq!(welch_t(&[0.5], &[0.1, 0.2, 0.3]), None);
+        assert_eq!(welch_t(&[0.1, 0.2, 0.3], &[0.5]), None);
+        assert_eq!(welch_t(&[], &[]), None);
+        assert_eq!(welch_t(&[0.2, 0.2], &[0.4, 0.4, 0.4]), None);
+        // one period without variance is enough
+        assert!(welch_t(&[0.2, 0.2], &[0.3, 0.5]).is_some());
+    }
+
+    #[test]
+    fn classifies_against_thresholds() {
+        let thresholds = Thresholds { gain: 0.25, loss: 0.25 };
+        let baseline = [scene([0.5; 4])];
+        let monitoring = [scene([0.75, 0.25, 1.0, 0.0])];
+        let comparison =
+            compare(Method::Median, &grid(), vec![1.0; 4], &baseline, &monitoring, thresholds)
+                .unwrap();
+
+        assert_eq!(comparison.delta.data, [0.25, -0.25, 0.5, -0.5]);
+        // a delta equal to a threshold is unchanged
+        let summary = &comparison.summary;
+        assert_eq!(summary.improved_fraction, 0.25);
+        assert_eq!(summary.degraded_fraction, 0.25);
+        assert_eq!(summary.unchanged_fraction, 0.5);
+        // one scene per period is too few for a t statistic
+        assert_eq!(summary.t_statistic, None);
+        assert_eq!(summary.degrees_of_freedom, None);
+    }
+
+    #[test]
+    fn compares_median_composites() {
+        let thresholds = Thresholds { gain: 0.1, loss: 0.1 };
+        let baseline = [scene([0.25, 0.25, 0.5, NAN]), scene([0.375, 0.25, 0.5, NAN])];
+        let monitoring = [scene([0.75, 0.25, 0.125, 0.5]), scene([0.75, 0.25, 0.375, 0.5])];
+        let weights = vec![1.0, 1.0, 0.5, 1.0];
+        let comparison =
+            compare(Method::Median, &grid(), weights, &baseline, &monitoring, thresholds).unwrap();
+
+        assert_eq!(comparison.baseline.values.data[..3], [0.3125, 0.25, 0.5]);
+        assert!(comparison.baseline.values.data[3].is_nan());
+        assert_eq!(comparison.monitoring.values.data, [0.75, 0.25, 0.25, 0.5]);
+        assert_eq!(comparison.delta.data[..3], [0.4375, 0.0, -0.25]);
+        assert!(comparison.delta.data[3].is_nan());
+
+        // the pixel without a baseline is left out, the half-weight pixel counts half
+        let summary = &comparison.summary;
+        assert_close(summary.improved_fraction, 0.4);
+        assert_close(summary.unchanged_fraction, 0.4);
+        assert_close(summary.degraded_fraction, 0.2);
+        assert_eq!(summary.delta.as_ref().unwrap().valid_pixels, 3);
+
+        // Welch's t runs on the mean of each scene over its valid pixels
+        let (t, df) = welch_t(&[1.0 / 3.0, 1.125 / 3.0], &[1.625 / 4.0, 1.875 / 4.0]).unwrap();
+        assert_close(summary.t_statistic.unwrap(), t);
+        assert_close(summary.degrees_of_freedom.unwrap(), df);
+    }
+
+    #[test]
+    fn needs_both_periods() {
+        let thresholds = Thresholds { gain: 0.1, loss: 0.1 };
+        let scenes = [scene([0.5; 4])];
+        let err = compare(Method::Median, &grid(), vec![1.0; 4], &[], &scenes, thresholds);
+        assert_eq!(err.unwrap_err().to_string(), "No baseline scene");
+        let err = compare(Method::Median, &grid(), vec![1.0; 4], &scenes, &[], thresholds);
+        assert_eq!(err.unwrap_err().to_string(), "No monitoring scene");
+    }
+}
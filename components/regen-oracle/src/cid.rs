@@ -0,0 +1,749 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Bytes per UnixFS leaf, Kubo's default `size-262144` chunker
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Most links per node of the balanced DAG layout, Kubo's default
+pub const MAX_LINKS: usize = 174;
+
+/// Multicodec of dag-pb blocks
+const DAG_PB: u64 = 0x70;
+/// Multicodec of raw blocks
+const RAW: u64 = 0x55;
+/// Multihash code of sha2-256
+const SHA2_256: u64 = 0x12;
+
+/// UnixFS `Data.Type` of a directory node
+const UNIXFS_DIRECTORY: u64 = 1;
+/// UnixFS `Data.Type` of a file node
+const UNIXFS_FILE: u64 = 2;
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// CID version and DAG parameters the pinning service imports files with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CidVersion {
+    /// CIDv0 with dag-pb leaves, base58btc `Qm...`; the `ipfs add` default, used by Lighthouse
+    #[default]
+    V0,
+    /// CIDv1 with raw leaves, base32 `bafy...` or `bafk...`, as `ipfs add --cid-version=1`
+    V1,
+}
+
+impl std::str::FromStr for CidVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "0" | "v0" => Ok(CidVersion::V0),
+            "1" | "v1" => Ok(CidVersion::V1),
+            other => Err(anyhow::anyhow!("Unknown CID version `{}`", other)),
+        }
+    }
+}
+
+/// A sha2-256 content identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cid {
+    pub version: CidVersion,
+    /// Multicodec of the block, always dag-pb for CIDv0
+    pub codec: u64,
+    pub digest: [u8; 32],
+}
+
+impl Cid {
+    fn of_block(version: CidVersion, codec: u64, block: &[u8]) -> Self {
+        Self { version, codec, digest: Sha256::digest(block).into() }
+    }
+
+    /// Whether `block` is the block this CID identifies, hashed as is
+    pub fn is_block(&self, block: &[u8]) -> bool {
+        Sha256::digest(block)[..] == self.digest
+    }
+
+    /// Binary form: the multihash for CIDv0, prefixed by version and codec for CIDv1
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(36);
+        if self.version == CidVersion::V1 {
+            write_varint(&mut bytes, 1);
+            write_varint(&mut bytes, self.codec);
+        }
+        write_varint(&mut bytes, SHA2_256);
+        write_varint(&mut bytes, 32);
+        bytes.extend_from_slice(&self.digest);
+        bytes
+    }
+
+    /// Parses a base58btc CIDv0 or a base32 CIDv1 with a sha2-256 multihash
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Unsupported or invalid CID `{}`", s);
+        let (version, bytes) = if s.len() == 46 && s.starts_with("Qm") {
+            (CidVersion::V0, base58_decode(s).ok_or_else(invalid)?)
+        } else if let Some(rest) = s.strip_prefix('b') {
+            (CidVersion::V1, base32_decode(rest).ok_or_else(invalid)?)
+        } else {
+            return Err(invalid());
+        };
+
+        let cid = Self::from_bytes(&bytes).ok_or_else(invalid)?;
+        if cid.version != version {
+            return Err(invalid());
+        }
+        Ok(cid)
+    }
+
+    /// Parses the binary form written by [`Cid::to_bytes`]
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut rest = bytes;
+        // a CIDv0 is a bare sha2-256 multihash
+        let (version, codec) = if bytes.first() == Some(&(SHA2_256 as u8)) {
+            (CidVersion::V0, DAG_PB)
+        } else {
+            if read_varint(&mut rest)? != 1 {
+                return None;
+            }
+            (CidVersion::V1, read_varint(&mut rest)?)
+        };
+        if read_varint(&mut rest)? != SHA2_256 || read_varint(&mut rest)? != 32 {
+            return None;
+        }
+        let digest = <[u8; 32]>::try_from(rest).ok()?;
+        Some(Self { version, codec, digest })
+    }
+}
+
+impl fmt::Display for Cid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            CidVersion::V0 => f.write_str(&base58_encode(&self.to_bytes())),
+            CidVersion::V1 => write!(f, "b{}", base32_encode(&self.to_bytes())),
+        }
+    }
+}
+
+/// A child of a dag-pb file node
+#[derive(Debug, Clone)]
+struct Link {
+    cid: Cid,
+    /// Bytes of the child's block and all blocks below it
+    tsize: u64,
+    /// Bytes of file content below the child
+    filesize: u64,
+}
+
+/// Computes the CID of a file the way `ipfs add` imports it, without keeping the file
+///
+/// Content is cut into [`CHUNK_SIZE`] leaves, arranged in a balanced tree of at most
+/// [`MAX_LINKS`] children per node. Only the leaf CIDs are held, so the file can be
+/// fed in as it streams past.
+#[derive(Debug, Clone)]
+pub struct UnixFsHasher {
+    version: CidVersion,
+    pending: Vec<u8>,
+    leaves: Vec<Link>,
+}
+
+impl UnixFsHasher {
+    pub fn new(version: CidVersion) -> Self {
+        Self { version, pending: Vec::new(), leaves: Vec::new() }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let n = data.len().min(CHUNK_SIZE - self.pending.len());
+            self.pending.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.pending.len() == CHUNK_SIZE {
+                let chunk = std::mem::take(&mut self.pending);
+                self.push_leaf(&chunk);
+            }
+        }
+    }
+
+    /// CID of the root of the file's DAG
+    pub fn finish(self) -> Cid {
+        self.finish_link().cid
+    }
+
+    fn finish_link(mut self) -> Link {
+        if !self.pending.is_empty() || self.leaves.is_empty() {
+            let chunk = std::mem::take(&mut self.pending);
+            self.push_leaf(&chunk);
+        }
+        let mut level = self.leaves;
+        while level.len() > 1 {
+            level =
+                level.chunks(MAX_LINKS).map(|children| parent(self.version, children)).collect();
+        }
+        level.swap_remove(0)
+    }
+
+    fn push_leaf(&mut self, chunk: &[u8]) {
+        let filesize = chunk.len() as u64;
+        let leaf = match self.version {
+            CidVersion::V0 => {
+                let block = dag_pb_node(std::iter::empty(), &unixfs_file(chunk, filesize, &[]));
+                let tsize = block.len() as u64;
+                Link { cid: Cid::of_block(CidVersion::V0, DAG_PB, &block), tsize, filesize }
+            }
+            CidVersion::V1 => {
+                Link { cid: Cid::of_block(CidVersion::V1, RAW, chunk), tsize: filesize, filesize }
+            }
+        };
+        self.leaves.push(leaf);
+    }
+}
+
+/// CID of `data` imported as a single file
+pub fn compute(data: &[u8], version: CidVersion) -> Cid {
+    let mut hasher = UnixFsHasher::new(version);
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// CID of a directory holding `files`, as `ipfs add` builds it for a folder
+///
+/// Each file is imported like [`compute`] does and linked under its name, in name
+/// order. Names must be unique and must not contain `/`; directories large enough
+/// for `ipfs add` to shard them are not supported.
+pub fn directory(files: &[(&str, &[u8])], version: CidVersion) -> Result<Cid> {
+    Ok(Cid::of_block(version, DAG_PB, &directory_block(files, version)?))
+}
+
+/// The dag-pb block of the directory [`directory`] identifies
+fn directory_block(files: &[(&str, &[u8])], version: CidVersion) -> Result<Vec<u8>> {
+    let mut entries = Vec::with_capacity(files.len());
+    for &(name, data) in files {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(anyhow::anyhow!("Invalid directory entry name `{}`", name));
+        }
+        let mut hasher = UnixFsHasher::new(version);
+        hasher.update(data);
+        entries.push((name, hasher.finish_link()));
+    }
+    entries.sort_by_key(|&(name, _)| name);
+    if let Some(pair) = entries.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Err(anyhow::anyhow!("Duplicate directory entry `{}`", pair[0].0));
+    }
+
+    let mut data = Vec::with_capacity(2);
+    write_varint(&mut data, 1 << 3);
+    write_varint(&mut data, UNIXFS_DIRECTORY);
+    Ok(dag_pb_node(entries.iter().map(|(name, link)| (*name, link)), &data))
+}
+
+/// CID of the entry `name` of the directory `dir`, read from its dag-pb `block`
+///
+/// The block must hash to `dir`, so an untrusted source cannot redirect the lookup.
+/// Sharded (HAMT) directories are not supported.
+pub fn directory_entry(dir: &Cid, block: &[u8], name: &str) -> Result<Cid> {
+    if dir.codec != DAG_PB || !dir.is_block(block) {
+        return Err(anyhow::anyhow!("Block does not hash to directory {}", dir));
+    }
+    let invalid = || anyhow::anyhow!("Directory {} is not a valid dag-pb node", dir);
+
+    let (mut links, mut data) = (Vec::new(), None);
+    let mut rest = block;
+    while !rest.is_empty() {
+        match read_field(&mut rest).ok_or_else(invalid)? {
+            (1, Field::Bytes(bytes)) => data = Some(bytes),
+            (2, Field::Bytes(bytes)) => links.push(bytes),
+            _ => return Err(invalid()),
+        }
+    }
+
+    let mut data = data.ok_or_else(invalid)?;
+    let mut kind = None;
+    while !data.is_empty() {
+        if let (1, Field::Varint(value)) = read_field(&mut data).ok_or_else(invalid)? {
+            kind = Some(value);
+        }
+    }
+    if kind != Some(UNIXFS_DIRECTORY) {
+        return Err(anyhow::anyhow!("{} is not a plain UnixFS directory", dir));
+    }
+
+    for mut link in links {
+        let (mut hash, mut link_name) = (None, None);
+        while !link.is_empty() {
+            match read_field(&mut link).ok_or_else(invalid)? {
+                (1, Field::Bytes(bytes)) => hash = Some(bytes),
+                (2, Field::Bytes(bytes)) => link_name = Some(bytes),
+                _ => {}
+            }
+        }
+        if link_name == Some(name.as_bytes()) {
+            return hash.and_then(Cid::from_bytes).ok_or_else(invalid);
+        }
+    }
+    Err(anyhow::anyhow!("Directory {} has no entry `{}`", dir, name))
+}
+
+/// Checks the CID a pinning service reported against the locally computed one
+pub fn verify(local: &Cid, reported: &str) -> Result<()> {
+    let remote = Cid::parse(reported)?;
+    if remote != *local {
+        return Err(anyhow::anyhow!(
+            "CID mismatch: the pinning service reported {} but the content hashes to {}; \
+             check that `ipfs_cid_version` matches the service's import settings",
+            reported,
+            local
+        ));
+    }
+    Ok(())
+}
+
+/// Intermediate file node linking `children`
+fn parent(version: CidVersion, children: &[Link]) -> Link {
+    let blocksizes: Vec<u64> = children.iter().map(|child| child.filesize).collect();
+    let filesize = blocksizes.iter().sum();
+    let links = children.iter().map(|child| ("", child));
+    let block = dag_pb_node(links, &unixfs_file(&[], filesize, &blocksizes));
+    Link {
+        cid: Cid::of_block(version, DAG_PB, &block),
+        tsize: block.len() as u64 + children.iter().map(|child| child.tsize).sum::<u64>(),
+        filesize,
+    }
+}
+
+/// Protobuf `UnixFS.Data` of a file node; empty fields are omitted like `ipfs add` does
+fn unixfs_file(data: &[u8], filesize: u64, blocksizes: &[u64]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 16 + blocksizes.len() * 4);
+    write_varint(&mut out, 1 << 3);
+    write_varint(&mut out, UNIXFS_FILE);
+    if !data.is_empty() {
+        write_bytes_field(&mut out, 2, data);
+    }
+    write_varint(&mut out, 3 << 3);
+    write_varint(&mut out, filesize);
+    for &size in blocksizes {
+        write_varint(&mut out, 4 << 3);
+        write_varint(&mut out, size);
+    }
+    out
+}
+
+/// Protobuf `PBNode` of named links: links first, then data, as dag-pb requires
+///
+/// Links of file nodes carry an empty name.
+fn dag_pb_node<'a>(links: impl Iterator<Item = (&'a str, &'a Link)>, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 64);
+    for (name, link) in links {
+        let mut encoded = Vec::with_capacity(48 + name.len());
+        write_bytes_field(&mut encoded, 1, &link.cid.to_bytes());
+        write_bytes_field(&mut encoded, 2, name.as_bytes());
+        write_varint(&mut encoded, 3 << 3);
+        write_varint(&mut encoded, link.tsize);
+        write_bytes_field(&mut out, 2, &encoded);
+    }
+    write_bytes_field(&mut out, 1, data);
+    out
+}
+
+/// Appends a length-delimited protobuf field
+fn write_bytes_field(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(out, field << 3 | 2);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Appends an unsigned LEB128 varint
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Value of a protobuf field, for the wire types dag-pb uses
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+/// Reads one protobuf field and its number
+fn read_field<'a>(bytes: &mut &'a [u8]) -> Option<(u64, Field<'a>)> {
+    let key = read_varint(bytes)?;
+    let value = match key & 7 {
+        0 => Field::Varint(read_varint(bytes)?),
+        2 => {
+            let len = usize::try_from(read_varint(bytes)?).ok()?;
+            let value = bytes.get(..len)?;
+            *bytes = &bytes[len..];
+            Field::Bytes(value)
+        }
+        _ => return None,
+    };
+    Some((key >> 3, value))
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn base58_encode(bytes: &[u8]) -> String {
+    // big-endian base-58 digits, least significant last
+    let mut digits: Vec<u8> = Vec::with_capacity(bytes.len() * 138 / 100 + 1);
+    for &byte in bytes {
+        let mut carry = u32::from(byte);
+        for digit in digits.iter_mut().rev() {
+            carry += u32::from(*digit) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.insert(0, (carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+    std::iter::repeat(b'1')
+        .take(zeros)
+        .chain(digits.iter().map(|&d| BASE58_ALPHABET[d as usize]))
+        .map(char::from)
+        .collect()
+}
+
+fn base58_decode(s: &str) -> Option<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(s.len());
+    for c in s.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|&a| a == c)? as u32;
+        for byte in bytes.iter_mut().rev() {
+            carry += u32::from(*byte) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = s.bytes().take_while(|&c| c == b'1').count();
+    Some(std::iter::repeat(0).take(zeros).chain(bytes).collect())
+}
+
+/// RFC 4648 base32, lowercase and unpadded as multibase `b` uses it
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in bytes {
+        buffer = buffer << 8 | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(char::from(BASE32_ALPHABET[(buffer >> bits & 31) as usize]));
+        }
+    }
+    if bits > 0 {
+        out.push(char::from(BASE32_ALPHABET[(buffer << (5 - bits) & 31) as usize]));
+    }
+    out
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in s.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c.to_ascii_lowercase())? as u32;
+        buffer = buffer << 5 | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &[u8] = b"hello world\n";
+
+    fn sha256_cid(version: CidVersion, codec: u64, block: &[u8]) -> Cid {
+        Cid { version, codec, digest: Sha256::digest(block).into() }
+    }
+
+    fn varint(value: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint(&mut out, value);
+        out
+    }
+
+    /// A dag-pb block spelled out field by field: `PBLink`s (hash, name, tsize), then data
+    fn pb_node(links: &[(Cid, &str, u64)], data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (cid, name, tsize) in links {
+            let hash = cid.to_bytes();
+            let mut link = vec![0x0a];
+            link.extend(varint(hash.len() as u64));
+            link.extend(hash);
+            link.push(0x12);
+            link.extend(varint(name.len() as u64));
+            link.extend(name.as_bytes());
+            link.push(0x18);
+            link.extend(varint(*tsize));
+            out.push(0x12);
+            out.extend(varint(link.len() as u64));
+            out.extend(link);
+        }
+        out.push(0x0a);
+        out.extend(varint(data.len() as u64));
+        out.extend(data);
+        out
+    }
+
+    /// `UnixFS.Data` of an intermediate file node
+    fn unixfs_parent(blocksizes: &[u64]) -> Vec<u8> {
+        let mut out = vec![0x08, 0x02, 0x18];
+        out.extend(varint(blocksizes.iter().sum()));
+        for &size in blocksizes {
+            out.push(0x20);
+            out.extend(varint(size));
+        }
+        out
+    }
+
+    #[test]
+    fn known_file_cids() {
+        assert_eq!(
+            compute(b"", CidVersion::V0).to_string(),
+            "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH"
+        );
+        assert_eq!(
+            compute(HELLO, CidVersion::V0).to_string(),
+            "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o"
+        );
+        assert_eq!(
+            compute(HELLO, CidVersion::V1).to_string(),
+            "bafkreifjjcie6lypi6ny7amxnfftagclbuxndqonfipmb64f2km2devei4"
+        );
+    }
+
+    #[test]
+    fn known_empty_directory() {
+        assert_eq!(
+            directory(&[], CidVersion::V0).unwrap().to_string(),
+            "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn"
+        );
+    }
+
+    /// `UnixFS.Data` of a file leaf holding `chunk`, as in a v0 DAG
+    fn unixfs_leaf(chunk: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x08, 0x02, 0x12];
+        out.extend(varint(chunk.len() as u64));
+        out.extend(chunk);
+        out.push(0x18);
+        out.extend(varint(chunk.len() as u64));
+        out
+    }
+
+    #[test]
+    fn file_over_one_chunk() {
+        // bytes `i % 251` for i in 0..262244, one full chunk and 100 bytes; the CIDs below
+        // are what `ipfs add -Q --only-hash` (and `--cid-version 1`) must print for
+        // python3 -c 'import sys; sys.stdout.buffer.write(bytes(i % 251 for i in range(262244)))'
+        let data: Vec<u8> = (0..CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        let (first, second) = data.split_at(CHUNK_SIZE);
+
+        // v0 leaves are dag-pb file nodes, encoded as `ipfs add` does a single-chunk file
+        let hello = pb_node(&[], &unixfs_leaf(HELLO));
+        assert_eq!(
+            sha256_cid(CidVersion::V0, DAG_PB, &hello).to_string(),
+            "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o"
+        );
+        let leaves: Vec<_> = [first, second]
+            .iter()
+            .map(|chunk| {
+                let block = pb_node(&[], &unixfs_leaf(chunk));
+                (sha256_cid(CidVersion::V0, DAG_PB, &block), "", block.len() as u64)
+            })
+            .collect();
+        let root = pb_node(&leaves, &unixfs_parent(&[CHUNK_SIZE as u64, 100]));
+        let v0 = sha256_cid(CidVersion::V0, DAG_PB, &root);
+        assert_eq!(compute(&data, CidVersion::V0), v0);
+        assert_eq!(v0.to_string(), "QmYQy8zjeUxRYnh9JYnjJr1dqNVFgtdEaemzEzPLYqbL4t");
+
+        // v1 leaves are raw blocks whose tsize is their length
+        let leaves = [
+            (sha256_cid(CidVersion::V1, RAW, first), "", CHUNK_SIZE as u64),
+            (sha256_cid(CidVersion::V1, RAW, second), "", 100),
+        ];
+        let root = pb_node(&leaves, &unixfs_parent(&[CHUNK_SIZE as u64, 100]));
+        let v1 = sha256_cid(CidVersion::V1, DAG_PB, &root);
+        assert_eq!(compute(&data, CidVersion::V1), v1);
+        assert_eq!(v1.to_string(), "bafybeihau3d5k2h3tbsp6paab6rc2fvf5bbzyf53uncwchbzxdoo2hsae4");
+
+        // a file of exactly one chunk has no parent
+        assert_eq!(compute(first, CidVersion::V1), sha256_cid(CidVersion::V1, RAW, first));
+    }
+
+    #[test]
+    fn file_over_max_links_chunks() {
+        let chunk = vec![7u8; CHUNK_SIZE];
+        let mut hasher = UnixFsHasher::new(CidVersion::V1);
+        for _ in 0..MAX_LINKS {
+            hasher.update(&chunk);
+        }
+        hasher.update(b"tail");
+
+        // 175 leaves: a full node of 174 and a node of one under a root of two
+        let size = CHUNK_SIZE as u64;
+        let leaf = (sha256_cid(CidVersion::V1, RAW, &chunk), "", size);
+        let tail = (sha256_cid(CidVersion::V1, RAW, b"tail"), "", 4);
+        let full = pb_node(&vec![leaf; MAX_LINKS], &unixfs_parent(&vec![size; MAX_LINKS]));
+        let last = pb_node(&[tail], &unixfs_parent(&[4]));
+        let children = [
+            (
+                sha256_cid(CidVersion::V1, DAG_PB, &full),
+                "",
+                full.len() as u64 + size * MAX_LINKS as u64,
+            ),
+            (sha256_cid(CidVersion::V1, DAG_PB, &last), "", last.len() as u64 + 4),
+        ];
+        let root = pb_node(&children, &unixfs_parent(&[size * MAX_LINKS as u64, 4]));
+        assert_eq!(hasher.finish(), sha256_cid(CidVersion::V1, DAG_PB, &root));
+    }
+
+    #[test]
+    fn streamed_in_pieces() {
+        let data: Vec<u8> = (0..3 * CHUNK_SIZE / 2).map(|i| (i * 7 % 256) as u8).collect();
+        let mut hasher = UnixFsHasher::new(CidVersion::V0);
+        for piece in data.chunks(65_537) {
+            hasher.update(piece);
+        }
+        assert_eq!(hasher.finish(), compute(&data, CidVersion::V0));
+    }
+
+    #[test]
+    fn two_file_directory() {
+        let files: [(&str, &[u8]); 2] = [("b.json", b"{}"), ("a.txt", HELLO)];
+        // entries are linked in name order with the file block sizes as tsize
+        let link = |name, data: &[u8]| {
+            let block = pb_node(&[], &unixfs_file(data, data.len() as u64, &[]));
+            (compute(data, CidVersion::V0), name, block.len() as u64)
+        };
+        let root = pb_node(&[link("a.txt", HELLO), link("b.json", b"{}")], &[0x08, 0x01]);
+        assert_eq!(
+            directory(&files, CidVersion::V0).unwrap(),
+            sha256_cid(CidVersion::V0, DAG_PB, &root)
+        );
+
+        let links = [
+            (compute(HELLO, CidVersion::V1), "a.txt", HELLO.len() as u64),
+            (compute(b"{}", CidVersion::V1), "b.json", 2),
+        ];
+        let root = pb_node(&links, &[0x08, 0x01]);
+        assert_eq!(
+            directory(&files, CidVersion::V1).unwrap(),
+            sha256_cid(CidVersion::V1, DAG_PB, &root)
+        );
+
+        assert!(directory(&[("a", b"1"), ("a", b"2")], CidVersion::V0).is_err());
+        assert!(directory(&[("a/b", b"1")], CidVersion::V0).is_err());
+        assert!(directory(&[("", b"1")], CidVersion::V0).is_err());
+    }
+
+    #[test]
+    fn looks_up_directory_entries() {
+        let files: [(&str, &[u8]); 2] = [("a.txt", HELLO), ("b.json", b"{}")];
+        for version in [CidVersion::V0, CidVersion::V1] {
+            let block = directory_block(&files, version).unwrap();
+            let dir = directory(&files, version).unwrap();
+            assert_eq!(directory_entry(&dir, &block, "a.txt").unwrap(), compute(HELLO, version));
+            assert_eq!(directory_entry(&dir, &block, "b.json").unwrap(), compute(b"{}", version));
+            let err = directory_entry(&dir, &block, "c.json").unwrap_err();
+            assert_eq!(err.to_string(), format!("Directory {} has no entry `c.json`", dir));
+        }
+
+        // the block must be the directory's
+        let dir = directory(&files, CidVersion::V0).unwrap();
+        let other = directory_block(&files[..1], CidVersion::V0).unwrap();
+        assert!(directory_entry(&dir, &other, "a.txt").is_err());
+
+        // a file node is not a directory
+        let file = pb_node(&[], &unixfs_file(HELLO, HELLO.len() as u64, &[]));
+        let cid = sha256_cid(CidVersion::V0, DAG_PB, &file);
+        assert!(directory_entry(&cid, &file, "a.txt").is_err());
+
+        // nor is a raw block
+        let raw = compute(HELLO, CidVersion::V1);
+        assert!(directory_entry(&raw, HELLO, "a.txt").is_err());
+
+        // truncated nodes are rejected once they hash right
+        let block = directory_block(&files, CidVersion::V0).unwrap();
+        let truncated = &block[..block.len() - 3];
+        let cid = sha256_cid(CidVersion::V0, DAG_PB, truncated);
+        assert!(directory_entry(&cid, truncated, "a.txt").is_err());
+    }
+
+    #[test]
+    fn parse_round_trips() {
+        for s in [
+            "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH",
+            "bafkreifjjcie6lypi6ny7amxnfftagclbuxndqonfipmb64f2km2devei4",
+            "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi",
+        ] {
+            assert_eq!(Cid::parse(s).unwrap().to_string(), s);
+        }
+        let cid =
+            Cid::parse("bafkreifjjcie6lypi6ny7amxnfftagclbuxndqonfipmb64f2km2devei4").unwrap();
+        assert_eq!((cid.version, cid.codec), (CidVersion::V1, RAW));
+        assert_eq!(cid, compute(HELLO, CidVersion::V1));
+        // base32 is case-insensitive; output is lowercase
+        let upper = "b".to_string()
+            + &"afkreifjjcie6lypi6ny7amxnfftagclbuxndqonfipmb64f2km2devei4".to_uppercase();
+        assert_eq!(Cid::parse(&upper).unwrap(), cid);
+    }
+
+    #[test]
+    fn parse_rejects_unsupported_input() {
+        for s in [
+            "",
+            // base58btc multibase prefix, not supported
+            "zb2rhe5P4gXftAwvA4eXQ5HJwsER2owDyS9sKaQRRVQPn93bA",
+            // CIDv0 with a character outside the alphabet, and a short one
+            "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1Aw0H",
+            "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQ",
+            // base32 with a digit outside the alphabet
+            "bafkreifjjcie6lypi6ny7amxnfftagclbuxndqonfipmb64f2km2devei1",
+            // truncated digest
+            "bafkreifjjcie6lypi6ny7amxnfftagclbuxndqonfipmb64f2km2d",
+        ] {
+            assert!(Cid::parse(s).is_err(), "{}", s);
+        }
+
+        // identity multihash, then a CIDv2
+        let mut bytes = vec![1, 0x55, 0x00, 3];
+        bytes.extend(b"abc");
+        assert!(Cid::parse(&format!("b{}", base32_encode(&bytes))).is_err());
+        let mut bytes = vec![2, 0x55, 0x12, 32];
+        bytes.extend([0; 32]);
+        assert!(Cid::parse(&format!("b{}", base32_encode(&bytes))).is_err());
+    }
+
+    #[test]
+    fn verify_compares_cids() {
+        let local = compute(HELLO, CidVersion::V0);
+        assert!(verify(&local, "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o").is_ok());
+        let err = verify(&local, "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH").unwrap_err();
+        assert!(err.to_string().starts_with("CID mismatch"));
+        // the same content under another version is a mismatch too
+        let v1 = compute(HELLO, CidVersion::V1).to_string();
+        assert!(verify(&local, &v1).is_err());
+    }
+}
@@ -0,0 +1,850 @@
+use anyhow::Result;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use wstd::http::{Client, Request, StatusCode};
+use wstd::io::{empty, AsyncRead};
+
+/// Bytes fetched by the first range request of [`Cog::open`]
+///
+/// GDAL writes a COG's IFDs and tile index arrays ahead of the image data, so for a
+/// Sentinel-2 band the whole structure normally arrives with this single request.
+const HEADER_PREFETCH: u64 = 64 * 1024;
+
+/// Upper bound on a single tag's value, guarding against corrupt counts
+const MAX_TAG_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Upper bound on the number of IFDs followed, guarding against offset cycles
+const MAX_IFDS: usize = 64;
+
+/// Upper bound on the entries of one IFD, guarding against corrupt BigTIFF counts
+const MAX_IFD_ENTRIES: u64 = 4096;
+
+/// Upper bound on a single range request, so that a corrupt offset or byte count
+/// cannot make the component buffer an arbitrary amount of data
+pub const MAX_RANGE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// TIFF and GeoTIFF tags read by this module and written by [`crate::cog_writer`]
+pub(crate) mod tag {
+    pub const NEW_SUBFILE_TYPE: u16 = 254;
+    pub const IMAGE_WIDTH: u16 = 256;
+    pub const IMAGE_LENGTH: u16 = 257;
+    pub const BITS_PER_SAMPLE: u16 = 258;
+    pub const COMPRESSION: u16 = 259;
+    /// Written only; readers assume BlackIsZero for single-band rasters
+    pub const PHOTOMETRIC_INTERPRETATION: u16 = 262;
+    pub const SAMPLES_PER_PIXEL: u16 = 277;
+    pub const PLANAR_CONFIGURATION: u16 = 284;
+    pub const PREDICTOR: u16 = 317;
+    pub const TILE_WIDTH: u16 = 322;
+    pub const TILE_LENGTH: u16 = 323;
+    pub const TILE_OFFSETS: u16 = 324;
+    pub const TILE_BYTE_COUNTS: u16 = 325;
+    pub const SAMPLE_FORMAT: u16 = 339;
+    pub const MODEL_PIXEL_SCALE: u16 = 33550;
+    pub const MODEL_TIEPOINT: u16 = 33922;
+    pub const MODEL_TRANSFORMATION: u16 = 34264;
+    pub const GEO_KEY_DIRECTORY: u16 = 34735;
+    pub const GEO_DOUBLE_PARAMS: u16 = 34736;
+    pub const GEO_ASCII_PARAMS: u16 = 34737;
+    /// Written only, to carry the scale and offset of int16 rasters
+    pub const GDAL_METADATA: u16 = 42112;
+    pub const GDAL_NODATA: u16 = 42113;
+
+    /// Tags parsed from an IFD; others are skipped
+    pub const ALL: [u16; 20] = [
+        NEW_SUBFILE_TYPE,
+        IMAGE_WIDTH,
+        IMAGE_LENGTH,
+        BITS_PER_SAMPLE,
+        COMPRESSION,
+        SAMPLES_PER_PIXEL,
+        PLANAR_CONFIGURATION,
+        PREDICTOR,
+        TILE_WIDTH,
+        TILE_LENGTH,
+        TILE_OFFSETS,
+        TILE_BYTE_COUNTS,
+        SAMPLE_FORMAT,
+        MODEL_PIXEL_SCALE,
+        MODEL_TIEPOINT,
+        MODEL_TRANSFORMATION,
+        GEO_KEY_DIRECTORY,
+        GEO_DOUBLE_PARAMS,
+        GEO_ASCII_PARAMS,
+        GDAL_NODATA,
+    ];
+}
+
+/// GeoKeys read by this module and written by [`crate::cog_writer`]
+pub mod geokey {
+    /// `GTModelTypeGeoKey`: 1 = projected, 2 = geographic
+    pub const MODEL_TYPE: u16 = 1024;
+    /// `GTRasterTypeGeoKey`: 1 = PixelIsArea, 2 = PixelIsPoint
+    pub const RASTER_TYPE: u16 = 1025;
+    /// `GeographicTypeGeoKey`, the EPSG code of a geographic CRS
+    pub const GEOGRAPHIC_TYPE: u16 = 2048;
+    /// `ProjectedCSTypeGeoKey`, the EPSG code of a projected CRS
+    pub const PROJECTED_CS_TYPE: u16 = 3072;
+    /// Value marking a user-defined CRS instead of an EPSG code
+    pub const USER_DEFINED: u16 = 32767;
+}
+
+/// Byte order declared in the TIFF header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// `II`
+    LittleEndian,
+    /// `MM`
+    BigEndian,
+}
+
+impl ByteOrder {
+    pub fn u16(&self, b: &[u8]) -> u16 {
+        let b = [b[0], b[1]];
+        match self {
+            ByteOrder::LittleEndian => u16::from_le_bytes(b),
+            ByteOrder::BigEndian => u16::from_be_bytes(b),
+        }
+    }
+
+    pub fn u32(&self, b: &[u8]) -> u32 {
+        let b = [b[0], b[1], b[2], b[3]];
+        match self {
+            ByteOrder::LittleEndian => u32::from_le_bytes(b),
+            ByteOrder::BigEndian => u32::from_be_bytes(b),
+        }
+    }
+
+    pub fn u64(&self, b: &[u8]) -> u64 {
+        let b = [b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]];
+        match self {
+            ByteOrder::LittleEndian => u64::from_le_bytes(b),
+            ByteOrder::BigEndian => u64::from_be_bytes(b),
+        }
+    }
+}
+
+/// Tile compression schemes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lzw,
+    Deflate,
+    /// Any other `Compression` tag value; the tiles can be located but not decoded
+    Other(u16),
+}
+
+impl From<u16> for Compression {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => Compression::None,
+            5 => Compression::Lzw,
+            // 32946 is the pre-standard code for Deflate, still written by some encoders
+            8 | 32946 => Compression::Deflate,
+            other => Compression::Other(other),
+        }
+    }
+}
+
+/// Differencing applied to samples before compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Predictor {
+    None,
+    /// Horizontal differencing of integer samples
+    Horizontal,
+    /// Byte-shuffled differencing of floating point samples
+    FloatingPoint,
+    Other(u16),
+}
+
+impl From<u16> for Predictor {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => Predictor::None,
+            2 => Predictor::Horizontal,
+            3 => Predictor::FloatingPoint,
+            other => Predictor::Other(other),
+        }
+    }
+}
+
+/// Value of a single GeoKey
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoKeyValue {
+    Short(u16),
+    Shorts(Vec<u16>),
+    Doubles(Vec<f64>),
+    Ascii(String),
+}
+
+/// Parsed `GeoKeyDirectoryTag` with its double and ASCII parameters resolved
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoKeyDirectory {
+    /// `(KeyDirectoryVersion, KeyRevision, MinorRevision)`
+    pub version: (u16, u16, u16),
+    pub keys: BTreeMap<u16, GeoKeyValue>,
+}
+
+impl GeoKeyDirectory {
+    fn parse(directory: &[u64], doubles: &[f64], ascii: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid GeoKey directory");
+        let short = |v: u64| u16::try_from(v).map_err(|_| invalid());
+
+        let header = directory.get(..4).ok_or_else(invalid)?;
+        let version = (short(header[0])?, short(header[1])?, short(header[2])?);
+        let count = header[3] as usize;
+        let entries = directory.get(4..4 + count * 4).ok_or_else(invalid)?;
+
+        let mut keys = BTreeMap::new();
+        for entry in entries.chunks_exact(4) {
+            let (key, location) = (short(entry[0])?, short(entry[1])?);
+            let (count, value) = (entry[2] as usize, entry[3] as usize);
+            let value = match location {
+                0 => GeoKeyValue::Short(short(entry[3])?),
+                tag::GEO_KEY_DIRECTORY => GeoKeyValue::Shorts(
+                    directory
+                        .get(value..value + count)
+                        .ok_or_else(invalid)?
+                        .iter()
+                        .map(|&v| short(v))
+                        .collect::<Result<_>>()?,
+                ),
+                tag::GEO_DOUBLE_PARAMS => GeoKeyValue::Doubles(
+                    doubles.get(value..value + count).ok_or_else(invalid)?.to_vec(),
+                ),
+                tag::GEO_ASCII_PARAMS => {
+                    let text = ascii.get(value..value + count).ok_or_else(invalid)?;
+                    // strings in GeoAsciiParams are terminated with `|`
+                    GeoKeyValue::Ascii(text.trim_end_matches(['|', '\0']).to_string())
+                }
+                other => {
+                    return Err(anyhow::anyhow!("GeoKey {} stored in unknown tag {}", key, other))
+                }
+            };
+            keys.insert(key, value);
+        }
+
+        Ok(Self { version, keys })
+    }
+
+    pub fn short(&self, key: u16) -> Option<u16> {
+        match self.keys.get(&key) {
+            Some(GeoKeyValue::Short(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// EPSG code of the raster's CRS, preferring the projected CRS over the geographic one
+    pub fn epsg(&self) -> Option<u16> {
+        [geokey::PROJECTED_CS_TYPE, geokey::GEOGRAPHIC_TYPE]
+            .into_iter()
+            .filter_map(|key| self.short(key))
+            .find(|&code| code != 0 && code != geokey::USER_DEFINED)
+    }
+
+    /// Whether pixel coordinates refer to pixel centers (`RasterPixelIsPoint`)
+    pub fn pixel_is_point(&self) -> bool {
+        self.short(geokey::RASTER_TYPE) == Some(2)
+    }
+}
+
+/// One image file directory: the full-resolution image, an overview or a mask
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ifd {
+    /// `NewSubfileType` bit flags
+    pub subfile_type: u32,
+    pub width: u64,
+    pub height: u64,
+    pub tile_width: u64,
+    pub tile_height: u64,
+    pub bits_per_sample: u16,
+    pub samples_per_pixel: u16,
+    /// `SampleFormat`: 1 unsigned integer, 2 signed integer, 3 IEEE float
+    pub sample_format: u16,
+    /// `PlanarConfiguration`: 1 chunky (interleaved), 2 planar
+    pub planar_configuration: u16,
+    pub compression: Compression,
+    pub predictor: Predictor,
+    /// File offset of each tile, row-major (and per plane for planar images)
+    pub tile_offsets: Vec<u64>,
+    /// Compressed size of each tile, parallel to `tile_offsets`
+    pub tile_byte_counts: Vec<u64>,
+    /// `ModelPixelScaleTag` as `[sx, sy, sz]`
+    pub pixel_scale: Option<[f64; 3]>,
+    /// `ModelTiepointTag`, groups of `[i, j, k, x, y, z]`
+    pub tiepoints: Vec<f64>,
+    /// `ModelTransformationTag`, a row-major 4x4 matrix
+    pub model_transformation: Option<[f64; 16]>,
+    /// Only present on the first IFD of a GeoTIFF
+    pub geo_keys: Option<GeoKeyDirectory>,
+    /// `GDAL_NODATA`
+    pub nodata: Option<f64>,
+}
+
+impl Ifd {
+    /// Whether this IFD is a reduced-resolution copy of the image
+    pub fn is_overview(&self) -> bool {
+        self.subfile_type & 0b001 != 0 && !self.is_mask()
+    }
+
+    /// Whether this IFD is a transparency mask
+    pub fn is_mask(&self) -> bool {
+        self.subfile_type & 0b100 != 0
+    }
+
+    pub fn tiles_across(&self) -> u64 {
+        self.width.div_ceil(self.tile_width)
+    }
+
+    pub fn tiles_down(&self) -> u64 {
+        self.height.div_ceil(self.tile_height)
+    }
+
+    /// Byte range `(offset, length)` of the tile at `(tile_col, tile_row)` in the first plane
+    ///
+    /// Returns `None` outside the tile grid. A length of zero marks a sparse tile
+    /// that was never written, which readers treat as nodata.
+    pub fn tile_range(&self, tile_col: u64, tile_row: u64) -> Option<(u64, u64)> {
+        if tile_col >= self.tiles_across() || tile_row >= self.tiles_down() {
+            return None;
+        }
+        let index = (tile_row * self.tiles_across() + tile_col) as usize;
+        Some((*self.tile_offsets.get(index)?, *self.tile_byte_counts.get(index)?))
+    }
+
+    /// Affine pixel-to-CRS transform `[a, b, c, d, e, f]`, in the `proj:transform` order
+    ///
+    /// `x = a * col + b * row + c` and `y = d * col + e * row + f`, with `(col, row)`
+    /// addressing pixel corners. `geo_keys` is the directory of the first IFD, needed
+    /// to shift `PixelIsPoint` rasters by half a pixel the way GDAL does.
+    pub fn transform(&self, geo_keys: Option<&GeoKeyDirectory>) -> Option<[f64; 6]> {
+        let mut t = if let Some(m) = self.model_transformation {
+            [m[0], m[1], m[3], m[4], m[5], m[7]]
+        } else {
+            let [sx, sy, _] = self.pixel_scale?;
+            let tp = self.tiepoints.get(..6)?;
+            [sx, 0.0, tp[3] - tp[0] * sx, 0.0, -sy, tp[4] + tp[1] * sy]
+        };
+        if geo_keys.is_some_and(GeoKeyDirectory::pixel_is_point) {
+            t[2] -= 0.5 * (t[0] + t[1]);
+            t[5] -= 0.5 * (t[3] + t[4]);
+        }
+        Some(t)
+    }
+}
+
+/// Structure of a cloud-optimized GeoTIFF, read without downloading its image data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cog {
+    pub url: String,
+    pub byte_order: ByteOrder,
+    pub big_tiff: bool,
+    /// IFDs in file order: the full-resolution image first, then overviews and masks
+    pub ifds: Vec<Ifd>,
+}
+
+impl Cog {
+    /// Reads the header and every IFD of the COG at `url` using HTTP range requests
+    ///
+    /// The first [`HEADER_PREFETCH`] bytes are fetched up front; anything that lies
+    /// beyond them, as happens with GeoTIFFs that are not cloud-optimized, is fetched
+    /// with additional range requests.
+    pub async fn open(url: &str) -> Result<Self> {
+        let prefix = fetch_range(url, 0, HEADER_PREFETCH).await?;
+        Self::parse(url, prefix).await
+    }
+
+    /// Parses the COG structure from `prefix`, the first bytes of the file at `url`
+    ///
+    /// A prefix shorter than [`HEADER_PREFETCH`] is taken to be the whole file, as
+    /// [`fetch_range`] only returns fewer bytes than asked for at the end of the file.
+    pub async fn parse(url: &str, prefix: Vec<u8>) -> Result<Self> {
+        let byte_order = match prefix.get(..2) {
+            Some(b"II") => ByteOrder::LittleEndian,
+            Some(b"MM") => ByteOrder::BigEndian,
+            _ => return Err(anyhow::anyhow!("{} is not a TIFF file", url)),
+        };
+        let complete = (prefix.len() as u64) < HEADER_PREFETCH;
+        let reader = RangeReader { url, prefix, complete, byte_order };
+
+        let header = reader.read(2, 14).await?;
+        let (big_tiff, mut offset) = match byte_order.u16(&header) {
+            42 => (false, u64::from(byte_order.u32(&header[2..]))),
+            43 if byte_order.u16(&header[2..]) == 8 => (true, byte_order.u64(&header[6..])),
+            version => {
+                return Err(anyhow::anyhow!("Unsupported TIFF version {} in {}", version, url))
+            }
+        };
+
+        let mut ifds = Vec::new();
+        while offset != 0 {
+            if ifds.len() == MAX_IFDS {
+                return Err(anyhow::anyhow!("More than {} IFDs in {}", MAX_IFDS, url));
+            }
+            let (ifd, next) = reader.read_ifd(offset, big_tiff).await?;
+            ifds.push(ifd);
+            offset = next;
+        }
+        if ifds.is_empty() {
+            return Err(anyhow::anyhow!("{} contains no images", url));
+        }
+
+        Ok(Self { url: url.to_string(), byte_order, big_tiff, ifds })
+    }
+
+    /// The full-resolution image
+    pub fn image(&self) -> &Ifd {
+        &self.ifds[0]
+    }
+
+    /// Reduced-resolution overviews, from the finest to the coarsest
+    pub fn overviews(&self) -> impl Iterator<Item = &Ifd> {
+        self.ifds.iter().skip(1).filter(|ifd| ifd.is_overview())
+    }
+
+    pub fn geo_keys(&self) -> Option<&GeoKeyDirectory> {
+        self.image().geo_keys.as_ref()
+    }
+
+    /// EPSG code of the raster's CRS
+    pub fn epsg(&self) -> Option<u16> {
+        self.geo_keys()?.epsg()
+    }
+
+    /// Affine transform of the full-resolution image, see [`Ifd::transform`]
+    pub fn transform(&self) -> Option<[f64; 6]> {
+        self.image().transform(self.geo_keys())
+    }
+}
+
+/// Fetches `len` bytes of `url` starting at `offset` with an HTTP `Range` request
+///
+/// Fewer bytes are returned only when the range runs past the end of the file.
+/// Servers that ignore `Range` and answer with the whole file are rejected rather
+/// than downloaded, and so are ranges longer than [`MAX_RANGE_BYTES`].
+pub async fn fetch_range(url: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    if len > MAX_RANGE_BYTES {
+        return Err(anyhow::anyhow!(
+            "Range of {} bytes at {} in {} exceeds the {} byte limit",
+            len,
+            offset,
+            url,
+            MAX_RANGE_BYTES
+        ));
+    }
+    let last = offset.checked_add(len - 1).ok_or_else(|| anyhow::anyhow!("Offset overflow"))?;
+    let range = format!("bytes={}-{}", offset, last);
+    let request = Request::get(url).header("Range", &range).body(empty())?;
+    let mut response = Client::new().send(request).await?;
+
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(anyhow::anyhow!(
+            "Range request for {} ({}) failed. Status: {:?}",
+            url,
+            range,
+            response.status()
+        ));
+    }
+
+    // read at most one byte past the range, enough to tell an oversized answer apart
+    let mut buf = vec![0; len as usize + 1];
+    let mut filled = 0;
+    while filled < buf.len() {
+        match response.body_mut().read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    if filled as u64 > len {
+        return Err(anyhow::anyhow!(
+            "Range request for {} ({}) returned more than {} bytes",
+            url,
+            range,
+            len
+        ));
+    }
+    buf.truncate(filled);
+    Ok(buf)
+}
+
+/// Raw value of an IFD entry
+struct Entry {
+    field_type: u16,
+    count: u64,
+    data: Vec<u8>,
+}
+
+/// Size in bytes of one value of a TIFF field type
+fn type_size(field_type: u16) -> Option<u64> {
+    match field_type {
+        // BYTE, ASCII, SBYTE, UNDEFINED
+        1 | 2 | 6 | 7 => Some(1),
+        // SHORT, SSHORT
+        3 | 8 => Some(2),
+        // LONG, SLONG, FLOAT, IFD
+        4 | 9 | 11 | 13 => Some(4),
+        // RATIONAL, SRATIONAL, DOUBLE, LONG8, SLONG8, IFD8
+        5 | 10 | 12 | 16 | 17 | 18 => Some(8),
+        _ => None,
+    }
+}
+
+impl Entry {
+    fn uints(&self, order: ByteOrder) -> Result<Vec<u64>> {
+        let size = type_size(self.field_type).unwrap_or(1) as usize;
+        let values = self.data.chunks_exact(size);
+        Ok(match self.field_type {
+            1 => values.map(|b| u64::from(b[0])).collect(),
+            3 => values.map(|b| u64::from(order.u16(b))).collect(),
+            4 | 13 => values.map(|b| u64::from(order.u32(b))).collect(),
+            16 | 18 => values.map(|b| order.u64(b)).collect(),
+            other => {
+                return Err(anyhow::anyhow!(
+                    "Expected an unsigned integer tag, found type {}",
+                    other
+                ))
+            }
+        })
+    }
+
+    fn uint(&self, order: ByteOrder) -> Result<u64> {
+        self.uints(order)?.first().copied().ok_or_else(|| anyhow::anyhow!("Empty tag"))
+    }
+
+    fn floats(&self, order: ByteOrder) -> Result<Vec<f64>> {
+        let size = type_size(self.field_type).unwrap_or(1) as usize;
+        let values = self.data.chunks_exact(size);
+        Ok(match self.field_type {
+            11 => values.map(|b| f64::from(f32::from_bits(order.u32(b)))).collect(),
+            12 => values.map(|b| f64::from_bits(order.u64(b))).collect(),
+            _ => self.uints(order)?.into_iter().map(|v| v as f64).collect(),
+        })
+    }
+
+    fn ascii(&self) -> Result<String> {
+        if self.field_type != 2 {
+            return Err(anyhow::anyhow!("Expected an ASCII tag, found type {}", self.field_type));
+        }
+        Ok(String::from_utf8_lossy(&self.data).trim_end_matches('\0').to_string())
+    }
+}
+
+/// Serves byte ranges of a remote file, from the prefetched prefix where possible
+struct RangeReader<'a> {
+    url: &'a str,
+    prefix: Vec<u8>,
+    /// Whether `prefix` holds the whole file, so nothing lies beyond it
+    complete: bool,
+    byte_order: ByteOrder,
+}
+
+impl RangeReader<'_> {
+    async fn read(&self, offset: u64, len: u64) -> Result<Cow<'_, [u8]>> {
+        let end = offset.checked_add(len).ok_or_else(|| anyhow::anyhow!("Offset overflow"))?;
+        let bytes = if end <= self.prefix.len() as u64 {
+            Cow::Borrowed(&self.prefix[offset as usize..end as usize])
+        } else if self.complete {
+            let start = usize::try_from(offset).unwrap_or(usize::MAX);
+            Cow::Borrowed(self.prefix.get(start..).unwrap_or_default())
+        } else {
+            Cow::Owned(fetch_range(self.url, offset, len).await?)
+        };
+        if (bytes.len() as u64) < len {
+            return Err(anyhow::anyhow!(
+                "Unexpected end of {} reading {} bytes at {}",
+                self.url,
+                len,
+                offset
+            ));
+        }
+        Ok(bytes)
+    }
+
+    /// Reads the IFD at `offset`, returning it and the offset of the next IFD
+    async fn read_ifd(&self, offset: u64, big_tiff: bool) -> Result<(Ifd, u64)> {
+        let order = self.byte_order;
+        let (count_size, entry_size, value_size) = if big_tiff { (8, 20, 8) } else { (2, 12, 4) };
+
+        let count = self.read(offset, count_size).await?;
+        let count = if big_tiff { order.u64(&count) } else { u64::from(order.u16(&count)) };
+        if count > MAX_IFD_ENTRIES {
+            return Err(anyhow::anyhow!(
+                "IFD at {} in {} has {} entries, more than the {} supported",
+                offset,
+                self.url,
+                count,
+                MAX_IFD_ENTRIES
+            ));
+        }
+        let overflow = || anyhow::anyhow!("IFD at {} in {} overflows", offset, self.url);
+        let entries_len = count.checked_mul(entry_size).ok_or_else(overflow)?;
+        let table_len = entries_len.checked_add(value_size).ok_or_else(overflow)?;
+        let table_offset = offset.checked_add(count_size).ok_or_else(overflow)?;
+        let table = self.read(table_offset, table_len).await?;
+        let (entries, next) = table.split_at(entries_len as usize);
+        let next = if big_tiff { order.u64(next) } else { u64::from(order.u32(next)) };
+
+        let mut tags = BTreeMap::new();
+        for entry in entries.chunks_exact(entry_size as usize) {
+            let tag = order.u16(entry);
+            if !tag::ALL.contains(&tag) {
+                continue;
+            }
+            let field_type = order.u16(&entry[2..]);
+            let Some(size) = type_size(field_type) else {
+                continue;
+            };
+            let (count, value) = if big_tiff {
+                (order.u64(&entry[4..]), &entry[12..20])
+            } else {
+                (u64::from(order.u32(&entry[4..])), &entry[8..12])
+            };
+            let len = count.saturating_mul(size);
+            if len > MAX_TAG_BYTES {
+                return Err(anyhow::anyhow!(
+                    "Tag {} in {} is too large ({} bytes)",
+                    tag,
+                    self.url,
+                    len
+                ));
+            }
+            let data = if len <= value_size {
+                value[..len as usize].to_vec()
+            } else {
+                let at = if big_tiff { order.u64(value) } else { u64::from(order.u32(value)) };
+                self.read(at, len).await?.into_owned()
+            };
+            tags.insert(tag, Entry { field_type, count, data });
+        }
+
+        Ok((self.build_ifd(&tags)?, next))
+    }
+
+    fn build_ifd(&self, tags: &BTreeMap<u16, Entry>) -> Result<Ifd> {
+        let order = self.byte_order;
+        let missing = |name: &str| anyhow::anyhow!("{} has no {} tag", self.url, name);
+        let uint_or = |tag: u16, default: u64| -> Result<u64> {
+            tags.get(&tag).map_or(Ok(default), |e| e.uint(order))
+        };
+        let uint = |tag: u16, name: &str| -> Result<u64> {
+            tags.get(&tag).ok_or_else(|| missing(name))?.uint(order)
+        };
+        let floats = |tag: u16| -> Result<Option<Vec<f64>>> {
+            tags.get(&tag).map(|e| e.floats(order)).transpose()
+        };
+
+        let tile_offsets = tags.get(&tag::TILE_OFFSETS).ok_or_else(|| missing("TileOffsets"))?;
+        let tile_byte_counts =
+            tags.get(&tag::TILE_BYTE_COUNTS).ok_or_else(|| missing("TileByteCounts"))?;
+        if tile_offsets.count != tile_byte_counts.count {
+            return Err(anyhow::anyhow!(
+                "{} has {} tile offsets but {} tile byte counts",
+                self.url,
+                tile_offsets.count,
+                tile_byte_counts.count
+            ));
+        }
+
+        let geo_keys = match tags.get(&tag::GEO_KEY_DIRECTORY) {
+            Some(directory) => Some(GeoKeyDirectory::parse(
+                &directory.uints(order)?,
+                &floats(tag::GEO_DOUBLE_PARAMS)?.unwrap_or_default(),
+                &tags
+                    .get(&tag::GEO_ASCII_PARAMS)
+                    .map(Entry::ascii)
+                    .transpose()?
+                    .unwrap_or_default(),
+            )?),
+            None => None,
+        };
+
+        let ifd = Ifd {
+            subfile_type: uint_or(tag::NEW_SUBFILE_TYPE, 0)? as u32,
+            width: uint(tag::IMAGE_WIDTH, "ImageWidth")?,
+            height: uint(tag::IMAGE_LENGTH, "ImageLength")?,
+            tile_width: uint(tag::TILE_WIDTH, "TileWidth")?,
+            tile_height: uint(tag::TILE_LENGTH, "TileLength")?,
+            bits_per_sample: uint_or(tag::BITS_PER_SAMPLE, 1)? as u16,
+            samples_per_pixel: uint_or(tag::SAMPLES_PER_PIXEL, 1)? as u16,
+            sample_format: uint_or(tag::SAMPLE_FORMAT, 1)? as u16,
+            planar_configuration: uint_or(tag::PLANAR_CONFIGURATION, 1)? as u16,
+            compression: Compression::from(uint_or(tag::COMPRESSION, 1)? as u16),
+            predictor: Predictor::from(uint_or(tag::PREDICTOR, 1)? as u16),
+            tile_offsets: tile_offsets.uints(order)?,
+            tile_byte_counts: tile_byte_counts.uints(order)?,
+            pixel_scale: match floats(tag::MODEL_PIXEL_SCALE)?.as_deref() {
+                Some(&[sx, sy, sz, ..]) => Some([sx, sy, sz]),
+                _ => None,
+            },
+            tiepoints: floats(tag::MODEL_TIEPOINT)?.unwrap_or_default(),
+            model_transformation: floats(tag::MODEL_TRANSFORMATION)?
+                .and_then(|m| <[f64; 16]>::try_from(m).ok()),
+            geo_keys,
+            nodata: tags
+                .get(&tag::GDAL_NODATA)
+                .map(Entry::ascii)
+                .transpose()?
+                .and_then(|v| v.trim().parse().ok()),
+        };
+
+        if ifd.tile_width == 0 || ifd.tile_height == 0 {
+            return Err(anyhow::anyhow!("{} has an empty tile size", self.url));
+        }
+        let planes =
+            if ifd.planar_configuration == 2 { u64::from(ifd.samples_per_pixel) } else { 1 };
+        let expected = ifd.tiles_across() * ifd.tiles_down() * planes;
+        if ifd.tile_offsets.len() as u64 != expected {
+            return Err(anyhow::anyhow!(
+                "{} has {} tiles, expected {}",
+                self.url,
+                ifd.tile_offsets.len(),
+                expected
+            ));
+        }
+        Ok(ifd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cog_writer::{write_cog, SampleFormat, TILE_SIZE};
+    use crate::raster::{GeoTransform, Raster};
+
+    fn parse(bytes: Vec<u8>) -> Result<Cog> {
+        wstd::runtime::block_on(async move { Cog::parse("test.tif", bytes).await })
+    }
+
+    /// A 300x20 float32 COG in EPSG:32610, with one overview
+    fn classic() -> Vec<u8> {
+        let raster = Raster {
+            width: 300,
+            height: 20,
+            data: (0..6000).map(|i| i as f32).collect(),
+            transform: GeoTransform([10.0, 0.0, 499_980.0, 0.0, -10.0, 4_200_000.0]),
+            nodata: Some(f32::NAN),
+        };
+        write_cog(&raster, 32610, SampleFormat::Float32).unwrap()
+    }
+
+    /// A 512x512 BigTIFF of four 256x256 tiles, with the tile arrays stored out of line
+    fn big_tiff(order: ByteOrder, count: u64) -> Vec<u8> {
+        let u16b = |v: u16| match order {
+            ByteOrder::LittleEndian => v.to_le_bytes().to_vec(),
+            ByteOrder::BigEndian => v.to_be_bytes().to_vec(),
+        };
+        let u64b = |v: u64| match order {
+            ByteOrder::LittleEndian => v.to_le_bytes().to_vec(),
+            ByteOrder::BigEndian => v.to_be_bytes().to_vec(),
+        };
+        // (tag, type, count, inline value or offset)
+        let (offsets_at, counts_at) = (16 + 8 + 6 * 20 + 8, 16 + 8 + 6 * 20 + 8 + 32);
+        let entries: [(u16, u16, u64, u64); 6] = [
+            (tag::IMAGE_WIDTH, 16, 1, 512),
+            (tag::IMAGE_LENGTH, 16, 1, 512),
+            (tag::TILE_WIDTH, 16, 1, 256),
+            (tag::TILE_LENGTH, 16, 1, 256),
+            (tag::TILE_OFFSETS, 16, 4, offsets_at),
+            (tag::TILE_BYTE_COUNTS, 16, 4, counts_at),
+        ];
+
+        let mut out = match order {
+            ByteOrder::LittleEndian => b"II".to_vec(),
+            ByteOrder::BigEndian => b"MM".to_vec(),
+        };
+        out.extend(u16b(43));
+        out.extend(u16b(8));
+        out.extend(u16b(0));
+        out.extend(u64b(16));
+        out.extend(u64b(count));
+        for (tag, field_type, count, value) in entries {
+            out.extend(u16b(tag));
+            out.extend(u16b(field_type));
+            out.extend(u64b(count));
+            out.extend(u64b(value));
+        }
+        out.extend(u64b(0));
+        for offset in [1000, 2000, 3000, 4000] {
+            out.extend(u64b(offset));
+        }
+        for len in [100, 0, 300, 400] {
+            out.extend(u64b(len));
+        }
+        out
+    }
+
+    #[test]
+    fn parses_classic_tiff() {
+        let cog = parse(classic()).unwrap();
+        assert_eq!(cog.byte_order, ByteOrder::LittleEndian);
+        assert!(!cog.big_tiff);
+        assert_eq!(cog.ifds.len(), 2);
+
+        let image = cog.image();
+        assert_eq!((image.width, image.height), (300, 20));
+        assert_eq!((image.tile_width, image.tile_height), (TILE_SIZE as u64, TILE_SIZE as u64));
+        assert_eq!((image.tiles_across(), image.tiles_down()), (2, 1));
+        assert_eq!((image.bits_per_sample, image.sample_format), (32, 3));
+        assert_eq!(image.compression, Compression::Deflate);
+        assert_eq!(image.predictor, Predictor::FloatingPoint);
+        assert!(image.nodata.is_some_and(f64::is_nan));
+        assert!(!image.is_overview());
+        assert!(image.tile_range(1, 0).is_some_and(|(_, len)| len > 0));
+        assert_eq!(image.tile_range(2, 0), None);
+
+        assert_eq!(cog.epsg(), Some(32610));
+        assert_eq!(cog.transform(), Some([10.0, 0.0, 499_980.0, 0.0, -10.0, 4_200_000.0]));
+        let overviews: Vec<_> = cog.overviews().collect();
+        assert_eq!(overviews.len(), 1);
+        assert_eq!((overviews[0].width, overviews[0].height), (150, 10));
+        assert_eq!(overviews[0].geo_keys, None);
+    }
+
+    #[test]
+    fn parses_big_tiff() {
+        for order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            let cog = parse(big_tiff(order, 6)).unwrap();
+            assert_eq!(cog.byte_order, order);
+            assert!(cog.big_tiff);
+            assert_eq!(cog.ifds.len(), 1);
+            let image = cog.image();
+            assert_eq!((image.width, image.height), (512, 512));
+            assert_eq!(image.tile_offsets, [1000, 2000, 3000, 4000]);
+            assert_eq!(image.tile_byte_counts, [100, 0, 300, 400]);
+            assert_eq!(image.tile_range(1, 1), Some((4000, 400)));
+            assert_eq!(image.tile_range(1, 0), Some((2000, 0)));
+            assert_eq!(image.compression, Compression::None);
+            assert_eq!(cog.epsg(), None);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_ifd() {
+        let mut bytes = classic();
+        bytes.truncate(100);
+        let err = parse(bytes).unwrap_err().to_string();
+        assert!(err.starts_with("Unexpected end of test.tif"), "{}", err);
+
+        let mut bytes = big_tiff(ByteOrder::LittleEndian, 6);
+        bytes.truncate(bytes.len() - 8);
+        assert!(parse(bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_entry_count() {
+        for count in [MAX_IFD_ENTRIES + 1, u64::MAX / 20 + 1, u64::MAX] {
+            let err = parse(big_tiff(ByteOrder::LittleEndian, count)).unwrap_err().to_string();
+            assert!(err.contains("more than the 4096 supported"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(parse(b"GIF89a".to_vec()).is_err());
+        assert!(parse(b"II\x2b\x00\x04\x00".to_vec()).is_err());
+        assert!(parse(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,496 @@
+use crate::cog::{geokey, tag};
+use crate::raster::{GeoTransform, Raster};
+use anyhow::Result;
+use miniz_oxide::deflate::compress_to_vec_zlib;
+
+/// Edge length of the written tiles, in pixels
+pub const TILE_SIZE: usize = 256;
+
+/// zlib level of the tile streams, fixed so every operator writes the same bytes
+const DEFLATE_LEVEL: u8 = 6;
+
+/// Scale of index values written as int16, mapping -1..1 onto -10000..10000
+pub const INDEX_INT16_SCALE: f64 = 1e-4;
+
+/// int16 sample marking nodata
+pub const INT16_NODATA: i16 = i16::MIN;
+
+/// How samples are stored in a written COG
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleFormat {
+    /// IEEE float32, NaN as nodata
+    Float32,
+    /// Signed 16-bit integers `dn` standing for `dn * scale + offset`, with
+    /// [`INT16_NODATA`] as nodata; values out of range are clamped
+    Int16 { scale: f64, offset: f64 },
+}
+
+impl std::str::FromStr for SampleFormat {
+    type Err = anyhow::Error;
+
+    /// `float32`, or `int16` scaled by [`INDEX_INT16_SCALE`]
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "float32" => Ok(SampleFormat::Float32),
+            "int16" => Ok(SampleFormat::Int16 { scale: INDEX_INT16_SCALE, offset: 0.0 }),
+            other => Err(anyhow::anyhow!("Unknown COG sample format `{}`", other)),
+        }
+    }
+}
+
+impl SampleFormat {
+    fn bits(&self) -> u16 {
+        match self {
+            SampleFormat::Float32 => 32,
+            SampleFormat::Int16 { .. } => 16,
+        }
+    }
+
+    /// `SampleFormat` tag value
+    fn tiff_format(&self) -> u16 {
+        match self {
+            SampleFormat::Float32 => 3,
+            SampleFormat::Int16 { .. } => 2,
+        }
+    }
+
+    /// `Predictor` tag value: floating point for float32, horizontal for int16
+    fn predictor(&self) -> u16 {
+        match self {
+            SampleFormat::Float32 => 3,
+            SampleFormat::Int16 { .. } => 2,
+        }
+    }
+
+    /// `GDAL_NODATA` tag value
+    fn nodata(&self) -> String {
+        match self {
+            SampleFormat::Float32 => "nan".to_string(),
+            SampleFormat::Int16 { .. } => INT16_NODATA.to_string(),
+        }
+    }
+
+    /// Encodes one row of samples with the predictor applied, ready for compression
+    fn encode_row(&self, row: &[f32], out: &mut Vec<u8>) {
+        match *self {
+            SampleFormat::Float32 => {
+                // TIFF floating point predictor: bytes regrouped most significant first,
+                // then differenced across the whole row
+                let start = out.len();
+                out.resize(start + row.len() * 4, 0);
+                let bytes = &mut out[start..];
+                for (i, value) in row.iter().enumerate() {
+                    for (plane, byte) in value.to_be_bytes().into_iter().enumerate() {
+                        bytes[plane * row.len() + i] = byte;
+                    }
+                }
+                for i in (1..bytes.len()).rev() {
+                    bytes[i] = bytes[i].wrapping_sub(bytes[i - 1]);
+                }
+            }
+            SampleFormat::Int16 { scale, offset } => {
+                let mut previous = 0i16;
+                for &value in row {
+                    let dn = if value.is_nan() {
+                        INT16_NODATA
+                    } else {
+                        ((f64::from(value) - offset) / scale)
+                            .round()
+                            .clamp(f64::from(i16::MIN + 1), f64::from(i16::MAX))
+                            as i16
+                    };
+                    out.extend_from_slice(&dn.wrapping_sub(previous).to_le_bytes());
+                    previous = dn;
+                }
+            }
+        }
+    }
+}
+
+/// Encodes `raster` as a little-endian, tiled, Deflate-compressed cloud-optimized GeoTIFF
+///
+/// The file carries the GeoKeys of `epsg`, the raster's transform, a `GDAL_NODATA`
+/// tag and, for int16, the scale and offset as GDAL metadata. Overviews halve the
+/// resolution, averaging the valid pixels of each 2x2 block, until the image fits in
+/// one tile. Following the COG layout, every IFD comes first and the tile data
+/// follows from the coarsest overview to the full image. The output depends only on
+/// the inputs, so every operator writes the same bytes.
+pub fn write_cog(raster: &Raster<f32>, epsg: u32, format: SampleFormat) -> Result<Vec<u8>> {
+    if raster.width == 0 || raster.height == 0 {
+        return Err(anyhow::anyhow!("Cannot write an empty raster"));
+    }
+    let epsg = u16::try_from(epsg).map_err(|_| anyhow::anyhow!("EPSG:{} out of range", epsg))?;
+
+    let mut levels = vec![raster.clone()];
+    while let Some(last) = levels.last().filter(|r| r.width.max(r.height) > TILE_SIZE) {
+        levels.push(downsample(last));
+    }
+    let tiles: Vec<Vec<Vec<u8>>> = levels.iter().map(|level| encode_tiles(level, format)).collect();
+
+    // IFD sizes do not depend on the offsets they hold, so lay them out with
+    // placeholder offsets first
+    let placeholder: Vec<Vec<u32>> = tiles.iter().map(|t| vec![0; t.len()]).collect();
+    let mut ifd_offsets = Vec::with_capacity(levels.len());
+    let mut end = 8u64;
+    for (i, level) in levels.iter().enumerate() {
+        ifd_offsets.push(end);
+        end += ifd_size(&ifd_entries(level, i, epsg, format, &placeholder[i], &tiles[i]));
+    }
+
+    // tile data, coarsest overview first
+    let mut tile_offsets = placeholder;
+    for i in (0..levels.len()).rev() {
+        for (offset, tile) in tile_offsets[i].iter_mut().zip(&tiles[i]) {
+            *offset = u32::try_from(end).map_err(|_| anyhow::anyhow!("COG exceeds 4 GiB"))?;
+            end += tile.len() as u64;
+        }
+    }
+    u32::try_from(end).map_err(|_| anyhow::anyhow!("COG exceeds 4 GiB"))?;
+
+    let mut out = Vec::with_capacity(end as usize);
+    out.extend_from_slice(b"II");
+    out.extend_from_slice(&42u16.to_le_bytes());
+    out.extend_from_slice(&(ifd_offsets[0] as u32).to_le_bytes());
+    for (i, level) in levels.iter().enumerate() {
+        let entries = ifd_entries(level, i, epsg, format, &tile_offsets[i], &tiles[i]);
+        let next = ifd_offsets.get(i + 1).copied().unwrap_or(0) as u32;
+        write_ifd(&mut out, &entries, next);
+    }
+    for level in tiles.iter().rev() {
+        for tile in level {
+            out.extend_from_slice(tile);
+        }
+    }
+    Ok(out)
+}
+
+/// Halves `raster`, averaging the non-NaN pixels of each 2x2 block in `f64`
+fn downsample(raster: &Raster<f32>) -> Raster<f32> {
+    let (width, height) = (raster.width.div_ceil(2), raster.height.div_ceil(2));
+    let mut data = Vec::with_capacity(width * height);
+    for row in 0..height {
+        for col in 0..width {
+            let (mut sum, mut n) = (0.0, 0u32);
+            for (c, r) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let (c, r) = (col * 2 + c, row * 2 + r);
+                if c < raster.width && r < raster.height {
+                    let value = raster.get(c, r);
+                    if !value.is_nan() {
+                        sum += f64::from(value);
+                        n += 1;
+                    }
+                }
+            }
+            data.push(if n == 0 { f32::NAN } else { (sum / f64::from(n)) as f32 });
+        }
+    }
+    let [a, b, c, d, e, f] = raster.transform.0;
+    Raster {
+        width,
+        height,
+        data,
+        transform: GeoTransform([a * 2.0, b * 2.0, c, d * 2.0, e * 2.0, f]),
+        nodata: Some(f32::NAN),
+    }
+}
+
+/// Compressed tiles of `raster`, row-major, edge tiles padded with nodata
+fn encode_tiles(raster: &Raster<f32>, format: SampleFormat) -> Vec<Vec<u8>> {
+    let (across, down) = (raster.width.div_ceil(TILE_SIZE), raster.height.div_ceil(TILE_SIZE));
+    let mut tiles = Vec::with_capacity(across * down);
+    let mut row = vec![f32::NAN; TILE_SIZE];
+    let mut buf = Vec::new();
+    for tile_row in 0..down {
+        for tile_col in 0..across {
+            buf.clear();
+            for r in tile_row * TILE_SIZE..(tile_row + 1) * TILE_SIZE {
+                row.fill(f32::NAN);
+                if r < raster.height {
+                    let start = tile_col * TILE_SIZE;
+                    let end = (start + TILE_SIZE).min(raster.width);
+                    let line = &raster.data[r * raster.width..(r + 1) * raster.width];
+                    row[..end - start].copy_from_slice(&line[start..end]);
+                }
+                format.encode_row(&row, &mut buf);
+            }
+            tiles.push(compress_to_vec_zlib(&buf, DEFLATE_LEVEL));
+        }
+    }
+    tiles
+}
+
+/// Value of an IFD entry
+enum Field {
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Double(Vec<f64>),
+    Ascii(String),
+}
+
+impl Field {
+    fn field_type(&self) -> u16 {
+        match self {
+            Field::Short(_) => 3,
+            Field::Long(_) => 4,
+            Field::Double(_) => 12,
+            Field::Ascii(_) => 2,
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match self {
+            Field::Short(v) => v.len() as u32,
+            Field::Long(v) => v.len() as u32,
+            Field::Double(v) => v.len() as u32,
+            // NUL terminated
+            Field::Ascii(s) => s.len() as u32 + 1,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Field::Short(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Field::Long(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Field::Double(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Field::Ascii(s) => s.bytes().chain([0]).collect(),
+        }
+    }
+}
+
+/// Entries of the IFD of `level`, sorted by tag; level 0 is the full image
+fn ifd_entries(
+    raster: &Raster<f32>,
+    level: usize,
+    epsg: u16,
+    format: SampleFormat,
+    tile_offsets: &[u32],
+    tiles: &[Vec<u8>],
+) -> Vec<(u16, Field)> {
+    let mut entries = vec![
+        (tag::NEW_SUBFILE_TYPE, Field::Long(vec![u32::from(level > 0)])),
+        (tag::IMAGE_WIDTH, Field::Long(vec![raster.width as u32])),
+        (tag::IMAGE_LENGTH, Field::Long(vec![raster.height as u32])),
+        (tag::BITS_PER_SAMPLE, Field::Short(vec![format.bits()])),
+        // Deflate
+        (tag::COMPRESSION, Field::Short(vec![8])),
+        // BlackIsZero
+        (tag::PHOTOMETRIC_INTERPRETATION, Field::Short(vec![1])),
+        (tag::SAMPLES_PER_PIXEL, Field::Short(vec![1])),
+        (tag::PLANAR_CONFIGURATION, Field::Short(vec![1])),
+        (tag::PREDICTOR, Field::Short(vec![format.predictor()])),
+        (tag::TILE_WIDTH, Field::Short(vec![TILE_SIZE as u16])),
+        (tag::TILE_LENGTH, Field::Short(vec![TILE_SIZE as u16])),
+        (tag::TILE_OFFSETS, Field::Long(tile_offsets.to_vec())),
+        (tag::TILE_BYTE_COUNTS, Field::Long(tiles.iter().map(|t| t.len() as u32).collect())),
+        (tag::SAMPLE_FORMAT, Field::Short(vec![format.tiff_format()])),
+        (tag::GDAL_NODATA, Field::Ascii(format.nodata())),
+    ];
+
+    if level == 0 {
+        let [a, b, c, d, e, f] = raster.transform.0;
+        if b == 0.0 && d == 0.0 {
+            entries.push((tag::MODEL_PIXEL_SCALE, Field::Double(vec![a, -e, 0.0])));
+            entries.push((tag::MODEL_TIEPOINT, Field::Double(vec![0.0, 0.0, 0.0, c, f, 0.0])));
+        } else {
+            // row-major 4x4 matrix mapping (col, row, 0, 1) to (x, y, z, 1)
+            let mut matrix = vec![a, b, 0.0, c, d, e, 0.0, f];
+            matrix.extend([0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+            entries.push((tag::MODEL_TRANSFORMATION, Field::Double(matrix)));
+        }
+
+        // EPSG:4326 and its kin are geographic, everything this oracle writes otherwise
+        // is projected
+        let (model_type, crs_key) = if (4000..5000).contains(&epsg) {
+            (2, geokey::GEOGRAPHIC_TYPE)
+        } else {
+            (1, geokey::PROJECTED_CS_TYPE)
+        };
+        // header (version 1.1.0, three keys), then `[key, location, count, value]`
+        // entries with the values stored inline
+        let mut directory = vec![1, 1, 0, 3];
+        directory.extend([geokey::MODEL_TYPE, 0, 1, model_type]);
+        // PixelIsArea
+        directory.extend([geokey::RASTER_TYPE, 0, 1, 1]);
+        directory.extend([crs_key, 0, 1, epsg]);
+        entries.push((tag::GEO_KEY_DIRECTORY, Field::Short(directory)));
+
+        if let SampleFormat::Int16 { scale, offset } = format {
+            entries.push((
+                tag::GDAL_METADATA,
+                Field::Ascii(format!(
+                    "<GDALMetadata><Item name=\"OFFSET\" sample=\"0\" role=\"offset\">{}</Item>\
+                     <Item name=\"SCALE\" sample=\"0\" role=\"scale\">{}</Item></GDALMetadata>",
+                    offset, scale
+                )),
+            ));
+        }
+    }
+
+    entries.sort_by_key(|(tag, _)| *tag);
+    entries
+}
+
+/// Bytes taken by an IFD and its out-of-line values
+fn ifd_size(entries: &[(u16, Field)]) -> u64 {
+    let values: u64 = entries
+        .iter()
+        .map(|(_, field)| field.bytes().len() as u64)
+        .filter(|&len| len > 4)
+        .map(|len| len + len % 2)
+        .sum();
+    2 + 12 * entries.len() as u64 + 4 + values
+}
+
+/// Appends an IFD followed by its out-of-line values, word aligned
+fn write_ifd(out: &mut Vec<u8>, entries: &[(u16, Field)], next: u32) {
+    let mut values_at = out.len() + 2 + 12 * entries.len() + 4;
+    let mut values = Vec::new();
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, field) in entries {
+        let bytes = field.bytes();
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&field.field_type().to_le_bytes());
+        out.extend_from_slice(&field.count().to_le_bytes());
+        if bytes.len() <= 4 {
+            let mut inline = [0u8; 4];
+            inline[..bytes.len()].copy_from_slice(&bytes);
+            out.extend_from_slice(&inline);
+        } else {
+            out.extend_from_slice(&(values_at as u32).to_le_bytes());
+            values_at += bytes.len() + bytes.len() % 2;
+            values.extend_from_slice(&bytes);
+            if bytes.len() % 2 == 1 {
+                values.push(0);
+            }
+        }
+    }
+    out.extend_from_slice(&next.to_le_bytes());
+    out.extend_from_slice(&values);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cog::{Cog, Compression, Ifd, Predictor};
+    use crate::raster::{decode_tile, Sample};
+
+    fn raster(width: usize, height: usize, value: impl Fn(usize, usize) -> f32) -> Raster<f32> {
+        let data = (0..width * height).map(|i| value(i % width, i / width)).collect();
+        Raster {
+            width,
+            height,
+            data,
+            transform: GeoTransform([10.0, 0.0, 499_980.0, 0.0, -10.0, 4_200_000.0]),
+            nodata: Some(f32::NAN),
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> Cog {
+        let prefix = bytes.to_vec();
+        wstd::runtime::block_on(async move { Cog::parse("test.tif", prefix).await }).unwrap()
+    }
+
+    /// Every pixel of `ifd`, decoded tile by tile from `bytes`
+    fn pixels<T: Sample>(cog: &Cog, ifd: &Ifd, bytes: &[u8]) -> Vec<T> {
+        let (width, height) = (ifd.width as usize, ifd.height as usize);
+        let mut data = vec![T::default(); width * height];
+        for tile_row in 0..ifd.tiles_down() {
+            for tile_col in 0..ifd.tiles_across() {
+                let (offset, len) = ifd.tile_range(tile_col, tile_row).unwrap();
+                let payload = &bytes[offset as usize..(offset + len) as usize];
+                let tile = decode_tile::<T>(ifd, cog.byte_order, payload).unwrap();
+                for (i, &sample) in tile.iter().enumerate() {
+                    let col = tile_col as usize * TILE_SIZE + i % TILE_SIZE;
+                    let row = tile_row as usize * TILE_SIZE + i / TILE_SIZE;
+                    if col < width && row < height {
+                        data[row * width + col] = sample;
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn float32_round_trip() {
+        let input = raster(520, 300, |col, row| {
+            if (col + row) % 7 == 0 {
+                f32::NAN
+            } else {
+                (col as f32 - row as f32) / 1000.0
+            }
+        });
+        let bytes = write_cog(&input, 32610, SampleFormat::Float32).unwrap();
+        let cog = parse(&bytes);
+
+        let image = cog.image();
+        assert_eq!((image.width, image.height), (520, 300));
+        assert_eq!((image.tile_width, image.tile_height), (256, 256));
+        assert_eq!((image.bits_per_sample, image.sample_format), (32, 3));
+        assert_eq!(image.compression, Compression::Deflate);
+        assert_eq!(image.predictor, Predictor::FloatingPoint);
+        assert!(image.nodata.is_some_and(f64::is_nan));
+        assert_eq!(cog.epsg(), Some(32610));
+        assert_eq!(cog.transform(), Some(input.transform.0));
+
+        // halved until the image fits in one tile
+        let sizes: Vec<_> = cog.overviews().map(|ifd| (ifd.width, ifd.height)).collect();
+        assert_eq!(sizes, [(260, 150), (130, 75)]);
+        assert!(cog.overviews().all(|ifd| ifd.predictor == Predictor::FloatingPoint));
+
+        let decoded = pixels::<f32>(&cog, image, &bytes);
+        let bits = |data: &[f32]| data.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&decoded), bits(&input.data));
+
+        let overview = cog.overviews().next().unwrap();
+        let decoded = pixels::<f32>(&cog, overview, &bytes);
+        assert_eq!(bits(&decoded), bits(&downsample(&input).data));
+    }
+
+    #[test]
+    fn int16_round_trip() {
+        let values = [0.1234, -0.5, f32::NAN, 5.0, -5.0, 0.0];
+        let input = raster(300, 2, |col, _| values[col % values.len()]);
+        let format: SampleFormat = "int16".parse().unwrap();
+        let bytes = write_cog(&input, 32756, format).unwrap();
+        let cog = parse(&bytes);
+
+        let image = cog.image();
+        assert_eq!((image.bits_per_sample, image.sample_format), (16, 2));
+        assert_eq!(image.predictor, Predictor::Horizontal);
+        assert_eq!(image.nodata, Some(f64::from(INT16_NODATA)));
+        assert_eq!(cog.epsg(), Some(32756));
+        assert_eq!(cog.overviews().count(), 1);
+
+        // scaled by 1e-4, nodata for NaN, clamped short of the nodata value
+        let decoded = pixels::<i16>(&cog, image, &bytes);
+        assert_eq!(decoded[..6], [1234, -5000, INT16_NODATA, i16::MAX, i16::MIN + 1, 0]);
+        assert_eq!(decoded[300..306], decoded[..6]);
+
+        let metadata = "<Item name=\"SCALE\" sample=\"0\" role=\"scale\">0.0001</Item>";
+        assert!(bytes.windows(metadata.len()).any(|w| w == metadata.as_bytes()));
+    }
+
+    #[test]
+    fn geographic_and_rotated_grids() {
+        let mut input = raster(4, 4, |col, row| (col * row) as f32);
+        input.transform = GeoTransform([0.001, 0.0, -123.0, 0.0, -0.001, 38.0]);
+        let cog = parse(&write_cog(&input, 4326, SampleFormat::Float32).unwrap());
+        assert_eq!(cog.epsg(), Some(4326));
+        assert_eq!(cog.overviews().count(), 0);
+
+        input.transform = GeoTransform([8.0, 6.0, 499_980.0, 6.0, -8.0, 4_200_000.0]);
+        let cog = parse(&write_cog(&input, 32610, SampleFormat::Float32).unwrap());
+        assert_eq!(cog.transform(), Some(input.transform.0));
+    }
+
+    #[test]
+    fn deterministic() {
+        let input = raster(300, 300, |col, row| ((col * 31 + row * 17) % 101) as f32 / 100.0);
+        let a = write_cog(&input, 32610, SampleFormat::Float32).unwrap();
+        let b = write_cog(&input, 32610, SampleFormat::Float32).unwrap();
+        assert_eq!(a, b);
+        assert!(write_cog(&raster(0, 3, |_, _| 0.0), 32610, SampleFormat::Float32).is_err());
+        assert!(write_cog(&input, 100_000, SampleFormat::Float32).is_err());
+    }
+}
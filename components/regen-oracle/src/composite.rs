@@ -0,0 +1,644 @@
+use crate::analysis::{self, SceneIndex};
+use crate::aoi::{Aoi, Coverage};
+use crate::bands::{Band, BandSet};
+use crate::cog_writer::{self, SampleFormat};
+use crate::index::{SpectralIndex, Stats};
+use crate::ipfs;
+use crate::mask::SclMask;
+use crate::metadata::{self, COMPONENT_VERSION};
+use crate::pinner::Pinner;
+use crate::raster::{GeoTransform, Raster, Window};
+use crate::stac::{Item, SearchRequest};
+use crate::utm::Utm;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Pixel size of the MGRS tile grid composites are built on, in metres
+pub const TILE_RESOLUTION: f64 = 10.0;
+
+/// Per-pixel rule combining the observations of a composite
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    /// Median of the valid observations
+    #[default]
+    Median,
+    /// The observation with the highest NDVI, the "greenest pixel"
+    MaxNdvi,
+}
+
+impl Method {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::Median => "median",
+            Method::MaxNdvi => "max_ndvi",
+        }
+    }
+
+    /// Whether compositing `index` needs an NDVI layer next to it to rank observations
+    pub fn needs_ndvi(&self, index: SpectralIndex) -> bool {
+        *self == Method::MaxNdvi && index != SpectralIndex::Ndvi
+    }
+
+    /// Bands read per scene: those of [`analysis::analysis_bands`], plus red and NIR
+    /// when [`Method::needs_ndvi`]
+    pub fn bands(&self, index: SpectralIndex) -> Vec<Band> {
+        let mut bands = analysis::analysis_bands(index);
+        if self.needs_ndvi(index) {
+            for &band in SpectralIndex::Ndvi.bands() {
+                if !bands.contains(&band) {
+                    bands.push(band);
+                }
+            }
+        }
+        bands
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for Method {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "median" => Ok(Method::Median),
+            "max_ndvi" => Ok(Method::MaxNdvi),
+            other => Err(anyhow::anyhow!("Unknown composite method `{}`", other)),
+        }
+    }
+}
+
+/// The window of an MGRS tile's native 10m grid covering the AOI
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileGrid {
+    /// `grid:code` of the tile, e.g. `MGRS-33UUP`
+    pub grid_code: String,
+    pub epsg: u32,
+    pub transform: GeoTransform,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl TileGrid {
+    /// Grid of `item`'s tile over the envelope of `aoi`
+    ///
+    /// Every Sentinel-2 band of a tile shares the tile origin, so the grid is derived
+    /// from the `proj:transform` and `proj:shape` of the index's first input band,
+    /// rescaled to [`TILE_RESOLUTION`]. All scenes of the tile then land on the same
+    /// pixels whatever bands the index reads.
+    pub fn new(item: &Item, bands: &BandSet, index: SpectralIndex, aoi: &Aoi) -> Result<Self> {
+        let grid_code = item
+            .properties
+            .grid_code
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Item {} has no grid:code", item.id))?;
+        let epsg = item
+            .properties
+            .epsg
+            .ok_or_else(|| anyhow::anyhow!("Item {} has no proj:epsg", item.id))?;
+        let band =
+            index.bands().first().ok_or_else(|| anyhow::anyhow!("{} has no input bands", index))?;
+        let asset = bands.get(*band)?;
+
+        let [a, b, c, d, e, f] = asset.transform;
+        if b != 0.0 || d != 0.0 {
+            return Err(anyhow::anyhow!("Asset {} of {} is not north-up", asset.key, item.id));
+        }
+        let scale = a.abs() / TILE_RESOLUTION;
+        let native = GeoTransform([
+            TILE_RESOLUTION.copysign(a),
+            0.0,
+            c,
+            0.0,
+            TILE_RESOLUTION.copysign(e),
+            f,
+        ]);
+        let [rows, cols] = asset.shape;
+        let shape = [(rows as f64 * scale).round() as u64, (cols as f64 * scale).round() as u64];
+
+        let bounds = aoi.project(&Utm::from_epsg(epsg)?).bounds();
+        let window = Window::for_bounds(&native, shape, &bounds).ok_or_else(|| {
+            anyhow::anyhow!("AOI does not overlap tile {} of {}", grid_code, item.id)
+        })?;
+
+        Ok(Self {
+            grid_code,
+            epsg,
+            transform: native.for_window(&window),
+            width: window.width as usize,
+            height: window.height as usize,
+        })
+    }
+
+    /// `raster`, resampled onto the grid by nearest neighbour
+    pub fn align(&self, raster: &Raster<f32>) -> Raster<f32> {
+        raster.resample_nearest(self.width, self.height, self.transform)
+    }
+
+    /// AOI weight of each pixel of the grid, see [`Aoi::rasterize`]
+    pub fn weights(&self, aoi: &Aoi, coverage: Coverage) -> Result<Vec<f32>> {
+        let projected = aoi.project(&Utm::from_epsg(self.epsg)?);
+        projected.rasterize(self.width, self.height, &self.transform, coverage)
+    }
+}
+
+/// `grid:code` of the tile with the most scenes among `items`, ties to the lowest code
+///
+/// Items without a `grid:code` are ignored.
+pub fn select_tile<'a>(items: impl IntoIterator<Item = &'a Item>) -> Option<String> {
+    let mut counts = BTreeMap::<&str, usize>::new();
+    for item in items {
+        if let Some(code) = item.properties.grid_code.as_deref() {
+            *counts.entry(code).or_default() += 1;
+        }
+    }
+    // `max_by_key` keeps the last maximum, so walk the codes from the highest down
+    counts.into_iter().rev().max_by_key(|&(_, n)| n).map(|(code, _)| code.to_string())
+}
+
+/// One scene read for compositing
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneLayers {
+    pub index: SceneIndex,
+    /// Masked NDVI ranking the observations, when [`Method::needs_ndvi`]
+    pub ndvi: Option<SceneIndex>,
+}
+
+/// Reads the masked `index` of `item` and, if `method` ranks by it, its masked NDVI
+///
+/// `bands` must hold [`Method::bands`]. Band windows the index has already read,
+/// such as the red and NIR of SAVI, are reused for the NDVI. See
+/// [`analysis::read_scene_indices`].
+pub async fn read_scene_layers(
+    item: &Item,
+    bands: &BandSet,
+    index: SpectralIndex,
+    method: Method,
+    aoi: &Aoi,
+    scl_mask: &SclMask,
+    coverage: Coverage,
+) -> Result<SceneLayers> {
+    let indices: &[SpectralIndex] =
+        if method.needs_ndvi(index) { &[index, SpectralIndex::Ndvi] } else { &[index] };
+    let mut scenes =
+        analysis::read_scene_indices(item, bands, indices, aoi, scl_mask, coverage).await?;
+    let ndvi = if scenes.len() > 1 { scenes.pop() } else { None };
+    let values = scenes.pop().ok_or_else(|| anyhow::anyhow!("No index read for {}", item.id))?;
+    Ok(SceneLayers { index: values, ndvi })
+}
+
+/// A per-pixel composite of several scenes of one tile
+#[derive(Debug, Clone, PartialEq)]
+pub struct Composite {
+    pub method: Method,
+    /// Composited index values, NaN where no observation is valid
+    pub values: Raster<f32>,
+    /// Number of valid observations of each pixel
+    pub count: Raster<u16>,
+}
+
+impl Composite {
+    /// Aligns `scenes` on `grid` and composites them by `method`
+    ///
+    /// `scenes` must be in datetime order: the max-NDVI rule gives ties to the
+    /// earliest observation.
+    pub fn build(method: Method, grid: &TileGrid, scenes: &[SceneLayers]) -> Result<Self> {
+        if scenes.is_empty() {
+            return Err(anyhow::anyhow!("No scenes to composite"));
+        }
+        let mut values = Vec::with_capacity(scenes.len());
+        let mut ndvi = Vec::with_capacity(scenes.len());
+        for scene in scenes {
+            if scene.index.epsg != grid.epsg {
+                return Err(anyhow::anyhow!(
+                    "Scene in EPSG:{} cannot be composited on tile {} in EPSG:{}",
+                    scene.index.epsg,
+                    grid.grid_code,
+                    grid.epsg
+                ));
+            }
+            values.push(grid.align(&scene.index.values));
+            ndvi.push(scene.ndvi.as_ref().map(|ndvi| grid.align(&ndvi.values)));
+        }
+        let layers: Vec<&Raster<f32>> = values.iter().collect();
+
+        let composite = match method {
+            Method::Median => median(&layers)?,
+            Method::MaxNdvi => {
+                let greenness: Vec<&Raster<f32>> = ndvi
+                    .iter()
+                    .zip(&values)
+                    .map(|(ndvi, values)| ndvi.as_ref().unwrap_or(values))
+                    .collect();
+                max_ndvi(&layers, &greenness)?
+            }
+        };
+        Ok(Self { method, values: composite, count: valid_count(&layers)? })
+    }
+
+    /// Statistics of the valid observation count over the AOI
+    pub fn count_stats(&self, weights: &[f32]) -> Option<Stats> {
+        Stats::weighted(&self.count_raster(), weights)
+    }
+
+    /// The valid observation count as floats, for statistics and encoding
+    pub fn count_raster(&self) -> Raster<f32> {
+        Raster {
+            width: self.count.width,
+            height: self.count.height,
+            data: self.count.data.iter().map(|&n| f32::from(n)).collect(),
+            transform: self.count.transform,
+            nodata: None,
+        }
+    }
+}
+
+/// Per-pixel median of `layers`, ignoring NaN
+///
+/// The layers must share one grid. With an even number of valid values the
+/// median is the mean of the two central ones; pixels without any valid value
+/// are NaN. Values are sorted with `f32::total_cmp` so every operator gets the
+/// same result bit for bit.
+pub fn median(layers: &[&Raster<f32>]) -> Result<Raster<f32>> {
+    let first = layers.first().ok_or_else(|| anyhow::anyhow!("No layers to composite"))?;
+    check_aligned(first, layers)?;
+
+    let mut values = Vec::with_capacity(layers.len());
+    let data = (0..first.data.len())
+        .map(|i| {
+            values.clear();
+            values.extend(layers.iter().map(|layer| layer.data[i]).filter(|v| !v.is_nan()));
+            values.sort_by(f32::total_cmp);
+            match values.len() {
+                0 => f32::NAN,
+                n if n % 2 == 1 => values[n / 2],
+                n => ((f64::from(values[n / 2 - 1]) + f64::from(values[n / 2])) / 2.0) as f32,
+            }
+        })
+        .collect();
+
+    Ok(nan_raster(first, data))
+}
+
+/// Per-pixel value of `layers` from the layer whose `greenness` is highest
+///
+/// Observations where either the value or its greenness is NaN are skipped, and
+/// ties go to the first layer, so the result does not depend on anything but the
+/// order of the layers.
+pub fn max_ndvi(layers: &[&Raster<f32>], greenness: &[&Raster<f32>]) -> Result<Raster<f32>> {
+    let first = layers.first().ok_or_else(|| anyhow::anyhow!("No layers to composite"))?;
+    if greenness.len() != layers.len() {
+        return Err(anyhow::anyhow!(
+            "{} greenness layers for {} layers",
+            greenness.len(),
+            layers.len()
+        ));
+    }
+    check_aligned(first, layers)?;
+    check_aligned(first, greenness)?;
+
+    let data = (0..first.data.len())
+        .map(|i| {
+            let mut best: Option<(f32, f32)> = None;
+            for (layer, green) in layers.iter().zip(greenness) {
+                let (value, green) = (layer.data[i], green.data[i]);
+                if value.is_nan() || green.is_nan() {
+                    continue;
+                }
+                if best.map_or(true, |(_, max)| green > max) {
+                    best = Some((value, green));
+                }
+            }
+            best.map_or(f32::NAN, |(value, _)| value)
+        })
+        .collect();
+
+    Ok(nan_raster(first, data))
+}
+
+/// Per-pixel number of non-NaN values of `layers`
+pub fn valid_count(layers: &[&Raster<f32>]) -> Result<Raster<u16>> {
+    let first = layers.first().ok_or_else(|| anyhow::anyhow!("No layers to composite"))?;
+    check_aligned(first, layers)?;
+    let data = (0..first.data.len())
+        .map(|i| {
+            let n = layers.iter().filter(|layer| !layer.data[i].is_nan()).count();
+            u16::try_from(n).unwrap_or(u16::MAX)
+        })
+        .collect();
+    Ok(Raster {
+        width: first.width,
+        height: first.height,
+        data,
+        transform: first.transform,
+        nodata: None,
+    })
+}
+
+fn nan_raster(grid: &Raster<f32>, data: Vec<f32>) -> Raster<f32> {
+    Raster {
+        width: grid.width,
+        height: grid.height,
+        data,
+        transform: grid.transform,
+        nodata: Some(f32::NAN),
+    }
+}
+
+fn check_aligned(first: &Raster<f32>, layers: &[&Raster<f32>]) -> Result<()> {
+    for layer in layers {
+        if layer.width != first.width
+            || layer.height != first.height
+            || layer.transform != first.transform
+        {
+            return Err(anyhow::anyhow!(
+                "Layer {}x{} at {:?} is not on the composite grid {}x{} at {:?}",
+                layer.width,
+                layer.height,
+                layer.transform.0,
+                first.width,
+                first.height,
+                first.transform.0
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Composite document of one period, uploaded as canonical JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompositeReport {
+    pub version: String,
+    pub search: SearchRequest,
+    pub index: SpectralIndex,
+    pub method: Method,
+    pub coverage: Coverage,
+    pub grid: TileGrid,
+    /// GeoJSON polygon outlining the grid, in WGS84
+    pub footprint: Value,
+    /// Items composited, in datetime order
+    pub items: Vec<String>,
+    /// Statistics of the composite over the AOI, `None` when no pixel is valid
+    pub stats: Option<Stats>,
+    /// Statistics of the valid observation count over the AOI
+    pub count: Option<Stats>,
+    /// Path of the composite raster within the bundle, set once it is uploaded
+    ///
+    /// A cloud-optimized GeoTIFF on `grid`, nodata where no observation is valid.
+    pub raster: Option<String>,
+    /// Path of the count raster within the bundle, an int16 cloud-optimized GeoTIFF
+    /// on `grid`
+    pub count_raster: Option<String>,
+}
+
+impl CompositeReport {
+    pub fn new(
+        search: SearchRequest,
+        index: SpectralIndex,
+        coverage: Coverage,
+        grid: TileGrid,
+        items: Vec<String>,
+        composite: &Composite,
+        weights: &[f32],
+    ) -> Result<Self> {
+        let utm = Utm::from_epsg(grid.epsg)?;
+        Ok(Self {
+            version: COMPONENT_VERSION.to_string(),
+            search,
+            index,
+            method: composite.method,
+            coverage,
+            footprint: analysis::footprint(&utm, &composite.values),
+            grid,
+            items,
+            stats: Stats::weighted(&composite.values, weights),
+            count: composite.count_stats(weights),
+            raster: None,
+            count_raster: None,
+        })
+    }
+
+    /// Name of the bundle directory: index, method, tile, last item, `composite`
+    pub fn bundle_name(&self) -> String {
+        format!(
+            "{}_{}_{}_{}_composite",
+            self.index,
+            self.method,
+            self.grid.grid_code,
+            self.items.last().map_or("none", String::as_str)
+        )
+    }
+
+    /// Uploads the canonical JSON document, the composite raster as a COG in `format`
+    /// and the count raster as an int16 COG as one directory
+    ///
+    /// Returns the JSON document's `ipfs://CID/composite.json` URI, CID being the
+    /// directory's.
+    pub async fn upload(
+        &mut self,
+        composite: &Composite,
+        format: SampleFormat,
+        pinner: &Pinner,
+    ) -> Result<String> {
+        let epsg = self.grid.epsg;
+        let values = cog_writer::write_cog(&composite.values, epsg, format)?;
+        let count = cog_writer::write_cog(
+            &composite.count_raster(),
+            epsg,
+            SampleFormat::Int16 { scale: 1.0, offset: 0.0 },
+        )?;
+        self.raster = Some("composite.tif".to_string());
+        self.count_raster = Some("count.tif".to_string());
+        let json = metadata::canonical_json(self)?;
+        let files = [
+            ("composite.json", json.as_bytes()),
+            ("composite.tif", values.as_slice()),
+            ("count.tif", count.as_slice()),
+        ];
+        let root = ipfs::upload_directory_to_ipfs(&self.bundle_name(), &files, pinner).await?;
+        Ok(ipfs::get_ipfs_url(&root, Some("composite.json")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bands::BandAsset;
+
+    const NAN: f32 = f32::NAN;
+
+    fn layer(data: &[f32]) -> Raster<f32> {
+        Raster {
+            width: data.len(),
+            height: 1,
+            data: data.to_vec(),
+            transform: GeoTransform([10.0, 0.0, 500_000.0, 0.0, -10.0, 4_200_000.0]),
+            nodata: Some(f32::NAN),
+        }
+    }
+
+    /// Bit patterns, so NaN compares equal to NaN
+    fn bits(raster: &Raster<f32>) -> Vec<u32> {
+        raster.data.iter().map(|v| v.to_bits()).collect()
+    }
+
+    #[test]
+    fn median_of_valid_values() {
+        let a = layer(&[0.1, 0.4, NAN, NAN, 0.25]);
+        let b = layer(&[0.3, 0.2, 0.5, NAN, 0.75]);
+        let c = layer(&[0.2, NAN, NAN, NAN, 0.5]);
+        let odd = median(&[&a, &b, &c]).unwrap();
+        // odd count, even count, a single value, none at all
+        assert_eq!(odd.data[..3], [0.2, 0.3, 0.5]);
+        assert!(odd.data[3].is_nan());
+        assert_eq!(odd.data[4], 0.5);
+
+        // even counts average the central values in f64
+        let d = layer(&[0.4, 0.1, 0.5, NAN, 1.0]);
+        let even = median(&[&a, &b, &c, &d]).unwrap();
+        assert_eq!(even.data[0], ((0.2f32 as f64 + 0.3f32 as f64) / 2.0) as f32);
+        assert_eq!(even.data[4], 0.625);
+        assert_eq!(even.nodata.map(f32::is_nan), Some(true));
+    }
+
+    #[test]
+    fn median_ignores_layer_order() {
+        let a = layer(&[0.1, -0.4, NAN, 0.9]);
+        let b = layer(&[0.3, 0.2, 0.5, NAN]);
+        let c = layer(&[-0.2, NAN, 0.6, 0.1]);
+        let d = layer(&[0.7, 0.0, -0.1, 0.3]);
+        let expected = bits(&median(&[&a, &b, &c, &d]).unwrap());
+        for order in [[&d, &c, &b, &a], [&b, &d, &a, &c], [&c, &a, &d, &b]] {
+            assert_eq!(bits(&median(&order).unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn max_ndvi_takes_the_greenest_observation() {
+        let values = [layer(&[1.0, 1.0, 1.0, 1.0]), layer(&[2.0, 2.0, NAN, 2.0])];
+        let greenness = [layer(&[0.3, 0.6, 0.1, NAN]), layer(&[0.5, 0.6, 0.9, NAN])];
+        let layers: Vec<_> = values.iter().collect();
+        let green: Vec<_> = greenness.iter().collect();
+        let composite = max_ndvi(&layers, &green).unwrap();
+        // greener second layer, a tie to the first, a NaN value skipped, NaN greenness skipped
+        assert_eq!(composite.data[..3], [2.0, 1.0, 1.0]);
+        assert!(composite.data[3].is_nan());
+
+        // swapping the layers moves only the tie
+        let swapped = max_ndvi(&[layers[1], layers[0]], &[green[1], green[0]]).unwrap();
+        assert_eq!(swapped.data[..3], [2.0, 2.0, 1.0]);
+
+        assert!(max_ndvi(&layers, &green[..1]).is_err());
+    }
+
+    #[test]
+    fn counts_valid_observations() {
+        let a = layer(&[0.1, NAN, NAN]);
+        let b = layer(&[0.2, 0.3, NAN]);
+        let count = valid_count(&[&a, &b]).unwrap();
+        assert_eq!(count.data, [2, 1, 0]);
+        assert_eq!(count.nodata, None);
+
+        let all_nan = median(&[&layer(&[NAN, NAN])]).unwrap();
+        assert!(all_nan.data.iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn rejects_layers_off_the_grid() {
+        let a = layer(&[0.1, 0.2]);
+        let b = layer(&[0.1, 0.2, 0.3]);
+        let mut c = layer(&[0.1, 0.2]);
+        c.transform.0[2] += 10.0;
+        assert!(median(&[&a, &b]).is_err());
+        assert!(median(&[&a, &c]).is_err());
+        assert!(valid_count(&[&a, &c]).is_err());
+        assert!(median(&[]).is_err());
+    }
+
+    fn item() -> Item {
+        serde_json::from_value(serde_json::json!({
+            "id": "S2B_10SEG_20240627_0_L2A",
+            "properties": {
+                "datetime": "2024-06-27T19:04:13.624000Z",
+                "grid:code": "MGRS-10SEG",
+                "proj:epsg": 32610
+            },
+            "geometry": null
+        }))
+        .unwrap()
+    }
+
+    fn band_set(band: Band, resolution: f64) -> BandSet {
+        let size = (109_800.0 / resolution) as u64;
+        let asset = BandAsset {
+            band,
+            key: band.asset_key().to_string(),
+            href: format!("https://example.com/{}.tif", band.asset_key()),
+            media_type: "image/tiff; application=geotiff; profile=cloud-optimized".to_string(),
+            shape: [size, size],
+            transform: [resolution, 0.0, 499_980.0, 0.0, -resolution, 4_200_000.0],
+            scale: 0.0001,
+            offset: -0.1,
+            nodata: Some(0.0),
+            gsd: None,
+        };
+        BandSet { item_id: item().id, bands: BTreeMap::from([(band, asset)]) }
+    }
+
+    #[test]
+    fn tile_grid_covers_the_aoi_on_the_10m_grid() {
+        let aoi = Aoi::from_bbox([-123.0, 37.9, -122.99, 37.91]);
+        let grid =
+            TileGrid::new(&item(), &band_set(Band::Nir, 10.0), SpectralIndex::Ndvi, &aoi).unwrap();
+        assert_eq!(grid.grid_code, "MGRS-10SEG");
+        assert_eq!(grid.epsg, 32610);
+
+        let [a, b, c, d, e, f] = grid.transform.0;
+        assert_eq!([a, b, d, e], [10.0, 0.0, 0.0, -10.0]);
+        assert_eq!((c - 499_980.0) % 10.0, 0.0);
+        assert_eq!((4_200_000.0 - f) % 10.0, 0.0);
+
+        // the grid covers the projected envelope, with less than a pixel to spare
+        let bounds = aoi.project(&Utm::from_epsg(32610).unwrap()).bounds();
+        let (max_x, min_y) = grid.transform.apply(grid.width as f64, grid.height as f64);
+        assert!(c <= bounds.min_x && c > bounds.min_x - 10.0);
+        assert!(f >= bounds.max_y && f < bounds.max_y + 10.0);
+        assert!(max_x >= bounds.max_x && max_x < bounds.max_x + 10.0);
+        assert!(min_y <= bounds.min_y && min_y > bounds.min_y - 10.0);
+
+        // a 20m first band lands on the same 10m grid
+        let coarse =
+            TileGrid::new(&item(), &band_set(Band::Nir08, 20.0), SpectralIndex::Ndmi, &aoi)
+                .unwrap();
+        assert_eq!(coarse, grid);
+    }
+
+    #[test]
+    fn tile_grid_errors() {
+        let aoi = Aoi::from_bbox([-123.0, 37.9, -122.99, 37.91]);
+        let bands = band_set(Band::Nir, 10.0);
+
+        let mut untiled = item();
+        untiled.properties.grid_code = None;
+        assert!(TileGrid::new(&untiled, &bands, SpectralIndex::Ndvi, &aoi).is_err());
+
+        let far = Aoi::from_bbox([-120.0, 37.9, -119.99, 37.91]);
+        let err = TileGrid::new(&item(), &bands, SpectralIndex::Ndvi, &far).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "AOI does not overlap tile MGRS-10SEG of S2B_10SEG_20240627_0_L2A"
+        );
+
+        let mut rotated = bands.clone();
+        rotated.bands.get_mut(&Band::Nir).unwrap().transform[1] = 1.0;
+        assert!(TileGrid::new(&item(), &rotated, SpectralIndex::Ndvi, &aoi).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::aoi::Coverage;
+use crate::bindings::host;
+use crate::change::Thresholds;
+use crate::cog_writer::SampleFormat;
+use crate::composite::Method;
+use crate::mask::{SclMask, DEFAULT_SCL_EXCLUDE};
+use crate::pinner::{Pinner, DEFAULT_KUBO_URL, DEFAULT_LIGHTHOUSE_URL, DEFAULT_PINATA_URL};
+use crate::stac::{PageLimits, DEFAULT_COLLECTION, DEFAULT_STAC_API_URL};
+use anyhow::Result;
+
+/// Prefix WAVS requires on environment variables exposed to components
+pub const ENV_PREFIX: &str = "WAVS_ENV_";
+
+/// A named setting resolved from the service config, the environment, or a default
+///
+/// Lookup order:
+/// 1. `host::config_var(name)` from the service's workflow component config
+/// 2. The `WAVS_ENV_<NAME>` environment variable (name upper-cased)
+/// 3. The built-in default, if any
+///
+/// Empty values are treated as unset.
+pub struct ConfigKey {
+    pub name: &'static str,
+    pub default: Option<&'static str>,
+}
+
+/// STAC API search endpoint
+pub const STAC_API_URL: ConfigKey =
+    ConfigKey { name: "stac_api_url", default: Some(DEFAULT_STAC_API_URL) };
+
+/// Pinning backend files are uploaded through: `lighthouse`, `pinata`, `kubo` or `local`
+pub const IPFS_PINNER: ConfigKey = ConfigKey { name: "ipfs_pinner", default: Some("lighthouse") };
+
+/// Upload endpoint of the `lighthouse` pinner
+pub const LIGHTHOUSE_API_URL: ConfigKey =
+    ConfigKey { name: "lighthouse_api_url", default: Some(DEFAULT_LIGHTHOUSE_URL) };
+
+/// Upload endpoint of the `pinata` pinner
+pub const PINATA_API_URL: ConfigKey =
+    ConfigKey { name: "pinata_api_url", default: Some(DEFAULT_PINATA_URL) };
+
+/// `add` endpoint of the `kubo` pinner
+pub const KUBO_API_URL: ConfigKey =
+    ConfigKey { name: "kubo_api_url", default: Some(DEFAULT_KUBO_URL) };
+
+/// CID version the IPFS endpoint imports files with: `0` (dag-pb leaves) or `1` (raw leaves)
+pub const IPFS_CID_VERSION: ConfigKey = ConfigKey { name: "ipfs_cid_version", default: Some("0") };
+
+/// STAC collection searched when the request does not name one
+pub const COLLECTION: ConfigKey =
+    ConfigKey { name: "collection", default: Some(DEFAULT_COLLECTION) };
+
+/// SCL classes masked out before computing statistics, e.g. `1,3,8,9,10,11`
+pub const SCL_EXCLUDE: ConfigKey =
+    ConfigKey { name: "scl_exclude", default: Some(DEFAULT_SCL_EXCLUDE) };
+
+/// Most STAC result pages fetched per search
+pub const STAC_MAX_PAGES: ConfigKey = ConfigKey { name: "stac_max_pages", default: Some("10") };
+
+/// Most STAC items kept per search; later items in the server's order are dropped
+pub const STAC_MAX_ITEMS: ConfigKey = ConfigKey { name: "stac_max_items", default: Some("500") };
+
+/// How partially covered pixels count towards the AOI: `center` or `area`
+pub const AOI_COVERAGE: ConfigKey = ConfigKey { name: "aoi_coverage", default: Some("center") };
+
+/// Index increase above which a pixel counts as improved in change detection
+pub const CHANGE_GAIN_THRESHOLD: ConfigKey =
+    ConfigKey { name: "change_gain_threshold", default: Some("0.05") };
+
+/// Index decrease above which a pixel counts as degraded in change detection
+pub const CHANGE_LOSS_THRESHOLD: ConfigKey =
+    ConfigKey { name: "change_loss_threshold", default: Some("0.05") };
+
+/// Per-pixel rule of the composite and change detection modes: `median` or `max_ndvi`
+pub const COMPOSITE_METHOD: ConfigKey =
+    ConfigKey { name: "composite_method", default: Some("median") };
+
+/// Sample format of the uploaded index rasters: `float32`, or `int16` scaled by 1e-4
+pub const COG_SAMPLE_FORMAT: ConfigKey =
+    ConfigKey { name: "cog_sample_format", default: Some("float32") };
+
+/// Longest side of the PNG preview, in pixels
+pub const PREVIEW_MAX_DIM: ConfigKey = ConfigKey { name: "preview_max_dim", default: Some("512") };
+
+/// Whether the AOI outline is drawn on the PNG preview: `true` or `false`
+pub const PREVIEW_OUTLINE: ConfigKey = ConfigKey { name: "preview_outline", default: Some("true") };
+
+/// Comma-separated IPFS gateways tried in order when fetching: base URLs of path gateways,
+/// or subdomain gateway URLs with a `{cid}` placeholder; read by [`crate::ipfs::fetch_from_ipfs`]
+pub const IPFS_GATEWAYS: ConfigKey = ConfigKey {
+    name: "ipfs_gateways",
+    default: Some(
+        "https://ipfs.io,https://{cid}.ipfs.dweb.link,https://gateway.lighthouse.storage",
+    ),
+};
+
+/// Seconds each gateway has to deliver the content before the next one is tried
+pub const IPFS_GATEWAY_TIMEOUT: ConfigKey =
+    ConfigKey { name: "ipfs_gateway_timeout", default: Some("30") };
+
+/// Directory the `local` pinner keeps files in, by CID; memory only when unset
+pub const IPFS_LOCAL_DIR: ConfigKey = ConfigKey { name: "ipfs_local_dir", default: None };
+
+/// Lighthouse API key; secret, so it is only read from the environment
+pub const LIGHTHOUSE_API_KEY: ConfigKey = ConfigKey { name: "lighthouse_api_key", default: None };
+
+/// Pinata JWT; secret, so it is only read from the environment
+pub const PINATA_JWT: ConfigKey = ConfigKey { name: "pinata_jwt", default: None };
+
+/// `Authorization` header value for a Kubo node behind an authenticating proxy, e.g.
+/// `Basic ...`; secret, so it is only read from the environment
+pub const KUBO_API_AUTH: ConfigKey = ConfigKey { name: "kubo_api_auth", default: None };
+
+impl ConfigKey {
+    /// Name of the environment variable backing this key, e.g. `WAVS_ENV_STAC_API_URL`
+    pub fn env_var(&self) -> String {
+        format!("{}{}", ENV_PREFIX, self.name.to_uppercase())
+    }
+
+    /// Resolves the key using the full lookup order
+    pub fn get(&self) -> Result<String> {
+        host::config_var(self.name)
+            .filter(|v| !v.is_empty())
+            .or_else(|| self.env_value())
+            .or_else(|| self.default.map(str::to_string))
+            .ok_or_else(|| self.missing())
+    }
+
+    /// Resolves the key and parses its value
+    pub fn parse<T>(&self) -> Result<T>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let value = self.get()?;
+        value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid `{}` value `{}`: {}", self.name, value, e))
+    }
+
+    /// Resolves the key from the environment only
+    ///
+    /// Service config is readable by anyone who can see the service definition,
+    /// so secrets must never be looked up there.
+    pub fn get_secret(&self) -> Result<String> {
+        self.env_value().ok_or_else(|| self.missing())
+    }
+
+    fn env_value(&self) -> Option<String> {
+        std::env::var(self.env_var()).ok().filter(|v| !v.is_empty())
+    }
+
+    fn missing(&self) -> anyhow::Error {
+        anyhow::anyhow!(
+            "Missing required config `{}`: set the `{}` config var or the `{}` environment variable",
+            self.name,
+            self.name,
+            self.env_var()
+        )
+    }
+}
+
+/// Settings resolved once per trigger
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub stac_api_url: String,
+    pub pinner: Pinner,
+    pub collection: String,
+    pub scl_mask: SclMask,
+    pub coverage: Coverage,
+    pub page_limits: PageLimits,
+    pub thresholds: Thresholds,
+    pub composite_method: Method,
+    pub cog_format: SampleFormat,
+    pub preview_max_dim: usize,
+    pub preview_outline: bool,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        Ok(Self {
+            stac_api_url: STAC_API_URL.get()?,
+            pinner: Pinner::load()?,
+            collection: COLLECTION.get()?,
+            scl_mask: SclMask::parse(&SCL_EXCLUDE.get()?)?,
+            coverage: AOI_COVERAGE.get()?.parse()?,
+            page_limits: PageLimits {
+                max_pages: STAC_MAX_PAGES.parse()?,
+                max_items: STAC_MAX_ITEMS.parse()?,
+            },
+            thresholds: Thresholds {
+                gain: CHANGE_GAIN_THRESHOLD.parse()?,
+                loss: CHANGE_LOSS_THRESHOLD.parse()?,
+            },
+            composite_method: COMPOSITE_METHOD.parse()?,
+            cog_format: COG_SAMPLE_FORMAT.parse()?,
+            preview_max_dim: PREVIEW_MAX_DIM.parse()?,
+            preview_outline: PREVIEW_OUTLINE.parse()?,
+        })
+    }
+}
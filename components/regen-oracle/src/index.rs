@@ -0,0 +1,404 @@
+use crate::bands::{Band, BandAsset};
+use crate::raster::Raster;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Converts a band's digital numbers into surface reflectance
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rescale {
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl From<&BandAsset> for Rescale {
+    fn from(asset: &BandAsset) -> Self {
+        Self { scale: asset.scale, offset: asset.offset }
+    }
+}
+
+impl Rescale {
+    /// Reflectance of `dn`
+    ///
+    /// The L2A offset (-0.1 since processing baseline 04.00) drives very dark pixels,
+    /// such as water and shadow, below zero.
+    pub fn apply(&self, dn: u16) -> f64 {
+        f64::from(dn) * self.scale + self.offset
+    }
+}
+
+/// Largest number of bands an index combines
+const MAX_INPUTS: usize = 3;
+
+/// Spectral indices the oracle can compute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpectralIndex {
+    /// Normalized difference vegetation index, `(nir - red) / (nir + red)`
+    #[default]
+    Ndvi,
+    /// Enhanced vegetation index, `2.5 (nir - red) / (nir + 6 red - 7.5 blue + 1)`
+    Evi,
+    /// Soil-adjusted vegetation index with `L = 0.5`, `1.5 (nir - red) / (nir + red + 0.5)`
+    Savi,
+    /// McFeeters' normalized difference water index, `(green - nir) / (green + nir)`
+    Ndwi,
+    /// Normalized difference moisture index, `(nir08 - swir16) / (nir08 + swir16)`
+    Ndmi,
+    /// Normalized burn ratio, `(nir08 - swir22) / (nir08 + swir22)`
+    Nbr,
+    /// Normalized difference red edge index, `(nir - rededge1) / (nir + rededge1)`
+    Ndre,
+}
+
+impl SpectralIndex {
+    pub const ALL: [SpectralIndex; 7] = [
+        SpectralIndex::Ndvi,
+        SpectralIndex::Evi,
+        SpectralIndex::Savi,
+        SpectralIndex::Ndwi,
+        SpectralIndex::Ndmi,
+        SpectralIndex::Nbr,
+        SpectralIndex::Ndre,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpectralIndex::Ndvi => "ndvi",
+            SpectralIndex::Evi => "evi",
+            SpectralIndex::Savi => "savi",
+            SpectralIndex::Ndwi => "ndwi",
+            SpectralIndex::Ndmi => "ndmi",
+            SpectralIndex::Nbr => "nbr",
+            SpectralIndex::Ndre => "ndre",
+        }
+    }
+
+    /// Bands the index is computed from, in the order [`SpectralIndex::compute`] expects them
+    pub fn bands(&self) -> &'static [Band] {
+        match self {
+            SpectralIndex::Ndvi | SpectralIndex::Savi => &[Band::Nir, Band::Red],
+            SpectralIndex::Evi => &[Band::Nir, Band::Red, Band::Blue],
+            SpectralIndex::Ndwi => &[Band::Green, Band::Nir],
+            SpectralIndex::Ndmi => &[Band::Nir08, Band::Swir16],
+            SpectralIndex::Nbr => &[Band::Nir08, Band::Swir22],
+            SpectralIndex::Ndre => &[Band::Nir, Band::RedEdge1],
+        }
+    }
+
+    /// Index value of one pixel from the reflectances of [`SpectralIndex::bands`]
+    ///
+    /// NaN where the index is undefined, e.g. a normalized difference of two zeros.
+    pub fn value(&self, r: &[f64]) -> f64 {
+        match self {
+            SpectralIndex::Ndvi
+            | SpectralIndex::Ndwi
+            | SpectralIndex::Ndmi
+            | SpectralIndex::Nbr
+            | SpectralIndex::Ndre => normalized_difference(r[0], r[1]),
+            SpectralIndex::Evi => {
+                let (nir, red, blue) = (r[0], r[1], r[2]);
+                let denominator = nir + 6.0 * red - 7.5 * blue + 1.0;
+                if denominator == 0.0 {
+                    f64::NAN
+                } else {
+                    2.5 * (nir - red) / denominator
+                }
+            }
+            SpectralIndex::Savi => {
+                let (nir, red) = (r[0], r[1]);
+                1.5 * (nir - red) / (nir + red + 0.5)
+            }
+        }
+    }
+
+    /// Per-pixel index over surface reflectance
+    ///
+    /// `inputs` holds one window per band of [`SpectralIndex::bands`], in that order,
+    /// all on the same grid. Pixels that are nodata in any band, that have a negative
+    /// reflectance in any band, or where the index is undefined, are NaN in the
+    /// result. Negative reflectances carry no usable signal, and an index computed
+    /// from them leaves its range, e.g. an NDVI above 1.
+    pub fn compute(&self, inputs: &[(&Raster<u16>, Rescale)]) -> Result<Raster<f32>> {
+        let bands = self.bands();
+        if inputs.len() != bands.len() {
+            return Err(anyhow::anyhow!(
+                "{} needs {} bands, got {}",
+                self,
+                bands.len(),
+                inputs.len()
+            ));
+        }
+        let (first, _) = inputs[0];
+        for (band, (raster, _)) in bands.iter().zip(inputs) {
+            if raster.width != first.width
+                || raster.height != first.height
+                || raster.transform != first.transform
+            {
+                return Err(anyhow::anyhow!(
+                    "{} ({}x{} at {:?}) and {} ({}x{} at {:?}) windows are not aligned",
+                    band,
+                    raster.width,
+                    raster.height,
+                    raster.transform.0,
+                    bands[0],
+                    first.width,
+                    first.height,
+                    first.transform.0
+                ));
+            }
+        }
+
+        let mut reflectances = [0.0; MAX_INPUTS];
+        let data = (0..first.data.len())
+            .map(|i| {
+                for (r, (raster, rescale)) in reflectances.iter_mut().zip(inputs) {
+                    let dn = raster.data[i];
+                    if Some(dn) == raster.nodata {
+                        return f32::NAN;
+                    }
+                    *r = rescale.apply(dn);
+                    if *r < 0.0 {
+                        return f32::NAN;
+                    }
+                }
+                self.value(&reflectances[..inputs.len()]) as f32
+            })
+            .collect();
+
+        Ok(Raster {
+            width: first.width,
+            height: first.height,
+            data,
+            transform: first.transform,
+            nodata: Some(f32::NAN),
+        })
+    }
+}
+
+impl fmt::Display for SpectralIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for SpectralIndex {
+    type Err = anyhow::Error;
+
+    /// Parses an index name case-insensitively, e.g. `ndvi` or `NBR`
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|index| index.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow::anyhow!("Unknown spectral index `{}`", s))
+    }
+}
+
+/// `(a - b) / (a + b)`, NaN when the sum is not positive
+fn normalized_difference(a: f64, b: f64) -> f64 {
+    let sum = a + b;
+    if sum > 0.0 {
+        (a - b) / sum
+    } else {
+        f64::NAN
+    }
+}
+
+/// Summary statistics of the valid pixels of a raster
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub mean: f64,
+    pub median: f64,
+    /// Population standard deviation
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub valid_pixels: u64,
+    /// Pixels within the AOI
+    pub total_pixels: u64,
+    /// Weight of the valid pixels over the weight of all pixels within the AOI
+    pub valid_fraction: f64,
+}
+
+impl Stats {
+    /// Statistics over the non-NaN values of `raster`, `None` when there are none
+    pub fn of(raster: &Raster<f32>) -> Option<Self> {
+        Self::weighted(raster, &vec![1.0; raster.data.len()])
+    }
+
+    /// Statistics over the non-NaN values of `raster`, each weighted by `weights`
+    ///
+    /// Pixels with zero weight lie outside the AOI and are ignored entirely, including
+    /// in the pixel counts. With uniform weights the median is the usual midpoint of
+    /// the two central values.
+    ///
+    /// Operators must agree bit-for-bit, so every reduction runs sequentially in
+    /// pixel order in `f64`, and the median sorts with `f64::total_cmp`.
+    pub fn weighted(raster: &Raster<f32>, weights: &[f32]) -> Option<Self> {
+        let mut values: Vec<(f64, f64)> = Vec::new();
+        let (mut total_pixels, mut total_weight) = (0u64, 0.0);
+        for (&value, &weight) in raster.data.iter().zip(weights) {
+            if weight <= 0.0 {
+                continue;
+            }
+            total_pixels += 1;
+            total_weight += f64::from(weight);
+            if !value.is_nan() {
+                values.push((f64::from(value), f64::from(weight)));
+            }
+        }
+        if values.is_empty() {
+            return None;
+        }
+
+        let weight = values.iter().map(|(_, w)| w).sum::<f64>();
+        let mean = values.iter().map(|(v, w)| v * w).sum::<f64>() / weight;
+        let variance =
+            values.iter().map(|(v, w)| w * (v - mean) * (v - mean)).sum::<f64>() / weight;
+
+        values.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let half = weight / 2.0;
+        let mut cumulative = 0.0;
+        let mut median = values[values.len() - 1].0;
+        for (i, &(value, w)) in values.iter().enumerate() {
+            cumulative += w;
+            if cumulative > half {
+                median = value;
+                break;
+            }
+            if cumulative == half {
+                median = values.get(i + 1).map_or(value, |next| (value + next.0) / 2.0);
+                break;
+            }
+        }
+
+        Some(Self {
+            mean,
+            median,
+            stddev: variance.sqrt(),
+            min: values[0].0,
+            max: values[values.len() - 1].0,
+            valid_pixels: values.len() as u64,
+            total_pixels,
+            valid_fraction: weight / total_weight,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::GeoTransform;
+
+    const L2A: Rescale = Rescale { scale: 1e-4, offset: -0.1 };
+
+    fn raster<T>(data: Vec<T>, nodata: Option<T>) -> Raster<T> {
+        Raster {
+            width: data.len(),
+            height: 1,
+            data,
+            transform: GeoTransform([10.0, 0.0, 0.0, 0.0, -10.0, 0.0]),
+            nodata,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        // f32 rasters carry about 7 significant digits
+        assert!((actual - expected).abs() < 1e-7, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn formulas() {
+        let (blue, green, red, nir, rededge, nir08, swir16, swir22) =
+            (0.04, 0.07, 0.05, 0.35, 0.12, 0.33, 0.18, 0.09);
+        let value = |index: SpectralIndex, bands: &[f64]| index.value(bands);
+
+        assert_close(value(SpectralIndex::Ndvi, &[nir, red]), 0.3 / 0.4);
+        assert_close(value(SpectralIndex::Evi, &[nir, red, blue]), 2.5 * 0.3 / 1.35);
+        assert_close(value(SpectralIndex::Savi, &[nir, red]), 1.5 * 0.3 / 0.9);
+        assert_close(value(SpectralIndex::Ndwi, &[green, nir]), -0.28 / 0.42);
+        assert_close(value(SpectralIndex::Ndmi, &[nir08, swir16]), 0.15 / 0.51);
+        assert_close(value(SpectralIndex::Nbr, &[nir08, swir22]), 0.24 / 0.42);
+        assert_close(value(SpectralIndex::Ndre, &[nir, rededge]), 0.23 / 0.47);
+
+        assert!(value(SpectralIndex::Ndvi, &[0.0, 0.0]).is_nan());
+        assert!(value(SpectralIndex::Evi, &[0.0, 0.0, 0.4 / 3.0]).is_nan());
+        for index in SpectralIndex::ALL {
+            assert_eq!(index.name().parse::<SpectralIndex>().unwrap(), index);
+            assert!(index.bands().len() <= MAX_INPUTS);
+        }
+    }
+
+    #[test]
+    fn computes_over_reflectance() {
+        let nir = raster(vec![4500u16, 4500, 0, 2000, 1500], Some(0));
+        let red = raster(vec![1500u16, 1500, 1500, 0, 900], Some(0));
+        let ndvi = SpectralIndex::Ndvi.compute(&[(&nir, L2A), (&red, L2A)]).unwrap();
+
+        assert_close(f64::from(ndvi.data[0]), 0.75);
+        // nodata in either band
+        assert!(ndvi.data[2].is_nan() && ndvi.data[3].is_nan());
+        // red below the offset is a negative reflectance, masked rather than NDVI > 1
+        assert!(ndvi.data[4].is_nan());
+        assert_eq!(ndvi.nodata.map(f32::is_nan), Some(true));
+
+        let wrong_count = SpectralIndex::Evi.compute(&[(&nir, L2A), (&red, L2A)]);
+        assert!(wrong_count.is_err());
+        let short = raster(vec![1u16], None);
+        assert!(SpectralIndex::Ndvi.compute(&[(&nir, L2A), (&short, L2A)]).is_err());
+    }
+
+    #[test]
+    fn uniform_stats() {
+        let stats = Stats::of(&raster(vec![0.5, f32::NAN, 0.1, 0.3, 0.9], None)).unwrap();
+        assert_close(stats.mean, 0.45);
+        assert_close(stats.median, 0.4);
+        assert_eq!((stats.min, stats.max), (f64::from(0.1f32), f64::from(0.9f32)));
+        assert_eq!((stats.valid_pixels, stats.total_pixels), (4, 5));
+        assert_close(stats.valid_fraction, 0.8);
+
+        let odd = Stats::of(&raster(vec![0.5, 0.1, 0.3], None)).unwrap();
+        assert_eq!(odd.median, f64::from(0.3f32));
+        let single = Stats::of(&raster(vec![0.25], None)).unwrap();
+        assert_eq!((single.mean, single.median, single.stddev), (0.25, 0.25, 0.0));
+        assert_eq!(Stats::of(&raster(vec![f32::NAN; 3], None)), None);
+    }
+
+    #[test]
+    fn weighted_stats() {
+        let values = raster(vec![0.0, 1.0, 2.0, 3.0, f32::NAN], None);
+
+        // half the weight on the last valid value puts the median at the cut
+        let stats = Stats::weighted(&values, &[1.0, 0.5, 0.5, 2.0, 1.0]).unwrap();
+        assert_close(stats.median, 2.5);
+        assert_close(stats.mean, (0.5 + 1.0 + 6.0) / 4.0);
+        let variance = (1.875f64.powi(2)
+            + 0.5 * 0.875f64.powi(2)
+            + 0.5 * 0.125f64.powi(2)
+            + 2.0 * 1.125f64.powi(2))
+            / 4.0;
+        assert_close(stats.stddev, variance.sqrt());
+        assert_close(stats.valid_fraction, 4.0 / 5.0);
+
+        // the median is the value where the cumulative weight passes half
+        let stats = Stats::weighted(&values, &[0.25, 0.25, 0.25, 1.0, 0.0]).unwrap();
+        assert_eq!(stats.median, 3.0);
+
+        // zero weights are outside the AOI and drop out of every count
+        let stats = Stats::weighted(&values, &[0.0, 0.0, 1.0, 1.0, 0.0]).unwrap();
+        assert_eq!((stats.valid_pixels, stats.total_pixels), (2, 2));
+        assert_eq!((stats.min, stats.median, stats.valid_fraction), (2.0, 2.5, 1.0));
+        assert_eq!(Stats::weighted(&values, &[0.0; 5]), None);
+    }
+
+    #[test]
+    fn stats_are_deterministic() {
+        let data: Vec<f32> = (0..10_001).map(|i| ((i * 7919) % 10_007) as f32 / 10_007.0).collect();
+        let weights: Vec<f32> = (0..data.len()).map(|i| 0.25 + (i % 4) as f32 * 0.25).collect();
+        let first = Stats::weighted(&raster(data.clone(), None), &weights).unwrap();
+        let again = Stats::weighted(&raster(data, None), &weights).unwrap();
+        assert_eq!(first.mean.to_bits(), again.mean.to_bits());
+        assert_eq!(first.stddev.to_bits(), again.stddev.to_bits());
+        assert_eq!(first.median.to_bits(), again.median.to_bits());
+    }
+}
@@ -1,159 +1,368 @@
+use crate::cid::{self, Cid, CidVersion, UnixFsHasher};
+use crate::config::{IPFS_GATEWAYS, IPFS_GATEWAY_TIMEOUT};
+use crate::pinner::{IpfsPinner, Pinner};
 use anyhow::Result;
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-    fs::File,
-    io::{Read, Write},
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::Duration,
 };
-use wstd::http::{IntoBody, Request};
+use wstd::future::FutureExt;
+use wstd::http::{Body, Request};
 use wstd::io::AsyncRead;
 
-/// Uploads a file using multipart request to IPFS
-async fn upload_to_ipfs(file_path: &str, ipfs_url: &str) -> Result<String> {
-    let api_key = std::env::var("WAVS_ENV_LIGHTHOUSE_API_KEY")
-        .map_err(|e| anyhow::anyhow!("Failed to get API key: {}", e))?;
-
-    eprintln!("Uploading file to IPFS: {}", file_path);
-
-    let mut file = File::open(file_path)?;
-    let mut file_bytes = Vec::new();
-    file.read_to_end(&mut file_bytes)?;
-
-    // define multipart request boundary
-    let boundary = "----RustBoundary";
-
-    // construct the body
-    let body = format!(
-        "--{}\r\n\
-        Content-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
-        Content-Type: application/octet-stream\r\n\r\n",
-        boundary, file_path
-    );
-
-    let mut request_body = body.into_bytes();
-    request_body.extend_from_slice(&file_bytes);
-    request_body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
-
-    let request = Request::post(ipfs_url)
-        .header("Authorization", &format!("Bearer {}", api_key))
-        .header("Content-Type", &format!("multipart/form-data; boundary={}", boundary))
-        .body(request_body.into_body())?;
-
-    let mut response = wstd::http::Client::new().send(request).await?;
-
-    if response.status().is_success() {
-        let mut body_buf = Vec::new();
-        response.body_mut().read_to_end(&mut body_buf).await?;
-
-        // Log the raw response for debugging
-        let response_str = std::str::from_utf8(&body_buf)
-            .map_err(|e| anyhow::anyhow!("Failed to convert response to string: {}", e))?;
-        eprintln!("IPFS API Response: {}", response_str);
-
-        // Parse using Lighthouse's response format (capitalized fields)
-        #[allow(non_snake_case)]
-        #[derive(Debug, Deserialize)]
-        struct LighthouseResponse {
-            Hash: String,
-        }
-
-        let hash = match serde_json::from_slice::<LighthouseResponse>(&body_buf) {
-            Ok(resp) => resp.Hash,
-            Err(e) => {
-                // Simple fallback - just look for the hash in the response text
-                eprintln!("Failed to parse response: {}", e);
-
-                if let Some(start) = response_str.find("\"Hash\":\"") {
-                    if let Some(end) = response_str[start + 8..].find("\"") {
-                        return Ok(response_str[start + 8..start + 8 + end].to_string());
-                    }
-                }
-
-                // If that fails too, try lowercase
-                if let Some(start) = response_str.find("\"hash\":\"") {
-                    if let Some(end) = response_str[start + 8..].find("\"") {
-                        return Ok(response_str[start + 8..start + 8 + end].to_string());
-                    }
-                }
-
-                return Err(anyhow::anyhow!(
-                    "Could not extract hash from response: {}",
-                    response_str
-                ));
-            }
-        };
+/// Largest file fetched from a gateway; the whole file is held in memory to be hashed
+const MAX_FETCH_BYTES: usize = 64 * 1024 * 1024;
+
+/// Largest directory block fetched from a gateway; IPFS peers exchange blocks of at
+/// most 2 MiB
+const MAX_BLOCK_BYTES: usize = 2 * 1024 * 1024;
+
+/// Outcome of a streamed upload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamedUpload {
+    /// `ipfs://CID/filename` URI of the uploaded file
+    pub uri: String,
+    pub cid: String,
+    /// Number of payload bytes relayed from the source (excluding multipart framing)
+    pub bytes: u64,
+}
 
-        // Return the hash directly
-        Ok(hash)
-    } else {
-        let mut body_buf = Vec::new();
-        response.body_mut().read_to_end(&mut body_buf).await?;
-        let error_body = std::str::from_utf8(&body_buf).unwrap_or("unable to read error body");
-        Err(anyhow::anyhow!(
-            "Failed to upload to IPFS. Status: {:?}, Body: {}",
-            response.status(),
-            error_body
-        ))
+/// Streams the body of `source_url` into an IPFS upload and returns its URI
+///
+/// The source response is relayed into the multipart request body chunk by chunk,
+/// so memory use stays bounded no matter how large the file is. Nothing touches
+/// the filesystem. When the source declares a `Content-Length`, the upload is sent
+/// with an exact length and the relayed byte count is checked against it. The CID
+/// is computed from the relayed bytes on the way through and checked against the
+/// one the pinning service reports.
+pub async fn upload_url_to_ipfs(
+    source_url: &str,
+    filename: &str,
+    pinner: &Pinner,
+) -> Result<StreamedUpload> {
+    let client = wstd::http::Client::new();
+
+    eprintln!("Streaming {} to IPFS as {}", source_url, filename);
+
+    let source = client.send(Request::get(source_url).body(wstd::io::empty())?).await?;
+    if !source.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Failed to fetch {}. Status: {:?}",
+            source_url,
+            source.status()
+        ));
     }
-}
 
-/// Uploads JSON data directly to IPFS and returns the CID
-pub async fn upload_json_to_ipfs(json_data: &str, ipfs_url: &str) -> Result<String> {
-    // Create a temporary file to store the JSON data
-    let filename = "nft_metadata.json".to_string();
-    let temp_path = format!("/tmp/{}", filename);
+    let source_len = source.body().len();
+    let version = pinner.cid_version();
+    let reader = HashingReader::new(source.into_body(), UnixFsHasher::new(version));
+    let relayed = reader.relayed.clone();
+    let hasher = reader.hasher.clone();
+
+    let added = pinner.add_stream(filename, reader, source_len).await;
+
+    let bytes = relayed.get();
+    if let Some(expected) = source_len {
+        if bytes != expected as u64 {
+            return Err(anyhow::anyhow!(
+                "Short read from {}: relayed {} of {} bytes",
+                source_url,
+                bytes,
+                expected
+            ));
+        }
+    }
 
-    eprint!("Temp path {}", temp_path);
+    let local = hasher.replace(UnixFsHasher::new(version)).finish();
+    eprintln!("Local CID of {}: {}", filename, local);
+    let cid = added?;
+    cid::verify(&local, &cid)?;
+    eprintln!("Streamed {} bytes from {} to {}", bytes, source_url, cid);
 
-    // Ensure the /tmp directory exists
-    std::fs::create_dir_all("/tmp")
-        .map_err(|e| anyhow::anyhow!("Failed to create /tmp directory: {}", e))?;
+    Ok(StreamedUpload { uri: get_ipfs_url(&cid, Some(filename)), cid, bytes })
+}
 
-    // Write JSON to temporary file
-    let mut file = File::create(&temp_path)?;
-    file.write_all(json_data.as_bytes())?;
+/// Reader that counts and hashes the bytes read through it
+struct HashingReader<R> {
+    source: R,
+    /// Payload bytes read from `source` so far, shared so it can be read after the
+    /// pinner consumes the reader
+    relayed: Rc<Cell<u64>>,
+    /// CID of the payload read so far, shared the same way
+    hasher: Rc<RefCell<UnixFsHasher>>,
+}
 
-    // Upload the file
-    let hash = upload_to_ipfs(&temp_path, ipfs_url).await?;
+impl<R: AsyncRead> HashingReader<R> {
+    fn new(source: R, hasher: UnixFsHasher) -> Self {
+        Self { source, relayed: Rc::new(Cell::new(0)), hasher: Rc::new(RefCell::new(hasher)) }
+    }
+}
 
-    // Clean up the temporary file
-    delete_file(&temp_path)?;
+impl<R: AsyncRead> AsyncRead for HashingReader<R> {
+    async fn read(&mut self, buf: &mut [u8]) -> wstd::io::Result<usize> {
+        let n = self.source.read(buf).await?;
+        self.relayed.set(self.relayed.get() + n as u64);
+        self.hasher.borrow_mut().update(&buf[..n]);
+        Ok(n)
+    }
+}
 
-    // Return the IPFS URI
-    Ok(get_ipfs_url(&hash, Some(&filename)))
+/// Uploads JSON data directly to IPFS under `filename` and returns the IPFS URI
+pub async fn upload_json_to_ipfs(
+    json_data: &str,
+    filename: &str,
+    pinner: &Pinner,
+) -> Result<String> {
+    upload_bytes_to_ipfs(json_data.as_bytes(), filename, pinner).await
 }
 
 /// Uploads an image to IPFS and returns the CID
 pub async fn upload_image_to_ipfs(
     image_data: &[u8],
     filename: &str,
-    ipfs_url: &str,
+    pinner: &Pinner,
 ) -> Result<String> {
-    // Create a temporary file to store the image data
-    let temp_path = format!("/tmp/{}", filename);
+    upload_bytes_to_ipfs(image_data, filename, pinner).await
+}
+
+/// Uploads an in-memory file to IPFS under `filename` and returns the IPFS URI
+///
+/// The file's CID is computed locally first, and the upload fails if the pinning
+/// service reports a different one.
+pub async fn upload_bytes_to_ipfs(data: &[u8], filename: &str, pinner: &Pinner) -> Result<String> {
+    eprintln!("Uploading {} ({} bytes) to IPFS", filename, data.len());
 
-    // Ensure the /tmp directory exists
-    std::fs::create_dir_all("/tmp")
-        .map_err(|e| anyhow::anyhow!("Failed to create /tmp directory: {}", e))?;
+    let local = cid::compute(data, pinner.cid_version());
+    eprintln!("Local CID of {}: {}", filename, local);
 
-    // Write image data to temporary file
-    let mut file = File::create(&temp_path)?;
-    file.write_all(image_data)?;
+    let hash = pinner.add(filename, data).await?;
+    cid::verify(&local, &hash)?;
+    Ok(get_ipfs_url(&hash, Some(filename)))
+}
 
-    // Upload the file
-    let hash = upload_to_ipfs(&temp_path, ipfs_url).await?;
+/// Uploads `files` as one directory and returns the directory's CID
+///
+/// `name` only labels the upload; the CID depends on the files' names and content.
+/// The CID is computed locally first, and the upload fails if the pinning service
+/// reports a different one. Each file is then reachable as `ipfs://CID/name`.
+pub async fn upload_directory_to_ipfs(
+    name: &str,
+    files: &[(&str, &[u8])],
+    pinner: &Pinner,
+) -> Result<String> {
+    eprintln!("Uploading directory {} ({} files) to IPFS", name, files.len());
 
-    // Clean up the temporary file
-    delete_file(&temp_path)?;
+    let local = cid::directory(files, pinner.cid_version())?;
+    eprintln!("Local CID of {}: {}", name, local);
 
-    // Return the IPFS URI
-    Ok(get_ipfs_url(&hash, Some(filename)))
+    let hash = pinner.add_directory(name, files).await?;
+    cid::verify(&local, &hash)?;
+    Ok(hash)
+}
+
+/// An IPFS HTTP gateway
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Gateway {
+    /// Serves content under `{base}/ipfs/{cid}`
+    Path(String),
+    /// Serves each CID from its own origin; `{cid}` in the URL stands for the CIDv1
+    /// in base32, e.g. `https://{cid}.ipfs.dweb.link`
+    Subdomain(String),
+}
+
+impl Gateway {
+    /// URL of `cid` on this gateway
+    pub fn url(&self, cid: &Cid) -> String {
+        match self {
+            Gateway::Path(base) => format!("{}/ipfs/{}", base, cid),
+            // host names are case-insensitive, so CIDv0 is spelled as the equivalent CIDv1
+            Gateway::Subdomain(template) => {
+                template.replace("{cid}", &Cid { version: CidVersion::V1, ..*cid }.to_string())
+            }
+        }
+    }
+
+    /// URL of the single block `cid` on this gateway, as the trustless gateway API
+    /// serves it
+    pub fn block_url(&self, cid: &Cid) -> String {
+        format!("{}?format=raw", self.url(cid))
+    }
+}
+
+impl std::str::FromStr for Gateway {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if !s.starts_with("https://") && !s.starts_with("http://") {
+            return Err(anyhow::anyhow!("Gateway `{}` is not an http(s) URL", s));
+        }
+        if s.contains("{cid}") {
+            Ok(Gateway::Subdomain(s.to_string()))
+        } else {
+            Ok(Gateway::Path(s.trim_end_matches('/').to_string()))
+        }
+    }
+}
+
+/// Gateways tried in order when fetching content, and how long each may take
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gateways {
+    pub gateways: Vec<Gateway>,
+    pub timeout: Duration,
+}
+
+impl Gateways {
+    /// Gateways from `ipfs_gateways`, each given `ipfs_gateway_timeout` seconds
+    pub fn load() -> Result<Self> {
+        Self::parse(&IPFS_GATEWAYS.get()?, Duration::from_secs(IPFS_GATEWAY_TIMEOUT.parse()?))
+    }
+
+    /// Parses a comma-separated gateway list
+    pub fn parse(list: &str, timeout: Duration) -> Result<Self> {
+        let gateways = list
+            .split(',')
+            .map(str::trim)
+            .filter(|gateway| !gateway.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<Gateway>>>()?;
+        if gateways.is_empty() {
+            return Err(anyhow::anyhow!("No IPFS gateway configured"));
+        }
+        Ok(Self { gateways, timeout })
+    }
+}
+
+/// What a gateway is asked for, and how its answer is checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Content {
+    /// A whole file, hashed again as `ipfs add` imports it
+    File,
+    /// The raw dag-pb block of a directory, hashed as is
+    DirectoryBlock,
+}
+
+impl Content {
+    /// Most bytes a gateway may return before the fetch is abandoned
+    fn limit(self) -> usize {
+        match self {
+            Content::File => MAX_FETCH_BYTES,
+            Content::DirectoryBlock => MAX_BLOCK_BYTES,
+        }
+    }
+}
+
+/// Fetches a file through the gateways configured by `ipfs_gateways`
+///
+/// See [`fetch_from_gateways`].
+pub async fn fetch_from_ipfs(target: &str) -> Result<Vec<u8>> {
+    fetch_from_gateways(target, &Gateways::load()?).await
 }
 
-/// Delete a file from the filesystem
-pub fn delete_file(file_path: &str) -> Result<()> {
-    std::fs::remove_file(file_path)?;
-    println!("File deleted successfully: {}", file_path);
-    Ok(())
+/// Fetches `target` like [`fetch_from_ipfs`] and parses it as JSON
+pub async fn fetch_json_from_ipfs<T: DeserializeOwned>(target: &str) -> Result<T> {
+    let data = fetch_from_ipfs(target).await?;
+    serde_json::from_slice(&data)
+        .map_err(|e| anyhow::anyhow!("{} is not valid JSON: {}", target, e))
+}
+
+/// Fetches a file from the first of `gateways` that returns content hashing to it
+///
+/// `target` is a CID, optionally followed by a path within the directory it names,
+/// e.g. `CID/change.json`, with or without the `ipfs://` scheme. Each directory on
+/// the path is fetched as a raw block and its hash checked before the named link is
+/// followed, and the file itself is checked against the CID of that link.
+///
+/// Gateways are untrusted: a response that does not hash back to its CID, or that
+/// runs past [`MAX_FETCH_BYTES`] ([`MAX_BLOCK_BYTES`] for a directory block), is
+/// discarded like a failed request and the next gateway is tried. Files are hashed
+/// again with the importer parameters of [`crate::cid`], so only files added with
+/// `ipfs add` defaults (CIDv0, or CIDv1 with raw leaves) can be fetched.
+pub async fn fetch_from_gateways(target: &str, gateways: &Gateways) -> Result<Vec<u8>> {
+    let target = target.strip_prefix("ipfs://").unwrap_or(target);
+    let (root, path) = target.split_once('/').unwrap_or((target, ""));
+    let mut cid = Cid::parse(root)?;
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        let block = fetch_content(&cid, Content::DirectoryBlock, gateways).await?;
+        cid = cid::directory_entry(&cid, &block, name)?;
+    }
+    let data = fetch_content(&cid, Content::File, gateways).await?;
+    eprintln!("Fetched {} ({} bytes)", target, data.len());
+    Ok(data)
+}
+
+/// Fetches `cid` from the first gateway whose answer checks out
+async fn fetch_content(cid: &Cid, content: Content, gateways: &Gateways) -> Result<Vec<u8>> {
+    let mut failures = Vec::new();
+    for gateway in &gateways.gateways {
+        let url = match content {
+            Content::File => gateway.url(cid),
+            Content::DirectoryBlock => gateway.block_url(cid),
+        };
+        match fetch_verified(&url, cid, content, gateways.timeout).await {
+            Ok(data) => return Ok(data),
+            Err(e) => {
+                eprintln!("Gateway {} failed: {}", url, e);
+                failures.push(format!("{}: {}", url, e));
+            }
+        }
+    }
+    Err(anyhow::anyhow!("Could not fetch {} from any gateway: {}", cid, failures.join("; ")))
+}
+
+/// Downloads `url` within `timeout` and checks the content hashes to `requested`
+async fn fetch_verified(
+    url: &str,
+    requested: &Cid,
+    content: Content,
+    timeout: Duration,
+) -> Result<Vec<u8>> {
+    let download = async {
+        let mut request = Request::get(url);
+        if content == Content::DirectoryBlock {
+            request = request.header("Accept", "application/vnd.ipld.raw");
+        }
+        let mut response = wstd::http::Client::new().send(request.body(wstd::io::empty())?).await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Status: {:?}", response.status()));
+        }
+        read_limited(response.body_mut(), content.limit()).await
+    };
+    let data = download
+        .timeout(wstd::time::Duration::from(timeout))
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out after {:?}", timeout))??;
+
+    match content {
+        Content::File => {
+            let actual = cid::compute(&data, requested.version);
+            if actual != *requested {
+                return Err(anyhow::anyhow!("Content hashes to {}, not {}", actual, requested));
+            }
+        }
+        Content::DirectoryBlock => {
+            if !requested.is_block(&data) {
+                return Err(anyhow::anyhow!("Block does not hash to {}", requested));
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// Reads `reader` to the end, failing once more than `limit` bytes arrive
+async fn read_limited<R: AsyncRead>(reader: &mut R, limit: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match reader.read(&mut buf).await? {
+            0 => return Ok(data),
+            n if data.len() + n > limit => {
+                return Err(anyhow::anyhow!("Response exceeds the {} byte limit", limit))
+            }
+            n => data.extend_from_slice(&buf[..n]),
+        }
+    }
 }
 
 /// Get IPFS URL from CID
@@ -165,12 +374,22 @@ pub fn get_ipfs_url(cid: &str, filename: Option<&str>) -> String {
     }
 }
 
+/// Splits an `ipfs://CID[/path]` URI into its CID and optional path
+pub fn parse_ipfs_url(url: &str) -> Option<(&str, Option<&str>)> {
+    let rest = url.strip_prefix("ipfs://")?;
+    match rest.split_once('/') {
+        Some((cid, path)) if !path.is_empty() => Some((cid, Some(path))),
+        Some((cid, _)) => Some((cid, None)),
+        None => Some((rest, None)),
+    }
+}
+
 /// Uploads NFT content (metadata and/or image) to IPFS
 /// Returns the IPFS URI (ipfs://CID) for the content
 pub async fn upload_nft_content(
     content_type: &str,
     content: &[u8],
-    ipfs_url: &str,
+    pinner: &Pinner,
 ) -> Result<String> {
     // Determine if this is JSON metadata or an image
     let ipfs_uri = if content_type.contains("json") || content_type == "application/json" {
@@ -179,7 +398,7 @@ pub async fn upload_nft_content(
             .map_err(|e| anyhow::anyhow!("Failed to convert JSON bytes to string: {}", e))?;
 
         // Upload the JSON and return the IPFS URI
-        upload_json_to_ipfs(json_str, ipfs_url).await?
+        upload_json_to_ipfs(json_str, "nft_metadata.json", pinner).await?
     } else {
         // It's an image or other binary content
         let extension = match content_type {
@@ -193,7 +412,7 @@ pub async fn upload_nft_content(
         let filename = format!("nft_image.{}", extension);
 
         // Upload the image and return the IPFS URI
-        upload_image_to_ipfs(content, &filename, ipfs_url).await?
+        upload_image_to_ipfs(content, &filename, pinner).await?
     };
 
     // Log the upload
@@ -202,3 +421,85 @@ pub async fn upload_nft_content(
     // Return IPFS URI
     Ok(ipfs_uri)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V0: &str = "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o";
+
+    #[test]
+    fn path_gateways() {
+        let gateway: Gateway = "https://ipfs.io/".parse().unwrap();
+        assert_eq!(gateway, Gateway::Path("https://ipfs.io".to_string()));
+        let cid = Cid::parse(V0).unwrap();
+        // path gateways keep the CID as given
+        assert_eq!(gateway.url(&cid), format!("https://ipfs.io/ipfs/{}", V0));
+        assert_eq!(gateway.block_url(&cid), format!("https://ipfs.io/ipfs/{}?format=raw", V0));
+    }
+
+    #[test]
+    fn subdomain_gateways() {
+        let gateway: Gateway = "https://{cid}.ipfs.dweb.link".parse().unwrap();
+        assert_eq!(gateway, Gateway::Subdomain("https://{cid}.ipfs.dweb.link".to_string()));
+
+        // CIDv0 becomes the CIDv1 of the same dag-pb node
+        let v0 = Cid::parse(V0).unwrap();
+        let v1 = Cid { version: CidVersion::V1, ..v0 }.to_string();
+        assert!(v1.starts_with("bafybei"));
+        assert_eq!(gateway.url(&v0), format!("https://{}.ipfs.dweb.link", v1));
+
+        let raw = cid::compute(b"hello world\n", CidVersion::V1);
+        assert_eq!(
+            gateway.url(&raw),
+            "https://bafkreifjjcie6lypi6ny7amxnfftagclbuxndqonfipmb64f2km2devei4.ipfs.dweb.link"
+        );
+    }
+
+    #[test]
+    fn parses_gateway_lists() {
+        let timeout = Duration::from_secs(30);
+        let gateways =
+            Gateways::parse(" https://ipfs.io, ,https://{cid}.ipfs.dweb.link ,", timeout).unwrap();
+        assert_eq!(
+            gateways.gateways,
+            [
+                Gateway::Path("https://ipfs.io".to_string()),
+                Gateway::Subdomain("https://{cid}.ipfs.dweb.link".to_string())
+            ]
+        );
+        assert_eq!(gateways.timeout, timeout);
+
+        let err = Gateways::parse(" , ", timeout).unwrap_err();
+        assert_eq!(err.to_string(), "No IPFS gateway configured");
+        let err = Gateways::parse("https://ipfs.io,ipfs.io", timeout).unwrap_err();
+        assert_eq!(err.to_string(), "Gateway `ipfs.io` is not an http(s) URL");
+        assert!(Gateways::parse("ftp://ipfs.io", timeout).is_err());
+    }
+
+    #[test]
+    fn ipfs_urls() {
+        assert_eq!(get_ipfs_url(V0, None), format!("ipfs://{}", V0));
+        let url = get_ipfs_url(V0, Some("change.json"));
+        assert_eq!(parse_ipfs_url(&url), Some((V0, Some("change.json"))));
+        assert_eq!(parse_ipfs_url(&format!("ipfs://{}/", V0)), Some((V0, None)));
+        assert_eq!(parse_ipfs_url(V0), None);
+    }
+
+    #[test]
+    fn limits_response_size() {
+        let read = |len: usize, limit: usize| {
+            wstd::runtime::block_on(async move {
+                read_limited(&mut wstd::io::Cursor::new(vec![7u8; len]), limit).await
+            })
+        };
+        assert_eq!(read(0, 0).unwrap(), Vec::<u8>::new());
+        assert_eq!(read(200_000, 200_000).unwrap(), vec![7u8; 200_000]);
+        let err = read(200_001, 200_000).unwrap_err();
+        assert_eq!(err.to_string(), "Response exceeds the 200000 byte limit");
+        assert!(read(1, 0).is_err());
+
+        assert_eq!(Content::File.limit(), MAX_FETCH_BYTES);
+        assert_eq!(Content::DirectoryBlock.limit(), MAX_BLOCK_BYTES);
+    }
+}
@@ -1,16 +1,49 @@
+pub mod analysis;
+pub mod aoi;
+pub mod bands;
+pub mod change;
+pub mod cid;
+pub mod cog;
+pub mod cog_writer;
+pub mod composite;
+pub mod config;
+pub mod index;
+pub mod ipfs;
+pub mod mask;
+pub mod metadata;
+pub mod multipart;
+pub mod ndvi;
+pub mod output;
+pub mod pinner;
+pub mod preview;
+pub mod raster;
+pub mod request;
+pub mod series;
+pub mod stac;
 mod trigger;
+pub mod utm;
+use analysis::Analysis;
+use aoi::Aoi;
+use bands::{Band, BandSet};
+use change::ChangeDetection;
+use composite::{Composite, CompositeReport, SceneLayers, TileGrid};
+use config::Config;
+use metadata::{BandUpload, RegenMetadata};
+use output::RegenOutput;
+use request::{Mode, Outputs, RegenRequest};
+use series::{Observation, Skipped, TimeSeries};
+use stac::{Item, PageLimits, SearchRequest};
+use std::collections::{BTreeMap, BTreeSet};
 use trigger::{decode_trigger_event, encode_trigger_output, Destination};
-use wavs_wasi_utils::http::{fetch_json, http_request_get};
 pub mod bindings;
 use crate::bindings::{export, Guest, TriggerAction, WasmResponse};
-use serde::{Deserialize, Serialize};
-use wstd::{http::HeaderValue, runtime::block_on};
+use wstd::runtime::block_on;
 
 struct Component;
 export!(Component with_types_in bindings);
 
 impl Guest for Component {
-    /// Main entry point for the price oracle component.
+    /// Main entry point for the regen oracle component.
     /// WAVS is subscribed to watch for events emitted by the blockchain.
     /// When WAVS observes an event is emitted, it will internally route the event and its data to this function (component).
     /// The processing then occurs before the output is returned back to WAVS to be submitted to the blockchain by the operator(s).
@@ -18,144 +51,361 @@ impl Guest for Component {
     /// This is why the `Destination::Ethereum` requires the encoded trigger output, it must be ABI encoded for the solidity contract.
     /// Failure to do so will result in a failed submission as the signature will not match the saved output.
     ///
-    /// After the data is properly set by the operator through WAVS, any user can query the price data from the blockchain in the solidity contract.
+    /// After the data is properly set by the operator through WAVS, any user can query the result from the blockchain in the solidity contract.
     /// You can also return `None` as the output if nothing needs to be saved to the blockchain. (great for performing some off chain action)
     ///
     /// This function:
     /// 1. Receives a trigger action containing encoded data
-    /// 2. Decodes the input to get a cryptocurrency ID (in hex)
-    /// 3. Fetches current price data from CoinMarketCap
-    /// 4. Returns the encoded response based on the destination
+    /// 2. Decodes the input as an ABI encoded `RegenQuery` and builds a STAC search from it
+    /// 3. Queries the Earth Search STAC API for matching Sentinel-2 scenes, following result pages up to the configured caps
+    /// 4. Selects the COG assets of the requested index's bands and the scene classification of the most recent scene
+    /// 5. Reads the AOI window of each band, masks clouds and computes index statistics inside the parcel
+    /// 6. Streams the index's bands from their source URLs into IPFS, if requested
+    /// 7. Uploads the masked index raster as a cloud-optimized GeoTIFF, a colormapped PNG preview
+    ///    of it, the band source links and an ERC-721 compatible metadata document linking the
+    ///    query, scene, bands, rasters and statistics, all in one IPFS directory
+    /// 8. Returns a `RegenResult` with the mean index value and the directory CID, encoded based on the destination
+    ///
+    /// In time series mode every scene on every result page is analysed instead, and the
+    /// per-date statistics are uploaded as JSON and CSV in place of the metadata document;
+    /// scenes whose metadata cannot serve the request (a missing band asset or `proj:epsg`,
+    /// or an AOI outside the tile) are listed as skipped rather than failing the series,
+    /// while fetch errors, and a search whose scenes were all skipped, fail the request.
+    /// Change detection mode composites the scenes of the baseline and monitoring periods
+    /// on a common MGRS tile grid and uploads their difference; composite mode uploads
+    /// the composite of the acquisition window itself.
     fn run(action: TriggerAction) -> std::result::Result<Option<WasmResponse>, String> {
         let (trigger_id, req, dest) =
             decode_trigger_event(action.data).map_err(|e| e.to_string())?;
 
-        // TODO: impl `crate::bindings::host::config_var` to showcase example binding
-
-        // Convert bytes to string and parse first char as u64
-        let input = std::str::from_utf8(&req).map_err(|e| e.to_string())?;
-        println!("input id: {}", input);
+        let config = Config::load().map_err(|e| e.to_string())?;
 
-        let id = input.chars().next().ok_or("Empty input")?;
-        let id = id.to_digit(16).ok_or("Invalid hex digit")? as u64;
+        let search = req.to_search(&config.collection);
+        println!("search: {}", serde_json::to_string(&search).map_err(|e| e.to_string())?);
 
         let res = block_on(async move {
-            let resp_data = get_price_feed(id).await?;
-            println!("resp_data: {:?}", resp_data);
-            serde_json::to_vec(&resp_data).map_err(|e| e.to_string())
+            match req.mode {
+                Mode::Latest => latest_scene(&req, &config, search).await,
+                Mode::TimeSeries => time_series(&req, &config, search).await,
+                Mode::ChangeDetection => change_detection(&req, &config, search).await,
+                Mode::Composite => tile_composite(&req, &config, search).await,
+            }
         })?;
 
         let output = match dest {
             Destination::Ethereum => Some(encode_trigger_output(trigger_id, &res)),
-            Destination::CliOutput => Some(WasmResponse { payload: res.into(), ordering: None }),
+            Destination::CliOutput => Some(WasmResponse {
+                payload: serde_json::to_vec(&res).map_err(|e| e.to_string())?,
+                ordering: None,
+            }),
         };
         Ok(output)
     }
 }
 
-/// Fetches cryptocurrency price data from CoinMarketCap's API
-///
-/// # Arguments
-/// * `id` - CoinMarketCap's unique identifier for the cryptocurrency
-///
-/// # Returns
-/// * `PriceFeedData` containing:
-///   - symbol: The cryptocurrency's ticker symbol (e.g., "BTC")
-///   - price: Current price in USD
-///   - timestamp: Server timestamp of the price data
-///
-/// # Implementation Details
-/// - Uses CoinMarketCap's v3 API endpoint
-/// - Includes necessary headers to avoid rate limiting:
-///   * User-Agent to mimic a browser
-///   * Random cookie with current timestamp
-///   * JSON content type headers
-///
-/// As of writing (Mar 31, 2025), the CoinMarketCap API is free to use and has no rate limits.
-/// This may change in the future so be aware of issues that you may encounter going forward.
-/// There is a more proper API for pro users that you can use
-/// - <https://coinmarketcap.com/api/documentation/v1/>
-async fn get_price_feed(id: u64) -> Result<PriceFeedData, String> {
-    let url = format!(
-        "https://api.coinmarketcap.com/data-api/v3/cryptocurrency/detail?id={}&range=1h",
-        id
+/// Analyses the most recent scene the search returns and uploads its metadata document
+async fn latest_scene(
+    req: &RegenRequest,
+    config: &Config,
+    search: SearchRequest,
+) -> std::result::Result<RegenOutput, String> {
+    // the search asks for the newest scene alone, so the first page has it
+    let items = search_items(config, &search, PageLimits { max_pages: 1, max_items: 1 }).await?;
+    let Some(item) = items.last() else {
+        println!("no scene matched the search");
+        return Ok(RegenOutput::no_scene(req.index));
+    };
+    let bands = BandSet::from_item(item, &analysis::analysis_bands(req.index))
+        .map_err(|e| e.to_string())?;
+
+    let aoi = req.aoi();
+    let scene = analysis::read_scene_index(
+        item,
+        &bands,
+        req.index,
+        &aoi,
+        &config.scl_mask,
+        config.coverage,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let index_cog = cog_writer::write_cog(&scene.values, scene.epsg, config.cog_format)
+        .map_err(|e| e.to_string())?;
+    let outline = aoi.project(&utm::Utm::from_epsg(scene.epsg).map_err(|e| e.to_string())?);
+    let preview = preview::render(
+        &scene.values,
+        config.preview_outline.then_some(&outline),
+        config.preview_max_dim,
+    )
+    .map_err(|e| e.to_string())?
+    .to_png();
+    let analysis = Analysis::new(&aoi, config.coverage, scene).map_err(|e| e.to_string())?;
+    println!(
+        "analysed {}x{} pixels, {:.1}% masked",
+        analysis.width,
+        analysis.height,
+        analysis.mask.masked_fraction * 100.0
     );
+    let mean_index = analysis.stats.as_ref().map(|stats| stats.mean);
 
-    let current_time = std::time::SystemTime::now().elapsed().unwrap().as_secs();
+    let mut uploads = BTreeMap::new();
+    if req.outputs.contains(Outputs::BANDS) {
+        for asset in bands.bands.values().filter(|asset| asset.band != Band::Scl) {
+            println!("{} ({}): {}", asset.band, asset.key, asset.href);
+            let filename = format!("{}_{}.tif", bands.item_id, asset.band);
+            let upload = ipfs::upload_url_to_ipfs(&asset.href, &filename, &config.pinner)
+                .await
+                .map_err(|e| e.to_string())?;
+            println!("{} uploaded: {} ({} bytes)", asset.band, upload.uri, upload.bytes);
+            uploads.insert(asset.band, BandUpload::new(&asset.href, &upload));
+        }
+    }
 
-    let mut req = http_request_get(&url).map_err(|e| e.to_string())?;
-    req.headers_mut().insert("Accept", HeaderValue::from_static("application/json"));
-    req.headers_mut().insert("Content-Type", HeaderValue::from_static("application/json"));
-    req.headers_mut()
-        .insert("User-Agent", HeaderValue::from_static("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/132.0.0.0 Safari/537.36"));
-    req.headers_mut().insert(
-        "Cookie",
-        HeaderValue::from_str(&format!("myrandom_cookie={}", current_time)).unwrap(),
+    let sources = bands.bands.values().map(|asset| (asset.band, asset.href.clone())).collect();
+    let mut metadata = RegenMetadata::new(search, item, uploads, analysis);
+    let uri = metadata
+        .upload(&index_cog, &preview, &sources, &config.pinner)
+        .await
+        .map_err(|e| e.to_string())?;
+    println!(
+        "metadata: {}, index raster: {:?}, preview: {:?}",
+        uri, metadata.index_raster, metadata.image
     );
 
-    let json: Root = fetch_json(req).await.map_err(|e| e.to_string())?;
+    let (cid, _) = ipfs::parse_ipfs_url(&uri).ok_or("Invalid metadata URI")?;
+    RegenOutput::for_scene(item, cid, req.index, mean_index).map_err(|e| e.to_string())
+}
+
+/// Analyses every scene on every result page and uploads the per-date series as JSON and CSV
+async fn time_series(
+    req: &RegenRequest,
+    config: &Config,
+    search: SearchRequest,
+) -> std::result::Result<RegenOutput, String> {
+    let items = search_items(config, &search, config.page_limits).await?;
 
-    Ok(PriceFeedData {
-        symbol: json.data.symbol,
-        price: json.data.statistics.price,
-        timestamp: json.status.timestamp,
-    })
+    let aoi = req.aoi();
+    let mut observations = Vec::with_capacity(items.len());
+    let mut skipped = Vec::new();
+    for item in &items {
+        match observe(req, config, item, &aoi).await {
+            Ok(observation) => observations.push(observation),
+            Err(e) if analysis::is_scene_error(&e) => {
+                println!("{}: skipped, {}", item.id, e);
+                skipped.push(Skipped::new(item, e));
+            }
+            Err(e) => return Err(format!("{}: {}", item.id, e)),
+        }
+    }
+
+    if items.is_empty() {
+        println!("no scene matched the search");
+        return Ok(RegenOutput::no_scene(req.index));
+    }
+    if observations.is_empty() {
+        let reason = skipped.first().map(|s| s.reason.as_str()).unwrap_or_default();
+        return Err(format!(
+            "None of the {} matched scenes could be read: {}",
+            items.len(),
+            reason
+        ));
+    }
+    let mut series = TimeSeries::new(search, req.index, config.coverage, observations, skipped);
+    let uri = series.upload(&config.pinner).await.map_err(|e| e.to_string())?;
+    println!("series: {} ({} dates)", uri, series.observations.len());
+
+    let (cid, _) = ipfs::parse_ipfs_url(&uri).ok_or("Invalid series URI")?;
+    RegenOutput::for_series(&series, cid).map_err(|e| e.to_string())
 }
 
-/// Represents the price feed response data structure
-/// This is the simplified version of the data that will be sent to the blockchain
-/// via the Submission of the operator(s).
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PriceFeedData {
-    symbol: String,
-    timestamp: String,
-    price: f64,
+/// Analyses one scene of a time series
+async fn observe(
+    req: &RegenRequest,
+    config: &Config,
+    item: &Item,
+    aoi: &Aoi,
+) -> anyhow::Result<Observation> {
+    let bands = BandSet::from_item(item, &analysis::analysis_bands(req.index))?;
+    let analysis =
+        analysis::analyze_scene(item, &bands, req.index, aoi, &config.scl_mask, config.coverage)
+            .await?;
+    println!("{}: {:.1}% masked", item.id, analysis.mask.masked_fraction * 100.0);
+    Observation::new(item, &analysis)
 }
 
-/// Root response structure from CoinMarketCap API
-/// Generated from the API response using <https://transform.tools/json-to-rust-serde>
-/// Contains detailed cryptocurrency information including price statistics
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Root {
-    pub data: Data,
-    pub status: Status,
+/// Composites the scenes of the best-covered tile and uploads the composite
+async fn tile_composite(
+    req: &RegenRequest,
+    config: &Config,
+    search: SearchRequest,
+) -> std::result::Result<RegenOutput, String> {
+    let items = search_items(config, &search, config.page_limits).await?;
+    let Some(tile) = composite::select_tile(&items) else {
+        println!("no scene matched the search");
+        return Ok(RegenOutput::no_scene(req.index));
+    };
+    let items = on_tile(items, &tile);
+    let (Some(first), Some(latest)) = (items.first(), items.last()) else {
+        return Ok(RegenOutput::no_scene(req.index));
+    };
+
+    let grid = tile_grid(req, config, first)?;
+    println!("compositing {} scenes on {} ({}x{})", items.len(), tile, grid.width, grid.height);
+    let scenes = read_tile(req, config, &items).await?;
+    let composite =
+        Composite::build(config.composite_method, &grid, &scenes).map_err(|e| e.to_string())?;
+    let weights = grid.weights(&req.aoi(), config.coverage).map_err(|e| e.to_string())?;
+
+    let mut report = CompositeReport::new(
+        search,
+        req.index,
+        config.coverage,
+        grid,
+        items.iter().map(|item| item.id.clone()).collect(),
+        &composite,
+        &weights,
+    )
+    .map_err(|e| e.to_string())?;
+    let uri = report
+        .upload(&composite, config.cog_format, &config.pinner)
+        .await
+        .map_err(|e| e.to_string())?;
+    println!("composite: {}, raster: {:?}", uri, report.raster);
+
+    let (cid, _) = ipfs::parse_ipfs_url(&uri).ok_or("Invalid composite URI")?;
+    RegenOutput::for_composite(&report, latest, cid).map_err(|e| e.to_string())
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Data {
-    pub id: f64,
-    pub name: String,
-    pub symbol: String,
-    pub statistics: Statistics,
-    pub description: String,
-    pub category: String,
-    pub slug: String,
+/// Composites the baseline and monitoring periods and uploads their difference
+async fn change_detection(
+    req: &RegenRequest,
+    config: &Config,
+    search: SearchRequest,
+) -> std::result::Result<RegenOutput, String> {
+    let baseline_search =
+        req.to_baseline_search(&config.collection).ok_or("Change detection needs a baseline")?;
+    println!(
+        "baseline search: {}",
+        serde_json::to_string(&baseline_search).map_err(|e| e.to_string())?
+    );
+
+    let baseline = search_items(config, &baseline_search, config.page_limits).await?;
+    let monitoring = search_items(config, &search, config.page_limits).await?;
+
+    // Both periods are composited on one tile grid, so only tiles seen in both can take part
+    let tiles = |items: &[Item]| -> BTreeSet<String> {
+        items.iter().filter_map(|item| item.properties.grid_code.clone()).collect()
+    };
+    let shared = &tiles(&baseline) & &tiles(&monitoring);
+    let candidates = baseline.iter().chain(&monitoring).filter(|item| {
+        item.properties.grid_code.as_ref().is_some_and(|code| shared.contains(code))
+    });
+    let Some(tile) = composite::select_tile(candidates) else {
+        println!("no tile has scenes in both periods");
+        return Ok(RegenOutput::no_scene(req.index));
+    };
+    let (baseline, monitoring) = (on_tile(baseline, &tile), on_tile(monitoring, &tile));
+    let (Some(first), Some(latest)) = (baseline.first(), monitoring.last()) else {
+        return Ok(RegenOutput::no_scene(req.index));
+    };
+
+    let grid = tile_grid(req, config, first)?;
+    println!("comparing on {} ({}x{})", tile, grid.width, grid.height);
+    let baseline_scenes = read_tile(req, config, &baseline).await?;
+    let monitoring_scenes = read_tile(req, config, &monitoring).await?;
+    let weights = grid.weights(&req.aoi(), config.coverage).map_err(|e| e.to_string())?;
+
+    let comparison = change::compare(
+        config.composite_method,
+        &grid,
+        weights,
+        &baseline_scenes,
+        &monitoring_scenes,
+        config.thresholds,
+    )
+    .map_err(|e| e.to_string())?;
+    let ids = |items: &[Item]| items.iter().map(|item| item.id.clone()).collect();
+    let mut change = ChangeDetection::new(
+        req.index,
+        config.coverage,
+        config.thresholds,
+        (baseline_search, ids(&baseline)),
+        (search, ids(&monitoring)),
+        &comparison,
+    )
+    .map_err(|e| e.to_string())?;
+    let uri = change
+        .upload(&comparison.delta, config.cog_format, &config.pinner)
+        .await
+        .map_err(|e| e.to_string())?;
+    println!("change: {}, delta raster: {:?}", uri, change.delta_raster);
+
+    let (cid, _) = ipfs::parse_ipfs_url(&uri).ok_or("Invalid change URI")?;
+    RegenOutput::for_change(&change, latest, cid).map_err(|e| e.to_string())
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Statistics {
-    pub price: f64,
-    #[serde(rename = "totalSupply")]
-    pub total_supply: f64,
+/// Runs `search` over result pages up to `limits`, returning the items in datetime order
+async fn search_items(
+    config: &Config,
+    search: &SearchRequest,
+    limits: PageLimits,
+) -> std::result::Result<Vec<Item>, String> {
+    let items =
+        stac::search_all(&config.stac_api_url, search, limits).await.map_err(|e| e.to_string())?;
+    println!("features returned: {}", items.len());
+    Ok(items)
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct CoinBitesVideo {
-    pub id: String,
-    pub category: String,
-    #[serde(rename = "videoUrl")]
-    pub video_url: String,
-    pub title: String,
-    pub description: String,
-    #[serde(rename = "previewImage")]
-    pub preview_image: String,
+/// Keeps the items of the MGRS tile `grid_code`
+fn on_tile(items: Vec<Item>, grid_code: &str) -> Vec<Item> {
+    items
+        .into_iter()
+        .filter(|item| {
+            let keep = item.properties.grid_code.as_deref() == Some(grid_code);
+            if !keep {
+                println!("{}: skipped, not on tile {}", item.id, grid_code);
+            }
+            keep
+        })
+        .collect()
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Status {
-    pub timestamp: String,
-    pub error_code: String,
-    pub error_message: String,
-    pub elapsed: String,
-    pub credit_count: f64,
+/// The AOI window of `item`'s tile grid
+fn tile_grid(
+    req: &RegenRequest,
+    config: &Config,
+    item: &Item,
+) -> std::result::Result<TileGrid, String> {
+    let bands = BandSet::from_item(item, &config.composite_method.bands(req.index))
+        .map_err(|e| e.to_string())?;
+    TileGrid::new(item, &bands, req.index, &req.aoi()).map_err(|e| e.to_string())
+}
+
+/// Reads the composite layers of every item, in the order given
+async fn read_tile(
+    req: &RegenRequest,
+    config: &Config,
+    items: &[Item],
+) -> std::result::Result<Vec<SceneLayers>, String> {
+    let aoi = req.aoi();
+    let method = config.composite_method;
+    let mut scenes = Vec::with_capacity(items.len());
+    for item in items {
+        let bands =
+            BandSet::from_item(item, &method.bands(req.index)).map_err(|e| e.to_string())?;
+        let scene = composite::read_scene_layers(
+            item,
+            &bands,
+            req.index,
+            method,
+            &aoi,
+            &config.scl_mask,
+            config.coverage,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        println!("{}: {:.1}% masked", item.id, scene.index.mask.masked_fraction * 100.0);
+        scenes.push(scene);
+    }
+    Ok(scenes)
 }
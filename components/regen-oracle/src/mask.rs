@@ -0,0 +1,239 @@
+use crate::raster::Raster;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Sentinel-2 L2A scene classification (SCL) classes
+pub mod class {
+    pub const NO_DATA: u8 = 0;
+    pub const SATURATED_OR_DEFECTIVE: u8 = 1;
+    pub const DARK_AREA_PIXELS: u8 = 2;
+    pub const CLOUD_SHADOWS: u8 = 3;
+    pub const VEGETATION: u8 = 4;
+    pub const NOT_VEGETATED: u8 = 5;
+    pub const WATER: u8 = 6;
+    pub const UNCLASSIFIED: u8 = 7;
+    pub const CLOUD_MEDIUM_PROBABILITY: u8 = 8;
+    pub const CLOUD_HIGH_PROBABILITY: u8 = 9;
+    pub const THIN_CIRRUS: u8 = 10;
+    pub const SNOW: u8 = 11;
+}
+
+/// Classes excluded by default: saturated/defective, cloud shadows, clouds, cirrus and snow
+pub const DEFAULT_SCL_EXCLUDE: &str = "1,3,8,9,10,11";
+
+/// The set of SCL classes whose pixels are left out of index statistics
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SclMask {
+    pub excluded: BTreeSet<u8>,
+}
+
+/// Outcome of masking a raster with the SCL layer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaskSummary {
+    pub excluded_classes: Vec<u8>,
+    /// Pixels within the AOI whose class is excluded or whose SCL value is nodata
+    pub masked_pixels: u64,
+    /// Pixels within the AOI
+    pub total_pixels: u64,
+    /// `masked_pixels / total_pixels`
+    pub masked_fraction: f64,
+    /// Number of pixels of each SCL class within the AOI
+    pub class_counts: BTreeMap<u8, u64>,
+}
+
+impl SclMask {
+    /// Parses a list of class values separated by commas or whitespace, e.g. `1,3,8,9,10,11`
+    pub fn parse(list: &str) -> Result<Self> {
+        let excluded = list
+            .split([',', ' ', '\t'])
+            .filter(|v| !v.is_empty())
+            .map(|v| match v.parse::<u8>() {
+                Ok(class) if class <= class::SNOW => Ok(class),
+                _ => Err(anyhow::anyhow!("Invalid SCL class `{}` in `{}`", v, list)),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { excluded })
+    }
+
+    pub fn excludes(&self, class: u8) -> bool {
+        self.excluded.contains(&class)
+    }
+
+    /// Sets the pixels of `raster` covered by excluded classes to NaN
+    ///
+    /// `scl` is the 20m classification window read for the same area; it is
+    /// resampled onto the raster's 10m grid by nearest neighbour first. Pixels
+    /// where the SCL is nodata are masked too; without a nodata value in the
+    /// asset, that is [`class::NO_DATA`], which is also what resampling fills
+    /// with outside the window. Only pixels with a non-zero AOI weight in
+    /// `weights` are counted in the summary.
+    pub fn apply(
+        &self,
+        raster: &mut Raster<f32>,
+        scl: &Raster<u8>,
+        weights: &[f32],
+    ) -> MaskSummary {
+        let scl = scl.resample_nearest(raster.width, raster.height, raster.transform);
+        let nodata = scl.nodata.unwrap_or(class::NO_DATA);
+
+        let mut class_counts = BTreeMap::new();
+        let (mut masked_pixels, mut total_pixels) = (0, 0u64);
+        for ((value, &class), &weight) in raster.data.iter_mut().zip(&scl.data).zip(weights) {
+            if weight <= 0.0 {
+                continue;
+            }
+            total_pixels += 1;
+            let is_nodata = class == nodata;
+            if !is_nodata {
+                *class_counts.entry(class).or_insert(0) += 1;
+            }
+            if is_nodata || self.excludes(class) {
+                *value = f32::NAN;
+                masked_pixels += 1;
+            }
+        }
+
+        MaskSummary {
+            excluded_classes: self.excluded.iter().copied().collect(),
+            masked_pixels,
+            total_pixels,
+            masked_fraction: if total_pixels == 0 {
+                0.0
+            } else {
+                masked_pixels as f64 / total_pixels as f64
+            },
+            class_counts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::GeoTransform;
+
+    /// 10m grid of a Sentinel-2 tile, upper-left corner at (499980, 4200000)
+    const TRANSFORM: GeoTransform = GeoTransform([10.0, 0.0, 499_980.0, 0.0, -10.0, 4_200_000.0]);
+
+    /// The 20m grid covering [`TRANSFORM`]'s area
+    const SCL_TRANSFORM: GeoTransform =
+        GeoTransform([20.0, 0.0, 499_980.0, 0.0, -20.0, 4_200_000.0]);
+
+    fn values(width: usize, height: usize) -> Raster<f32> {
+        Raster {
+            width,
+            height,
+            data: (0..width * height).map(|i| i as f32 / 100.0).collect(),
+            transform: TRANSFORM,
+            nodata: None,
+        }
+    }
+
+    fn scl(width: usize, height: usize, data: Vec<u8>, nodata: Option<u8>) -> Raster<u8> {
+        Raster { width, height, data, transform: SCL_TRANSFORM, nodata }
+    }
+
+    fn mask(classes: &[u8]) -> SclMask {
+        SclMask { excluded: classes.iter().copied().collect() }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(SclMask::parse(DEFAULT_SCL_EXCLUDE).unwrap(), mask(&[1, 3, 8, 9, 10, 11]));
+        assert_eq!(SclMask::parse("3 8\t9").unwrap(), mask(&[3, 8, 9]));
+        assert_eq!(SclMask::parse("3, 8,,9,").unwrap(), mask(&[3, 8, 9]));
+        assert_eq!(SclMask::parse("9,9,3").unwrap(), mask(&[3, 9]));
+        assert_eq!(SclMask::parse("").unwrap(), mask(&[]));
+        assert_eq!(SclMask::parse(" , ").unwrap(), mask(&[]));
+        assert_eq!(SclMask::parse("0,11").unwrap(), mask(&[0, 11]));
+
+        for list in ["12", "3,12", "256", "-1", "cloud", "3;8", "3.0"] {
+            let err = SclMask::parse(list).unwrap_err().to_string();
+            assert!(err.starts_with("Invalid SCL class"), "{}: {}", list, err);
+        }
+    }
+
+    #[test]
+    fn apply_masks_excluded_classes() {
+        let mut raster = values(4, 2);
+        // Each 20m SCL pixel covers two 10m columns
+        let scl = scl(2, 1, vec![class::VEGETATION, class::CLOUD_HIGH_PROBABILITY], None);
+        let summary = mask(&[class::CLOUD_HIGH_PROBABILITY]).apply(&mut raster, &scl, &[1.0; 8]);
+
+        let masked: Vec<bool> = raster.data.iter().map(|v| v.is_nan()).collect();
+        assert_eq!(masked, [false, false, true, true, false, false, true, true]);
+        assert_eq!(raster.data[..2], [0.0, 0.01]);
+        assert_eq!(summary.excluded_classes, [class::CLOUD_HIGH_PROBABILITY]);
+        assert_eq!((summary.masked_pixels, summary.total_pixels), (4, 8));
+        assert_eq!(summary.masked_fraction, 0.5);
+        assert_eq!(
+            summary.class_counts,
+            BTreeMap::from([(class::VEGETATION, 4), (class::CLOUD_HIGH_PROBABILITY, 4)])
+        );
+    }
+
+    #[test]
+    fn apply_masks_nodata_without_counting_it() {
+        let data = vec![class::VEGETATION, 255, class::NO_DATA, class::WATER];
+        let mut raster = values(4, 4);
+        let summary = mask(&[]).apply(&mut raster, &scl(2, 2, data.clone(), Some(255)), &[1.0; 16]);
+        let masked: Vec<usize> = (0..16).filter(|&i| raster.data[i].is_nan()).collect();
+        assert_eq!(masked, [2, 3, 6, 7]);
+        assert_eq!((summary.masked_pixels, summary.total_pixels), (4, 16));
+        assert_eq!(
+            summary.class_counts,
+            BTreeMap::from([(class::NO_DATA, 4), (class::VEGETATION, 4), (class::WATER, 4)])
+        );
+
+        // Without a nodata value in the asset, class 0 is nodata
+        let mut raster = values(4, 4);
+        let summary = mask(&[]).apply(&mut raster, &scl(2, 2, data, None), &[1.0; 16]);
+        let masked: Vec<usize> = (0..16).filter(|&i| raster.data[i].is_nan()).collect();
+        assert_eq!(masked, [8, 9, 12, 13]);
+        assert_eq!((summary.masked_pixels, summary.total_pixels), (4, 16));
+        assert_eq!(
+            summary.class_counts,
+            BTreeMap::from([(class::VEGETATION, 4), (class::WATER, 4), (255, 4)])
+        );
+    }
+
+    #[test]
+    fn apply_outside_scl_window_is_nodata() {
+        // The SCL window covers only the left half of the raster
+        let mut raster = values(4, 2);
+        let scl = scl(1, 1, vec![class::VEGETATION], None);
+        let summary = mask(&[]).apply(&mut raster, &scl, &[1.0; 8]);
+        let masked: Vec<bool> = raster.data.iter().map(|v| v.is_nan()).collect();
+        assert_eq!(masked, [false, false, true, true, false, false, true, true]);
+        assert_eq!((summary.masked_pixels, summary.total_pixels), (4, 8));
+        assert_eq!(summary.class_counts, BTreeMap::from([(class::VEGETATION, 4)]));
+    }
+
+    #[test]
+    fn apply_ignores_zero_weight_pixels() {
+        let mut raster = values(4, 2);
+        let scl = scl(2, 1, vec![class::VEGETATION, class::SNOW], None);
+        let weights = [1.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0, 1.0];
+        let summary = mask(&[class::SNOW]).apply(&mut raster, &scl, &weights);
+
+        // Pixels outside the AOI are neither masked nor counted
+        let masked: Vec<usize> = (0..8).filter(|&i| raster.data[i].is_nan()).collect();
+        assert_eq!(masked, [2, 7]);
+        assert_eq!((summary.masked_pixels, summary.total_pixels), (2, 3));
+        assert_eq!(
+            summary.class_counts,
+            BTreeMap::from([(class::VEGETATION, 1), (class::SNOW, 2)])
+        );
+    }
+
+    #[test]
+    fn apply_without_aoi_pixels() {
+        let mut raster = values(4, 2);
+        let scl = scl(2, 1, vec![class::CLOUD_SHADOWS; 2], None);
+        let summary = mask(&[class::CLOUD_SHADOWS]).apply(&mut raster, &scl, &[0.0; 8]);
+        assert_eq!((summary.masked_pixels, summary.total_pixels), (0, 0));
+        assert_eq!(summary.masked_fraction, 0.0);
+        assert!(summary.class_counts.is_empty());
+    }
+}
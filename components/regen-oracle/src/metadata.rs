@@ -0,0 +1,203 @@
+use crate::analysis::Analysis;
+use crate::bands::Band;
+use crate::cid;
+use crate::ipfs::{self, StreamedUpload};
+use crate::pinner::{IpfsPinner, Pinner};
+use crate::stac::{Item, SearchRequest};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Version of this component, recorded in every metadata document
+pub const COMPONENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Name of the metadata document within its bundle
+pub const METADATA_FILE: &str = "metadata.json";
+
+/// Name of the band source listing within a scene bundle
+pub const SOURCES_FILE: &str = "sources.json";
+
+/// Metadata document describing one oracle run
+///
+/// Links the STAC query that was executed, the scene that was chosen, the IPFS
+/// copies of its band rasters, the masked index raster and the statistics computed
+/// over the AOI. The document is uploaded in one directory with the files it
+/// describes, and the directory's CID is the oracle output.
+///
+/// The `name`, `description`, `image` and `attributes` fields follow the ERC-721
+/// metadata JSON schema, so the document can serve directly as a token's metadata.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegenMetadata {
+    pub name: String,
+    pub description: String,
+    /// `ipfs://` URI of the PNG preview of the index raster, set once it is uploaded
+    ///
+    /// The preview is in the bundle but linked by its own CID, since wallets need an
+    /// absolute URI and the bundle's CID depends on this document.
+    pub image: Option<String>,
+    pub attributes: Vec<Attribute>,
+    pub version: String,
+    pub query: SearchRequest,
+    pub scene: Scene,
+    pub bands: BTreeMap<Band, BandUpload>,
+    pub analysis: Analysis,
+    /// Path within the bundle of the masked index raster, a cloud-optimized GeoTIFF
+    /// on the analysis grid; set once it is uploaded
+    pub index_raster: Option<String>,
+    /// Path within the bundle of the JSON map from every band read to its source
+    /// asset href; set once it is uploaded
+    pub sources: Option<String>,
+}
+
+/// The STAC item the oracle selected
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    pub item_id: String,
+    pub datetime: String,
+    pub platform: Option<String>,
+    pub cloud_cover: Option<f64>,
+    pub bbox: Option<Vec<f64>>,
+    /// GeoJSON footprint in WGS84
+    pub geometry: Value,
+}
+
+/// An ERC-721 metadata attribute
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attribute {
+    pub trait_type: String,
+    pub value: Value,
+}
+
+/// A band raster pinned to IPFS
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BandUpload {
+    pub uri: String,
+    /// Asset href the raster was copied from
+    pub source: String,
+    pub bytes: u64,
+}
+
+impl From<&Item> for Scene {
+    fn from(item: &Item) -> Self {
+        Self {
+            item_id: item.id.clone(),
+            datetime: item.properties.datetime.clone(),
+            platform: item.properties.platform.clone(),
+            cloud_cover: item.properties.cloud_cover,
+            bbox: item.bbox.clone(),
+            geometry: item.geometry.clone(),
+        }
+    }
+}
+
+impl BandUpload {
+    pub fn new(source: &str, upload: &StreamedUpload) -> Self {
+        Self { uri: upload.uri.clone(), source: source.to_string(), bytes: upload.bytes }
+    }
+}
+
+impl Attribute {
+    pub fn new(trait_type: &str, value: Value) -> Self {
+        Self { trait_type: trait_type.to_string(), value }
+    }
+}
+
+impl RegenMetadata {
+    pub fn new(
+        query: SearchRequest,
+        item: &Item,
+        bands: BTreeMap<Band, BandUpload>,
+        analysis: Analysis,
+    ) -> Self {
+        let index = analysis.index.name().to_uppercase();
+        let mut attributes = vec![
+            Attribute::new("Index", json!(index)),
+            Attribute::new("Scene", json!(item.id)),
+            Attribute::new("Date", json!(item.properties.datetime.get(..10))),
+            Attribute::new("Cloud cover", json!(item.properties.cloud_cover)),
+        ];
+        if let Some(stats) = &analysis.stats {
+            attributes.push(Attribute::new("Mean", json!(stats.mean)));
+            attributes.push(Attribute::new("Valid fraction", json!(stats.valid_fraction)));
+        }
+
+        Self {
+            name: format!("{} of {}", index, item.id),
+            description: format!(
+                "Cloud-masked {} of Sentinel-2 scene {} over the requested parcel",
+                index, item.id
+            ),
+            image: None,
+            attributes,
+            version: COMPONENT_VERSION.to_string(),
+            query,
+            scene: item.into(),
+            bands,
+            analysis,
+            index_raster: None,
+            sources: None,
+        }
+    }
+
+    /// Name of the bundle directory, e.g. `S2B_10SEG_20240627_0_L2A_ndvi`
+    pub fn bundle_name(&self) -> String {
+        format!("{}_{}", self.scene.item_id, self.analysis.index)
+    }
+
+    /// Serializes the document canonically: object keys sorted, no insignificant whitespace
+    ///
+    /// Every operator must upload byte-identical documents for the CIDs to agree.
+    pub fn to_canonical_json(&self) -> Result<String> {
+        canonical_json(self)
+    }
+
+    /// Uploads `index_cog`, the encoded index raster, `preview`, its PNG rendering,
+    /// and the `sources` of every band read as one directory, with the canonical
+    /// document linking to them by relative path
+    ///
+    /// Returns the document's `ipfs://CID/metadata.json` URI, CID being the directory's.
+    pub async fn upload(
+        &mut self,
+        index_cog: &[u8],
+        preview: &[u8],
+        sources: &BTreeMap<Band, String>,
+        pinner: &Pinner,
+    ) -> Result<String> {
+        let raster = format!("{}.tif", self.analysis.index);
+        let image = format!("{}.png", self.analysis.index);
+        let sources = canonical_json(sources)?;
+        self.index_raster = Some(raster.clone());
+        self.sources = Some(SOURCES_FILE.to_string());
+        let preview_cid = cid::compute(preview, pinner.cid_version()).to_string();
+        self.image = Some(ipfs::get_ipfs_url(&preview_cid, None));
+
+        let document = self.to_canonical_json()?;
+        let files = [
+            (METADATA_FILE, document.as_bytes()),
+            (raster.as_str(), index_cog),
+            (image.as_str(), preview),
+            (SOURCES_FILE, sources.as_bytes()),
+        ];
+        let root = ipfs::upload_directory_to_ipfs(&self.bundle_name(), &files, pinner).await?;
+        Ok(ipfs::get_ipfs_url(&root, Some(METADATA_FILE)))
+    }
+}
+
+/// Serializes `value` with object keys sorted and no insignificant whitespace
+pub fn canonical_json<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string(&canonicalize(serde_json::to_value(value)?))?)
+}
+
+/// Recursively sorts object keys, independent of serde_json's map ordering
+fn canonicalize(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(entries.into_iter().map(|(k, v)| (k, canonicalize(v))).collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonicalize).collect()),
+        other => other,
+    }
+}
@@ -0,0 +1,391 @@
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use wstd::http::Body;
+use wstd::io::AsyncRead;
+
+/// Start of every boundary; the rest is derived from the parts
+const BOUNDARY_PREFIX: &str = "regen-oracle-";
+
+/// Hex digits of the parts' hash in a boundary, 128 bits
+const BOUNDARY_HASH_LEN: usize = 32;
+
+/// A part of a form under construction
+enum Part<'a, R> {
+    Text { name: String, value: String },
+    Directory { path: String },
+    Bytes { filename: String, data: &'a [u8] },
+    Stream { filename: String, source: R, len: Option<usize> },
+}
+
+/// Builds a multipart/form-data body from text fields and files held in memory or
+/// read from a stream
+///
+/// Files are sent under the `file` field with the filename given, which may contain
+/// `/` to place it in a directory declared before it. The boundary is derived from a
+/// hash of the parts instead of drawn at random, so the same parts always give the
+/// same request. In memory content and text are checked not to contain it, and the
+/// next candidate is taken if they do; streamed content is only hashed by length, and
+/// would have to contain the 128-bit boundary by chance.
+pub struct MultipartBuilder<'a, R> {
+    parts: Vec<Part<'a, R>>,
+    url_escaped: bool,
+}
+
+impl<'a, R: AsyncRead> Default for MultipartBuilder<'a, R> {
+    fn default() -> Self {
+        Self { parts: Vec::new(), url_escaped: false }
+    }
+}
+
+impl<'a, R: AsyncRead> MultipartBuilder<'a, R> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Percent-encodes `%` and `+` in field names and filenames too, for servers that
+    /// URL-unescape them as Kubo does
+    pub fn url_escaped_names(mut self) -> Self {
+        self.url_escaped = true;
+        self
+    }
+
+    /// Adds a text field
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        self.parts.push(Part::Text { name: name.to_string(), value: value.to_string() });
+        self
+    }
+
+    /// Declares a directory, as Kubo's `add` expects before the files inside it
+    pub fn directory(mut self, path: &str) -> Self {
+        self.parts.push(Part::Directory { path: path.to_string() });
+        self
+    }
+
+    /// Adds a file whose content is in memory
+    pub fn file(mut self, filename: &str, data: &'a [u8]) -> Self {
+        self.parts.push(Part::Bytes { filename: filename.to_string(), data });
+        self
+    }
+
+    /// Adds a file whose content is read from `source`, `len` bytes long when known
+    pub fn stream(mut self, filename: &str, source: R, len: Option<usize>) -> Self {
+        self.parts.push(Part::Stream { filename: filename.to_string(), source, len });
+        self
+    }
+
+    /// Picks the boundary and lays out the body
+    pub fn build(self) -> Multipart<'a, R> {
+        let boundary = self.boundary();
+        let url_escaped = self.url_escaped;
+        let mut segments = VecDeque::with_capacity(self.parts.len() * 2 + 1);
+        let mut len = Some(0);
+        let mut push = |segments: &mut VecDeque<Segment<'a, R>>, segment: Segment<'a, R>| {
+            let segment_len = match &segment {
+                Segment::Owned(bytes) => Some(bytes.len()),
+                Segment::Borrowed(bytes) => Some(bytes.len()),
+                Segment::Stream(_, len) => *len,
+            };
+            len = len.zip(segment_len).map(|(a, b)| a + b);
+            segments.push_back(segment);
+        };
+
+        let mut separator = String::new();
+        for part in self.parts {
+            separator.push_str(&format!("--{}\r\n", boundary));
+            match part {
+                Part::Text { name, value } => {
+                    separator.push_str(&format!(
+                        "Content-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                        escape(&name, url_escaped),
+                        value
+                    ));
+                }
+                Part::Directory { path } => {
+                    separator.push_str(&format!(
+                        "Content-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+                        Content-Type: application/x-directory\r\n\r\n\r\n",
+                        escape(&path, url_escaped)
+                    ));
+                }
+                Part::Bytes { filename, data } => {
+                    separator.push_str(&file_header(&filename, url_escaped));
+                    push(
+                        &mut segments,
+                        Segment::Owned(std::mem::take(&mut separator).into_bytes()),
+                    );
+                    push(&mut segments, Segment::Borrowed(data));
+                    separator.push_str("\r\n");
+                }
+                Part::Stream { filename, source, len } => {
+                    separator.push_str(&file_header(&filename, url_escaped));
+                    push(
+                        &mut segments,
+                        Segment::Owned(std::mem::take(&mut separator).into_bytes()),
+                    );
+                    push(&mut segments, Segment::Stream(source, len));
+                    separator.push_str("\r\n");
+                }
+            }
+        }
+        separator.push_str(&format!("--{}--\r\n", boundary));
+        push(&mut segments, Segment::Owned(separator.into_bytes()));
+
+        Multipart { boundary, segments, pos: 0, len }
+    }
+
+    /// First candidate boundary that none of the in-memory parts contain
+    fn boundary(&self) -> String {
+        self.first_absent(&self.seed())
+    }
+
+    /// Hash of the parts the boundary candidates derive from
+    fn seed(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for part in &self.parts {
+            match part {
+                Part::Text { name, value } => {
+                    hash_field(&mut hasher, name.as_bytes());
+                    hash_field(&mut hasher, value.as_bytes());
+                }
+                Part::Directory { path } => hash_field(&mut hasher, path.as_bytes()),
+                Part::Bytes { filename, data } => {
+                    hash_field(&mut hasher, filename.as_bytes());
+                    hash_field(&mut hasher, data);
+                }
+                Part::Stream { filename, len, .. } => {
+                    hash_field(&mut hasher, filename.as_bytes());
+                    hash_field(&mut hasher, &len.map_or(u64::MAX, |len| len as u64).to_le_bytes());
+                }
+            }
+        }
+        hasher.finalize().into()
+    }
+
+    /// First boundary candidate of `seed` that none of the in-memory parts contain
+    ///
+    /// Candidates are distinct hashes, and the parts can only contain finitely many of
+    /// them, so the search ends.
+    fn first_absent(&self, seed: &[u8; 32]) -> String {
+        (0u32..)
+            .map(|attempt| candidate(seed, attempt))
+            .find(|boundary| {
+                self.parts.iter().all(|part| match part {
+                    Part::Text { value, .. } => !contains(value.as_bytes(), boundary.as_bytes()),
+                    Part::Bytes { data, .. } => !contains(data, boundary.as_bytes()),
+                    Part::Directory { .. } | Part::Stream { .. } => true,
+                })
+            })
+            .expect("some boundary candidate is absent from finite content")
+    }
+}
+
+/// Boundary candidate number `attempt` of `seed`
+fn candidate(seed: &[u8; 32], attempt: u32) -> String {
+    let digest = Sha256::new().chain_update(seed).chain_update(attempt.to_le_bytes());
+    let hex: String = digest.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}{}", BOUNDARY_PREFIX, &hex[..BOUNDARY_HASH_LEN])
+}
+
+/// Headers of a file part, after its boundary line
+fn file_header(filename: &str, url_escaped: bool) -> String {
+    format!(
+        "Content-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+        Content-Type: application/octet-stream\r\n\r\n",
+        escape(filename, url_escaped)
+    )
+}
+
+/// Percent-encodes the characters that would end a quoted header parameter
+///
+/// With `url_escaped`, for servers that URL-unescape the value and would read `+` as
+/// a space, `%` and `+` are encoded too, `%` first so the other escapes survive.
+fn escape(value: &str, url_escaped: bool) -> String {
+    let value =
+        if url_escaped { value.replace('%', "%25").replace('+', "%2B") } else { value.to_string() };
+    value.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Hashes a length-prefixed field, so adjacent fields cannot run into each other
+fn hash_field(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.update((bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+enum Segment<'a, R> {
+    Owned(Vec<u8>),
+    Borrowed(&'a [u8]),
+    Stream(R, Option<usize>),
+}
+
+/// A multipart/form-data body, read part by part
+pub struct Multipart<'a, R> {
+    boundary: String,
+    segments: VecDeque<Segment<'a, R>>,
+    /// Bytes of the front segment already read, for in-memory segments
+    pos: usize,
+    len: Option<usize>,
+}
+
+impl<'a, R> Multipart<'a, R> {
+    /// Value of the request's `Content-Type` header
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+}
+
+impl<'a, R: AsyncRead> AsyncRead for Multipart<'a, R> {
+    async fn read(&mut self, buf: &mut [u8]) -> wstd::io::Result<usize> {
+        while let Some(segment) = self.segments.front_mut() {
+            let n = match segment {
+                Segment::Owned(bytes) => copy(bytes, &mut self.pos, buf),
+                Segment::Borrowed(bytes) => copy(bytes, &mut self.pos, buf),
+                Segment::Stream(source, _) => source.read(buf).await?,
+            };
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            self.segments.pop_front();
+            self.pos = 0;
+        }
+        Ok(0)
+    }
+}
+
+impl<'a, R: AsyncRead> Body for Multipart<'a, R> {
+    fn len(&self) -> Option<usize> {
+        self.len
+    }
+}
+
+/// Copies the unread remainder of `bytes` into `buf`
+fn copy(bytes: &[u8], pos: &mut usize, buf: &mut [u8]) -> usize {
+    let n = buf.len().min(bytes.len() - *pos);
+    buf[..n].copy_from_slice(&bytes[*pos..*pos + n]);
+    *pos += n;
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wstd::io::{Cursor, Empty};
+
+    fn read<R: AsyncRead + 'static>(body: Multipart<'static, R>) -> Vec<u8> {
+        wstd::runtime::block_on(async move {
+            let mut body = body;
+            let mut out = Vec::new();
+            body.read_to_end(&mut out).await.unwrap();
+            out
+        })
+    }
+
+    #[test]
+    fn frames_parts() {
+        let body = MultipartBuilder::<Empty>::new()
+            .text("pinataOptions", "{\"cidVersion\":0}")
+            .directory("bundle")
+            .file("bundle/a.txt", b"hello world\n")
+            .build();
+        let boundary = body.boundary.clone();
+        assert_eq!(body.content_type(), format!("multipart/form-data; boundary={}", boundary));
+        assert!(boundary.starts_with(BOUNDARY_PREFIX));
+        assert_eq!(boundary.len(), BOUNDARY_PREFIX.len() + BOUNDARY_HASH_LEN);
+
+        let expected = format!(
+            "--{b}\r\n\
+             Content-Disposition: form-data; name=\"pinataOptions\"\r\n\r\n\
+             {{\"cidVersion\":0}}\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"bundle\"\r\n\
+             Content-Type: application/x-directory\r\n\r\n\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"bundle/a.txt\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n\
+             hello world\n\r\n\
+             --{b}--\r\n",
+            b = boundary
+        );
+        let len = body.len();
+        let bytes = read(body);
+        assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+        assert_eq!(len, Some(expected.len()));
+    }
+
+    #[test]
+    fn streams_with_and_without_length() {
+        let data = vec![7u8; 100_000];
+        let body = MultipartBuilder::new()
+            .stream("scene.tif", Cursor::new(data.clone()), Some(100_000))
+            .build();
+        let len = body.len();
+        let bytes = read(body);
+        assert_eq!(len, Some(bytes.len()));
+        assert!(contains(&bytes, &data));
+
+        let body = MultipartBuilder::new().stream("scene.tif", Cursor::new(data), None).build();
+        assert_eq!(body.len(), None);
+    }
+
+    #[test]
+    fn boundary_is_deterministic_and_absent_from_parts() {
+        let build = |text: &str| MultipartBuilder::<Empty>::new().text("a", text).boundary();
+        assert_eq!(build("x"), build("x"));
+        assert_ne!(build("x"), build("y"));
+
+        // content holding the first candidate moves the boundary to the next one
+        let seed = [7; 32];
+        let first = candidate(&seed, 0);
+        let content = format!("before {} after", first);
+        let builder = MultipartBuilder::<Empty>::new().file("a.txt", content.as_bytes());
+        assert_eq!(builder.first_absent(&seed), candidate(&seed, 1));
+        let both = format!("{}{}", first, candidate(&seed, 1));
+        let builder = MultipartBuilder::<Empty>::new().text("both", &both);
+        assert_eq!(builder.first_absent(&seed), candidate(&seed, 2));
+
+        // and the boundary of a built body appears only as delimiters
+        let body = MultipartBuilder::<Empty>::new().text("a", &first).text("b", &content).build();
+        let boundary = format!("--{}", body.boundary);
+        let text = String::from_utf8(read(body)).unwrap();
+        assert_eq!(text.matches(&boundary).count(), 3);
+        assert!(!first.contains(&boundary[2..]) && !content.contains(&boundary[2..]));
+    }
+
+    #[test]
+    fn escapes_header_parameters() {
+        assert_eq!(escape("a b+c.json", false), "a b+c.json");
+        assert_eq!(escape("100%.txt", false), "100%.txt");
+        assert_eq!(escape("%22\"\r\n", false), "%22%22%0D%0A");
+        assert_eq!(escape("a b+c.json", true), "a b%2Bc.json");
+        assert_eq!(escape("100%.txt", true), "100%25.txt");
+        // `%` first, so an existing escape is not decoded back
+        assert_eq!(escape("%22\"\r\n", true), "%2522%22%0D%0A");
+        assert_eq!(
+            file_header("x\"y", false).lines().next().unwrap(),
+            "Content-Disposition: form-data; name=\"file\"; filename=\"x%22y\""
+        );
+    }
+
+    #[test]
+    fn url_escapes_names_on_request() {
+        let header = |builder: MultipartBuilder<'static, Empty>| {
+            let text = String::from_utf8(read(builder.build())).unwrap();
+            text.lines().nth(1).unwrap().to_string()
+        };
+        let plain = MultipartBuilder::new().file("a+b%.txt", b"x");
+        assert_eq!(
+            header(plain),
+            "Content-Disposition: form-data; name=\"file\"; filename=\"a+b%.txt\""
+        );
+        let escaped = MultipartBuilder::new().url_escaped_names().directory("a+b");
+        assert_eq!(
+            header(escaped),
+            "Content-Disposition: form-data; name=\"file\"; filename=\"a%2Bb\""
+        );
+        let escaped = MultipartBuilder::new().url_escaped_names().text("a+b", "c+d");
+        assert_eq!(header(escaped), "Content-Disposition: form-data; name=\"a%2Bb\"");
+    }
+}
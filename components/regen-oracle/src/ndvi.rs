@@ -0,0 +1,42 @@
+use crate::index::SpectralIndex;
+use crate::raster::Raster;
+use anyhow::Result;
+
+pub use crate::index::{Rescale, Stats};
+
+/// Per-pixel NDVI, `(nir - red) / (nir + red)` over surface reflectance
+///
+/// Shorthand for [`SpectralIndex::Ndvi`], kept for callers of the original NDVI
+/// module; see [`SpectralIndex::compute`] for the pixels left NaN.
+pub fn ndvi(
+    red: &Raster<u16>,
+    red_rescale: Rescale,
+    nir: &Raster<u16>,
+    nir_rescale: Rescale,
+) -> Result<Raster<f32>> {
+    SpectralIndex::Ndvi.compute(&[(nir, nir_rescale), (red, red_rescale)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::GeoTransform;
+
+    #[test]
+    fn matches_the_index() {
+        let raster = |data: Vec<u16>| Raster {
+            width: data.len(),
+            height: 1,
+            data,
+            transform: GeoTransform([10.0, 0.0, 0.0, 0.0, -10.0, 0.0]),
+            nodata: Some(0),
+        };
+        let (red, nir) = (raster(vec![1500, 0, 900]), raster(vec![4500, 2000, 1500]));
+        let rescale = Rescale { scale: 1e-4, offset: -0.1 };
+
+        let result = ndvi(&red, rescale, &nir, rescale).unwrap();
+        assert_eq!(result.data[0], 0.75);
+        assert!(result.data[1].is_nan() && result.data[2].is_nan());
+        assert!(ndvi(&red, rescale, &raster(vec![1]), rescale).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use crate::change::ChangeDetection;
+use crate::composite::CompositeReport;
+use crate::index::SpectralIndex;
+use crate::request::parse_rfc3339;
+use crate::series::TimeSeries;
+use crate::stac::Item;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Fixed-point scale of `RegenResult.meanIndex` and `RegenResult.meanDelta`
+pub const INDEX_SCALE: f64 = 1e6;
+
+/// Fixed-point scale of the `RegenResult` pixel fractions (basis points)
+pub const FRACTION_SCALE: f64 = 10_000.0;
+
+/// Status codes of `RegenResult.status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum Status {
+    Ok = 0,
+    NoScene = 1,
+    IndexUnavailable = 2,
+}
+
+/// The oracle's answer, mirrored on-chain by the `RegenResult` ABI struct in `ITypes.sol`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegenOutput {
+    /// CID of the directory holding the metadata document and the files it links
+    pub cid: String,
+    pub item_id: String,
+    /// Scene acquisition time, unix seconds
+    pub scene_timestamp: u64,
+    /// Scene cloud cover in percent
+    pub cloud_cover: f64,
+    pub index: SpectralIndex,
+    pub mean_index: Option<f64>,
+    pub status: Status,
+    /// Change between the baseline and monitoring composites, in change detection mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<ChangeOutput>,
+}
+
+/// Summary of a change detection, mirrored by the change fields of `RegenResult`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeOutput {
+    /// Weighted mean of the per-pixel delta, monitoring minus baseline
+    pub mean_delta: f64,
+    pub improved_fraction: f64,
+    pub degraded_fraction: f64,
+    pub unchanged_fraction: f64,
+}
+
+impl RegenOutput {
+    /// Result for a query that matched no scene
+    pub fn no_scene(index: SpectralIndex) -> Self {
+        Self {
+            cid: String::new(),
+            item_id: String::new(),
+            scene_timestamp: 0,
+            cloud_cover: 0.0,
+            index,
+            mean_index: None,
+            status: Status::NoScene,
+            change: None,
+        }
+    }
+
+    /// Result for a processed scene
+    pub fn for_scene(
+        item: &Item,
+        cid: &str,
+        index: SpectralIndex,
+        mean_index: Option<f64>,
+    ) -> Result<Self> {
+        Ok(Self {
+            cid: cid.to_string(),
+            item_id: item.id.clone(),
+            scene_timestamp: parse_rfc3339(&item.properties.datetime)?,
+            cloud_cover: item.properties.cloud_cover.unwrap_or_default(),
+            index,
+            mean_index,
+            status: if mean_index.is_some() { Status::Ok } else { Status::IndexUnavailable },
+            change: None,
+        })
+    }
+
+    /// Result for a time series; the scene fields describe its latest observation
+    pub fn for_series(series: &TimeSeries, cid: &str) -> Result<Self> {
+        let Some(latest) = series.latest() else {
+            return Ok(Self::no_scene(series.index));
+        };
+        let mean_index = latest.stats.as_ref().map(|stats| stats.mean);
+        Ok(Self {
+            cid: cid.to_string(),
+            item_id: latest.item_id.clone(),
+            scene_timestamp: parse_rfc3339(&latest.datetime)?,
+            cloud_cover: latest.cloud_cover.unwrap_or_default(),
+            index: series.index,
+            mean_index,
+            status: if mean_index.is_some() { Status::Ok } else { Status::IndexUnavailable },
+            change: None,
+        })
+    }
+
+    /// Result for a change detection
+    ///
+    /// The scene fields describe `latest`, the most recent monitoring scene, and the
+    /// mean index is that of the monitoring composite.
+    pub fn for_change(change: &ChangeDetection, latest: &Item, cid: &str) -> Result<Self> {
+        let summary = &change.summary;
+        let delta = summary.delta.as_ref();
+        Ok(Self {
+            cid: cid.to_string(),
+            item_id: latest.id.clone(),
+            scene_timestamp: parse_rfc3339(&latest.properties.datetime)?,
+            cloud_cover: latest.properties.cloud_cover.unwrap_or_default(),
+            index: change.index,
+            mean_index: change.monitoring.stats.as_ref().map(|stats| stats.mean),
+            status: if delta.is_some() { Status::Ok } else { Status::IndexUnavailable },
+            change: delta.map(|delta| ChangeOutput {
+                mean_delta: delta.mean,
+                improved_fraction: summary.improved_fraction,
+                degraded_fraction: summary.degraded_fraction,
+                unchanged_fraction: summary.unchanged_fraction,
+            }),
+        })
+    }
+
+    /// Result for a composite
+    ///
+    /// The scene fields describe `latest`, the most recent scene composited, and the
+    /// mean index is that of the composite.
+    pub fn for_composite(report: &CompositeReport, latest: &Item, cid: &str) -> Result<Self> {
+        let mean_index = report.stats.as_ref().map(|stats| stats.mean);
+        Ok(Self {
+            cid: cid.to_string(),
+            item_id: latest.id.clone(),
+            scene_timestamp: parse_rfc3339(&latest.properties.datetime)?,
+            cloud_cover: latest.properties.cloud_cover.unwrap_or_default(),
+            index: report.index,
+            mean_index,
+            status: if mean_index.is_some() { Status::Ok } else { Status::IndexUnavailable },
+            change: None,
+        })
+    }
+
+    /// Cloud cover in basis points, as stored on-chain
+    pub fn cloud_cover_bps(&self) -> u16 {
+        (self.cloud_cover * 100.0).round().clamp(0.0, 10_000.0) as u16
+    }
+
+    /// Mean index value scaled by [`INDEX_SCALE`], 0 when unavailable
+    pub fn mean_index_fixed(&self) -> i32 {
+        self.mean_index.map_or(0, fixed)
+    }
+}
+
+impl ChangeOutput {
+    /// Mean delta scaled by [`INDEX_SCALE`]
+    pub fn mean_delta_fixed(&self) -> i32 {
+        fixed(self.mean_delta)
+    }
+
+    /// A pixel fraction in basis points, as stored on-chain
+    pub fn bps(fraction: f64) -> u16 {
+        (fraction * FRACTION_SCALE).round().clamp(0.0, FRACTION_SCALE) as u16
+    }
+}
+
+/// `value` scaled by [`INDEX_SCALE`], saturating at the `int32` range, with NaN as 0
+fn fixed(value: f64) -> i32 {
+    if value.is_nan() {
+        return 0;
+    }
+    (value * INDEX_SCALE).round().clamp(i32::MIN as f64, i32::MAX as f64) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(cloud_cover: Option<f64>) -> Item {
+        let mut properties = serde_json::json!({ "datetime": "2024-06-27T19:04:13.624000Z" });
+        if let Some(cloud_cover) = cloud_cover {
+            properties["eo:cloud_cover"] = cloud_cover.into();
+        }
+        serde_json::from_value(serde_json::json!({
+            "id": "S2B_10SEG_20240627_0_L2A",
+            "properties": properties,
+            "geometry": null
+        }))
+        .unwrap()
+    }
+
+    fn output(cloud_cover: f64, mean_index: Option<f64>) -> RegenOutput {
+        RegenOutput { cloud_cover, mean_index, ..RegenOutput::no_scene(SpectralIndex::Ndvi) }
+    }
+
+    fn change(mean_delta: f64) -> ChangeOutput {
+        ChangeOutput {
+            mean_delta,
+            improved_fraction: 0.0,
+            degraded_fraction: 0.0,
+            unchanged_fraction: 1.0,
+        }
+    }
+
+    #[test]
+    fn for_scene_status() {
+        let scene =
+            RegenOutput::for_scene(&item(Some(3.25)), "cid", SpectralIndex::Ndvi, Some(0.5))
+                .unwrap();
+        assert_eq!(scene.status, Status::Ok);
+        assert_eq!(scene.scene_timestamp, 1_719_515_053);
+        assert_eq!(scene.cloud_cover, 3.25);
+        assert_eq!(scene.item_id, "S2B_10SEG_20240627_0_L2A");
+
+        let unavailable =
+            RegenOutput::for_scene(&item(None), "cid", SpectralIndex::Ndvi, None).unwrap();
+        assert_eq!(unavailable.status, Status::IndexUnavailable);
+        assert_eq!(unavailable.cloud_cover, 0.0);
+        assert_eq!(unavailable.mean_index_fixed(), 0);
+
+        let none = RegenOutput::no_scene(SpectralIndex::Ndwi);
+        assert_eq!(none.status, Status::NoScene);
+        assert_eq!((none.status as u8, none.cloud_cover_bps(), none.mean_index_fixed()), (1, 0, 0));
+    }
+
+    #[test]
+    fn cloud_cover_bps() {
+        assert_eq!(output(0.0, None).cloud_cover_bps(), 0);
+        assert_eq!(output(12.345, None).cloud_cover_bps(), 1235);
+        assert_eq!(output(12.344, None).cloud_cover_bps(), 1234);
+        assert_eq!(output(100.0, None).cloud_cover_bps(), 10_000);
+        assert_eq!(output(100.5, None).cloud_cover_bps(), 10_000);
+        assert_eq!(output(-1.0, None).cloud_cover_bps(), 0);
+        assert_eq!(output(f64::NAN, None).cloud_cover_bps(), 0);
+    }
+
+    #[test]
+    fn mean_index_fixed() {
+        assert_eq!(output(0.0, Some(0.123_456_4)).mean_index_fixed(), 123_456);
+        assert_eq!(output(0.0, Some(0.123_456_6)).mean_index_fixed(), 123_457);
+        assert_eq!(output(0.0, Some(-0.123_456_6)).mean_index_fixed(), -123_457);
+        assert_eq!(output(0.0, Some(1.0)).mean_index_fixed(), 1_000_000);
+        assert_eq!(output(0.0, Some(-1.0)).mean_index_fixed(), -1_000_000);
+        assert_eq!(output(0.0, None).mean_index_fixed(), 0);
+        assert_eq!(output(0.0, Some(f64::NAN)).mean_index_fixed(), 0);
+        assert_eq!(output(0.0, Some(1e9)).mean_index_fixed(), i32::MAX);
+        assert_eq!(output(0.0, Some(-1e9)).mean_index_fixed(), i32::MIN);
+        assert_eq!(output(0.0, Some(f64::INFINITY)).mean_index_fixed(), i32::MAX);
+    }
+
+    #[test]
+    fn mean_delta_fixed() {
+        assert_eq!(change(0.25).mean_delta_fixed(), 250_000);
+        assert_eq!(change(-0.000_000_6).mean_delta_fixed(), -1);
+        assert_eq!(change(-0.000_000_4).mean_delta_fixed(), 0);
+        assert_eq!(change(f64::NAN).mean_delta_fixed(), 0);
+        assert_eq!(change(f64::NEG_INFINITY).mean_delta_fixed(), i32::MIN);
+    }
+
+    #[test]
+    fn fraction_bps() {
+        assert_eq!(ChangeOutput::bps(0.0), 0);
+        assert_eq!(ChangeOutput::bps(0.123_45), 1235);
+        assert_eq!(ChangeOutput::bps(0.123_44), 1234);
+        assert_eq!(ChangeOutput::bps(1.0), 10_000);
+        assert_eq!(ChangeOutput::bps(1.2), 10_000);
+        assert_eq!(ChangeOutput::bps(-0.1), 0);
+        assert_eq!(ChangeOutput::bps(f64::NAN), 0);
+    }
+}
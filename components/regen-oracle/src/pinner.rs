@@ -0,0 +1,572 @@
+use crate::cid::{self, CidVersion};
+use crate::config::{
+    IPFS_CID_VERSION, IPFS_LOCAL_DIR, IPFS_PINNER, KUBO_API_AUTH, KUBO_API_URL, LIGHTHOUSE_API_KEY,
+    LIGHTHOUSE_API_URL, PINATA_API_URL, PINATA_JWT,
+};
+use crate::multipart::MultipartBuilder;
+use anyhow::Result;
+use serde::Deserialize;
+use std::{cell::RefCell, collections::BTreeMap, fmt, rc::Rc};
+use wstd::http::{Body, Request};
+use wstd::io::{AsyncRead, Empty};
+
+/// Lighthouse's Kubo-compatible `add` endpoint
+pub const DEFAULT_LIGHTHOUSE_URL: &str = "https://node.lighthouse.storage/api/v0/add";
+
+/// Pinata's pinning endpoint for files
+pub const DEFAULT_PINATA_URL: &str = "https://api.pinata.cloud/pinning/pinFileToIPFS";
+
+/// `add` endpoint of a Kubo node's RPC API on its default port
+pub const DEFAULT_KUBO_URL: &str = "http://127.0.0.1:5001/api/v0/add";
+
+/// A service that adds files to IPFS and keeps them pinned
+#[allow(async_fn_in_trait)]
+pub trait IpfsPinner {
+    /// CID version and DAG parameters the service imports files with
+    fn cid_version(&self) -> CidVersion;
+
+    /// Adds the file `filename` and returns the CID the service reports for it
+    async fn add(&self, filename: &str, data: &[u8]) -> Result<String>;
+
+    /// Adds the file `filename` read from `source` and returns the CID the service
+    /// reports for it
+    ///
+    /// `len` is the content length when known up front.
+    async fn add_stream<R: AsyncRead>(
+        &self,
+        filename: &str,
+        source: R,
+        len: Option<usize>,
+    ) -> Result<String>;
+
+    /// Adds a directory `name` holding `files` and returns the CID the service reports
+    /// for the directory
+    async fn add_directory(&self, name: &str, files: &[(&str, &[u8])]) -> Result<String>;
+}
+
+/// Pinning backend chosen by `ipfs_pinner`
+#[derive(Debug, Clone)]
+pub enum Pinner {
+    Lighthouse(Lighthouse),
+    Pinata(Pinata),
+    Kubo(Kubo),
+    Local(LocalPinner),
+}
+
+impl Pinner {
+    /// Builds the configured backend with its endpoint, credentials and CID version
+    ///
+    /// Each backend reads its own endpoint override, `lighthouse_api_url`,
+    /// `pinata_api_url` or `kubo_api_url`, so credentials are only ever sent to an
+    /// endpoint configured for their backend; for the local stand-in,
+    /// `ipfs_local_dir` optionally names a directory to keep files in.
+    pub fn load() -> Result<Self> {
+        let version = IPFS_CID_VERSION.parse()?;
+        match IPFS_PINNER.get()?.as_str() {
+            "lighthouse" => Ok(Pinner::Lighthouse(Lighthouse {
+                url: LIGHTHOUSE_API_URL.get()?,
+                api_key: Secret(LIGHTHOUSE_API_KEY.get_secret()?),
+                version,
+            })),
+            "pinata" => Ok(Pinner::Pinata(Pinata {
+                url: PINATA_API_URL.get()?,
+                jwt: Secret(PINATA_JWT.get_secret()?),
+                version,
+            })),
+            "kubo" => Ok(Pinner::Kubo(Kubo {
+                url: KUBO_API_URL.get()?,
+                authorization: KUBO_API_AUTH.get_secret().ok().map(Secret),
+                version,
+            })),
+            "local" => Ok(Pinner::Local(LocalPinner::new(version, IPFS_LOCAL_DIR.get().ok()))),
+            other => Err(anyhow::anyhow!(
+                "Unknown IPFS pinner `{}`: expected lighthouse, pinata, kubo or local",
+                other
+            )),
+        }
+    }
+}
+
+impl IpfsPinner for Pinner {
+    fn cid_version(&self) -> CidVersion {
+        match self {
+            Pinner::Lighthouse(pinner) => pinner.cid_version(),
+            Pinner::Pinata(pinner) => pinner.cid_version(),
+            Pinner::Kubo(pinner) => pinner.cid_version(),
+            Pinner::Local(pinner) => pinner.cid_version(),
+        }
+    }
+
+    async fn add(&self, filename: &str, data: &[u8]) -> Result<String> {
+        match self {
+            Pinner::Lighthouse(pinner) => pinner.add(filename, data).await,
+            Pinner::Pinata(pinner) => pinner.add(filename, data).await,
+            Pinner::Kubo(pinner) => pinner.add(filename, data).await,
+            Pinner::Local(pinner) => pinner.add(filename, data).await,
+        }
+    }
+
+    async fn add_stream<R: AsyncRead>(
+        &self,
+        filename: &str,
+        source: R,
+        len: Option<usize>,
+    ) -> Result<String> {
+        match self {
+            Pinner::Lighthouse(pinner) => pinner.add_stream(filename, source, len).await,
+            Pinner::Pinata(pinner) => pinner.add_stream(filename, source, len).await,
+            Pinner::Kubo(pinner) => pinner.add_stream(filename, source, len).await,
+            Pinner::Local(pinner) => pinner.add_stream(filename, source, len).await,
+        }
+    }
+
+    async fn add_directory(&self, name: &str, files: &[(&str, &[u8])]) -> Result<String> {
+        match self {
+            Pinner::Lighthouse(pinner) => pinner.add_directory(name, files).await,
+            Pinner::Pinata(pinner) => pinner.add_directory(name, files).await,
+            Pinner::Kubo(pinner) => pinner.add_directory(name, files).await,
+            Pinner::Local(pinner) => pinner.add_directory(name, files).await,
+        }
+    }
+}
+
+/// A credential, kept out of `Debug` output
+#[derive(Clone)]
+struct Secret(String);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+/// Lighthouse, authenticated with a bearer API key
+///
+/// Lighthouse imports files as CIDv0 and does not take import options, so
+/// `ipfs_cid_version` must stay `0` for the local CIDs to match.
+#[derive(Debug, Clone)]
+pub struct Lighthouse {
+    url: String,
+    api_key: Secret,
+    version: CidVersion,
+}
+
+impl IpfsPinner for Lighthouse {
+    fn cid_version(&self) -> CidVersion {
+        self.version
+    }
+
+    async fn add(&self, filename: &str, data: &[u8]) -> Result<String> {
+        self.send(MultipartBuilder::<Empty>::new().file(filename, data), filename).await
+    }
+
+    async fn add_stream<R: AsyncRead>(
+        &self,
+        filename: &str,
+        source: R,
+        len: Option<usize>,
+    ) -> Result<String> {
+        self.send(MultipartBuilder::new().stream(filename, source, len), filename).await
+    }
+
+    async fn add_directory(&self, name: &str, files: &[(&str, &[u8])]) -> Result<String> {
+        self.send(kubo_directory_form(name, files), name).await
+    }
+}
+
+impl Lighthouse {
+    async fn send<R: AsyncRead>(
+        &self,
+        form: MultipartBuilder<'_, R>,
+        name: &str,
+    ) -> Result<String> {
+        let authorization = format!("Bearer {}", self.api_key.0);
+        let response = post(&self.url, Some(&authorization), form).await?;
+        read_kubo_response(&response, name)
+    }
+}
+
+/// Pinata, authenticated with a JWT; the CID version is sent as a pinning option
+#[derive(Debug, Clone)]
+pub struct Pinata {
+    url: String,
+    jwt: Secret,
+    version: CidVersion,
+}
+
+impl IpfsPinner for Pinata {
+    fn cid_version(&self) -> CidVersion {
+        self.version
+    }
+
+    async fn add(&self, filename: &str, data: &[u8]) -> Result<String> {
+        self.send(MultipartBuilder::<Empty>::new().file(filename, data)).await
+    }
+
+    async fn add_stream<R: AsyncRead>(
+        &self,
+        filename: &str,
+        source: R,
+        len: Option<usize>,
+    ) -> Result<String> {
+        self.send(MultipartBuilder::new().stream(filename, source, len)).await
+    }
+    /// Pinata treats files sharing a leading path segment as one directory
+    async fn add_directory(&self, name: &str, files: &[(&str, &[u8])]) -> Result<String> {
+        let paths: Vec<String> =
+            files.iter().map(|(file, _)| format!("{}/{}", name, file)).collect();
+        let mut form = MultipartBuilder::<Empty>::new();
+        for (path, &(_, data)) in paths.iter().zip(files) {
+            form = form.file(path, data);
+        }
+        self.send(form).await
+    }
+}
+
+impl Pinata {
+    /// Completes `form` with the pinning options
+    fn form<'a, R: AsyncRead>(&self, form: MultipartBuilder<'a, R>) -> MultipartBuilder<'a, R> {
+        let options = format!("{{\"cidVersion\":{}}}", version_number(self.version));
+        form.text("pinataOptions", &options)
+    }
+
+    async fn send<R: AsyncRead>(&self, form: MultipartBuilder<'_, R>) -> Result<String> {
+        let form = self.form(form);
+        let authorization = format!("Bearer {}", self.jwt.0);
+        let response = post(&self.url, Some(&authorization), form).await?;
+
+        #[allow(non_snake_case)]
+        #[derive(Debug, Deserialize)]
+        struct PinataResponse {
+            IpfsHash: String,
+        }
+
+        serde_json::from_slice::<PinataResponse>(&response)
+            .map(|resp| resp.IpfsHash)
+            .map_err(|e| unexpected_response(e, &response))
+    }
+}
+
+/// A Kubo node's RPC `add` endpoint, with an optional `Authorization` header for
+/// nodes behind an authenticating proxy
+///
+/// The CID version is passed as `cid-version`; Kubo switches to raw leaves for CIDv1.
+#[derive(Debug, Clone)]
+pub struct Kubo {
+    url: String,
+    authorization: Option<Secret>,
+    version: CidVersion,
+}
+
+impl IpfsPinner for Kubo {
+    fn cid_version(&self) -> CidVersion {
+        self.version
+    }
+
+    async fn add(&self, filename: &str, data: &[u8]) -> Result<String> {
+        self.send(MultipartBuilder::<Empty>::new().file(filename, data), filename).await
+    }
+
+    async fn add_stream<R: AsyncRead>(
+        &self,
+        filename: &str,
+        source: R,
+        len: Option<usize>,
+    ) -> Result<String> {
+        self.send(MultipartBuilder::new().stream(filename, source, len), filename).await
+    }
+
+    async fn add_directory(&self, name: &str, files: &[(&str, &[u8])]) -> Result<String> {
+        self.send(kubo_directory_form(name, files), name).await
+    }
+}
+
+impl Kubo {
+    /// Completes `form` for Kubo, which URL-unescapes the names in it
+    fn form<'a, R: AsyncRead>(&self, form: MultipartBuilder<'a, R>) -> MultipartBuilder<'a, R> {
+        form.url_escaped_names()
+    }
+
+    async fn send<R: AsyncRead>(
+        &self,
+        form: MultipartBuilder<'_, R>,
+        name: &str,
+    ) -> Result<String> {
+        let form = self.form(form);
+        let separator = if self.url.contains('?') { '&' } else { '?' };
+        let url = format!("{}{}cid-version={}", self.url, separator, version_number(self.version));
+        let authorization = self.authorization.as_ref().map(|auth| auth.0.as_str());
+        let response = post(&url, authorization, form).await?;
+        read_kubo_response(&response, name)
+    }
+}
+
+/// Stand-in that keeps files in memory, and in a directory when one is given, keyed by
+/// their locally computed CID
+///
+/// Nothing is published; it lets the component run without pinning credentials.
+#[derive(Debug, Clone)]
+pub struct LocalPinner {
+    version: CidVersion,
+    dir: Option<String>,
+    files: Rc<RefCell<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl LocalPinner {
+    pub fn new(version: CidVersion, dir: Option<String>) -> Self {
+        Self { version, dir, files: Rc::default() }
+    }
+
+    /// Content added under `cid`, or `cid/name` for a file of a directory, from
+    /// memory or the directory
+    pub fn get(&self, cid: &str) -> Option<Vec<u8>> {
+        if let Some(data) = self.files.borrow().get(cid) {
+            return Some(data.clone());
+        }
+        let dir = self.dir.as_ref()?;
+        std::fs::read(format!("{}/{}", dir, cid)).ok()
+    }
+}
+
+impl IpfsPinner for LocalPinner {
+    fn cid_version(&self) -> CidVersion {
+        self.version
+    }
+
+    async fn add(&self, filename: &str, data: &[u8]) -> Result<String> {
+        self.store(filename, data.to_vec())
+    }
+
+    async fn add_stream<R: AsyncRead>(
+        &self,
+        filename: &str,
+        mut source: R,
+        _len: Option<usize>,
+    ) -> Result<String> {
+        let mut data = Vec::new();
+        source.read_to_end(&mut data).await?;
+        self.store(filename, data)
+    }
+
+    async fn add_directory(&self, name: &str, files: &[(&str, &[u8])]) -> Result<String> {
+        let root = cid::directory(files, self.version)?.to_string();
+        if let Some(dir) = &self.dir {
+            std::fs::create_dir_all(format!("{}/{}", dir, root))
+                .map_err(|e| anyhow::anyhow!("Failed to create {}/{}: {}", dir, root, e))?;
+        }
+        for &(file, data) in files {
+            self.store(file, data.to_vec())?;
+            let path = format!("{}/{}", root, file);
+            if let Some(dir) = &self.dir {
+                std::fs::write(format!("{}/{}", dir, path), data)?;
+            }
+            self.files.borrow_mut().insert(path, data.to_vec());
+        }
+        eprintln!("Stored directory {} locally as {}", name, root);
+        Ok(root)
+    }
+}
+
+impl LocalPinner {
+    fn store(&self, filename: &str, data: Vec<u8>) -> Result<String> {
+        let cid = cid::compute(&data, self.version).to_string();
+        if let Some(dir) = &self.dir {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", dir, e))?;
+            std::fs::write(format!("{}/{}", dir, cid), &data)?;
+        }
+        eprintln!("Stored {} locally as {}", filename, cid);
+        self.files.borrow_mut().insert(cid.clone(), data);
+        Ok(cid)
+    }
+}
+
+fn version_number(version: CidVersion) -> u8 {
+    match version {
+        CidVersion::V0 => 0,
+        CidVersion::V1 => 1,
+    }
+}
+
+/// Sends a multipart upload and returns the response body of a successful one
+async fn post<R: AsyncRead>(
+    url: &str,
+    authorization: Option<&str>,
+    form: MultipartBuilder<'_, R>,
+) -> Result<Vec<u8>> {
+    let body = form.build();
+    let mut request = Request::post(url).header("Content-Type", &body.content_type());
+    if let Some(authorization) = authorization {
+        request = request.header("Authorization", authorization);
+    }
+    if let Some(len) = body.len() {
+        request = request.header("Content-Length", &len.to_string());
+    }
+
+    let mut response = wstd::http::Client::new().send(request.body(body)?).await?;
+    let mut body_buf = Vec::new();
+    response.body_mut().read_to_end(&mut body_buf).await?;
+
+    if response.status().is_success() {
+        // Log the raw response for debugging
+        eprintln!("IPFS API Response: {}", String::from_utf8_lossy(&body_buf));
+        Ok(body_buf)
+    } else {
+        let error_body = std::str::from_utf8(&body_buf).unwrap_or("unable to read error body");
+        Err(anyhow::anyhow!(
+            "Failed to upload to IPFS. Status: {:?}, Body: {}",
+            response.status(),
+            error_body
+        ))
+    }
+}
+
+/// Form of a Kubo-style directory upload: the directory, then the files inside it
+fn kubo_directory_form<'a>(name: &str, files: &[(&str, &'a [u8])]) -> MultipartBuilder<'a, Empty> {
+    let mut form = MultipartBuilder::new().directory(name);
+    for &(file, data) in files {
+        form = form.file(&format!("{}/{}", name, file), data);
+    }
+    form
+}
+
+/// Extracts the CID of the entry `name` from a Kubo-style `add` response
+///
+/// The response holds one JSON object (capitalized fields) per line, one for every
+/// file and directory added. Without an entry named `name`, the entry with an empty
+/// name, a directory the service wrapped the upload in, is taken instead.
+fn read_kubo_response(body: &[u8], name: &str) -> Result<String> {
+    #[allow(non_snake_case)]
+    #[derive(Debug, Deserialize)]
+    struct AddResponse {
+        Name: String,
+        Hash: String,
+    }
+
+    let entries = body
+        .split(|&byte| byte == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .map(serde_json::from_slice::<AddResponse>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| unexpected_response(e, body))?;
+    let named = |wanted: &str| entries.iter().find(|entry| entry.Name == wanted);
+    named(name).or_else(|| named("")).map(|entry| entry.Hash.clone()).ok_or_else(|| {
+        anyhow::anyhow!(
+            "IPFS API response has no entry for {}: {}",
+            name,
+            String::from_utf8_lossy(body)
+        )
+    })
+}
+
+fn unexpected_response(e: serde_json::Error, body: &[u8]) -> anyhow::Error {
+    anyhow::anyhow!("Unexpected IPFS API response ({}): {}", e, String::from_utf8_lossy(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &[u8] = b"hello world\n";
+
+    #[test]
+    fn reads_a_single_file() {
+        let body = br#"{"Name":"scene.json","Hash":"QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o","Size":"20"}
+"#;
+        assert_eq!(
+            read_kubo_response(body, "scene.json").unwrap(),
+            "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o"
+        );
+        // an entry under another name is not the file
+        let err = read_kubo_response(body, "other.json").unwrap_err();
+        assert!(err.to_string().starts_with("IPFS API response has no entry for other.json"));
+    }
+
+    #[test]
+    fn reads_a_directory() {
+        let body = br#"{"Name":"bundle/a.txt","Hash":"QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o","Size":"20"}
+{"Name":"bundle","Hash":"QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn","Size":"4"}
+{"Name":"","Hash":"QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH","Size":"60"}
+"#;
+        assert_eq!(
+            read_kubo_response(body, "bundle").unwrap(),
+            "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn"
+        );
+        assert_eq!(
+            read_kubo_response(body, "bundle/a.txt").unwrap(),
+            "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o"
+        );
+
+        // a service that wraps the upload reports the wrapper under the empty name
+        let wrapped = br#"{"Name":"","Hash":"QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn"}"#;
+        assert_eq!(
+            read_kubo_response(wrapped, "bundle").unwrap(),
+            "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn"
+        );
+    }
+
+    #[test]
+    fn rejects_error_bodies() {
+        let body = br#"{"Message":"invalid path","Code":0,"Type":"error"}"#;
+        let err = read_kubo_response(body, "scene.json").unwrap_err();
+        assert!(err.to_string().starts_with("Unexpected IPFS API response"));
+        assert!(read_kubo_response(b"", "scene.json").is_err());
+    }
+
+    #[test]
+    fn local_pinner_matches_computed_cids() {
+        for version in [CidVersion::V0, CidVersion::V1] {
+            let pinner = LocalPinner::new(version, None);
+            let files: [(&str, &[u8]); 2] = [("a.txt", HELLO), ("b.json", b"{}")];
+            // clones share the stored files
+            let store = pinner.clone();
+            let (file, streamed, dir) = wstd::runtime::block_on(async move {
+                let file = store.add("a.txt", HELLO).await?;
+                let cursor = wstd::io::Cursor::new(b"{}".to_vec());
+                let streamed = store.add_stream("b.json", cursor, Some(2)).await?;
+                let dir = store.add_directory("bundle", &files).await?;
+                anyhow::Ok((file, streamed, dir))
+            })
+            .unwrap();
+
+            assert_eq!(file, cid::compute(HELLO, version).to_string());
+            assert_eq!(streamed, cid::compute(b"{}", version).to_string());
+            assert_eq!(dir, cid::directory(&files, version).unwrap().to_string());
+            assert_eq!(pinner.get(&file).unwrap(), HELLO);
+            assert_eq!(pinner.get(&format!("{}/b.json", dir)).unwrap(), b"{}");
+            assert_eq!(pinner.get(&format!("{}/c.json", dir)), None);
+        }
+    }
+
+    /// The body `form` is sent as
+    fn body(form: MultipartBuilder<'static, Empty>) -> String {
+        let mut body = form.build();
+        let bytes = wstd::runtime::block_on(async move {
+            let mut bytes = Vec::new();
+            body.read_to_end(&mut bytes).await.map(|_| bytes)
+        })
+        .unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn escapes_names_for_kubo_only() {
+        let pinata = Pinata {
+            url: DEFAULT_PINATA_URL.to_string(),
+            jwt: Secret("jwt".to_string()),
+            version: CidVersion::V1,
+        };
+        let text = body(pinata.form(MultipartBuilder::new().file("a+b%.json", HELLO)));
+        assert!(text.contains("filename=\"a+b%.json\""), "{}", text);
+        assert!(text.contains("{\"cidVersion\":1}"));
+
+        let kubo = Kubo {
+            url: DEFAULT_KUBO_URL.to_string(),
+            authorization: None,
+            version: CidVersion::V0,
+        };
+        let text = body(kubo.form(kubo_directory_form("a+b", &[("c%.json", HELLO)])));
+        assert!(text.contains("filename=\"a%2Bb\""), "{}", text);
+        assert!(text.contains("filename=\"a%2Bb/c%25.json\""), "{}", text);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::aoi::Aoi;
+use crate::raster::Raster;
+use anyhow::Result;
+use miniz_oxide::deflate::compress_to_vec_zlib;
+
+/// Index values mapped onto the ends of the colormap; values outside saturate
+pub const PREVIEW_RANGE: (f32, f32) = (0.0, 1.0);
+
+/// ColorBrewer RdYlGn, 11 classes, from the lowest to the highest value
+const RDYLGN: [[u8; 3]; 11] = [
+    [165, 0, 38],
+    [215, 48, 39],
+    [244, 109, 67],
+    [253, 174, 97],
+    [254, 224, 139],
+    [255, 255, 191],
+    [217, 239, 139],
+    [166, 217, 106],
+    [102, 189, 99],
+    [26, 152, 80],
+    [0, 104, 55],
+];
+
+/// RGBA of the AOI outline
+const OUTLINE: [u8; 4] = [33, 33, 33, 255];
+
+/// zlib level of the image data, fixed so every operator writes the same bytes
+const DEFLATE_LEVEL: u8 = 6;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// An RGBA image, row-major
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+/// Renders `raster` with the red-yellow-green colormap over [`PREVIEW_RANGE`]
+///
+/// The raster is shrunk by the smallest whole factor that brings its longer side to
+/// `max_dim` pixels or fewer, averaging the valid values of each block. Blocks
+/// without any are transparent. `outline`, in the raster's CRS, is drawn over the
+/// result.
+pub fn render(raster: &Raster<f32>, outline: Option<&Aoi>, max_dim: usize) -> Result<Image> {
+    if raster.width == 0 || raster.height == 0 || max_dim == 0 {
+        return Err(anyhow::anyhow!("Cannot render an empty preview"));
+    }
+    let factor = raster.width.max(raster.height).div_ceil(max_dim);
+    let (width, height) = (raster.width.div_ceil(factor), raster.height.div_ceil(factor));
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in 0..height {
+        for col in 0..width {
+            let (mut sum, mut n) = (0.0, 0u32);
+            for r in row * factor..((row + 1) * factor).min(raster.height) {
+                for c in col * factor..((col + 1) * factor).min(raster.width) {
+                    let value = raster.get(c, r);
+                    if !value.is_nan() {
+                        sum += f64::from(value);
+                        n += 1;
+                    }
+                }
+            }
+            pixels.push(if n == 0 { [0; 4] } else { colormap((sum / f64::from(n)) as f32) });
+        }
+    }
+    let mut image = Image { width, height, pixels };
+
+    if let Some(aoi) = outline {
+        let to_pixel = |[x, y]: [f64; 2]| {
+            raster.transform.invert(x, y).map(|(c, r)| (c / factor as f64, r / factor as f64))
+        };
+        for polygon in &aoi.polygons {
+            for ring in std::iter::once(&polygon.exterior).chain(&polygon.holes) {
+                let points: Vec<_> = ring.iter().filter_map(|&p| to_pixel(p)).collect();
+                for (i, &from) in points.iter().enumerate() {
+                    image.line(from, points[(i + 1) % points.len()]);
+                }
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// Color of `value` on the red-yellow-green ramp
+fn colormap(value: f32) -> [u8; 4] {
+    let (min, max) = PREVIEW_RANGE;
+    let t = ((value - min) / (max - min)).clamp(0.0, 1.0) * (RDYLGN.len() - 1) as f32;
+    let i = (t.floor() as usize).min(RDYLGN.len() - 2);
+    let frac = t - i as f32;
+    let (a, b) = (RDYLGN[i], RDYLGN[i + 1]);
+    let lerp =
+        |k: usize| (f32::from(a[k]) + (f32::from(b[k]) - f32::from(a[k])) * frac).round() as u8;
+    [lerp(0), lerp(1), lerp(2), 255]
+}
+
+impl Image {
+    /// Draws a one-pixel line between two pixel positions, clipped to the image
+    fn line(&mut self, (x0, y0): (f64, f64), (x1, y1): (f64, f64)) {
+        let (mut x, mut y) = (x0.floor() as i64, y0.floor() as i64);
+        let (x1, y1) = (x1.floor() as i64, y1.floor() as i64);
+        let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+        let (sx, sy) = (if x < x1 { 1 } else { -1 }, if y < y1 { 1 } else { -1 });
+        let mut err = dx + dy;
+        loop {
+            if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+                self.pixels[y as usize * self.width + x as usize] = OUTLINE;
+            }
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Encodes the image as an 8-bit RGBA PNG
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // bit depth 8, color type 6 (RGBA), deflate, adaptive filtering, no interlace
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let mut scanlines = Vec::with_capacity(self.height * (1 + self.width * 4));
+        for row in self.pixels.chunks(self.width) {
+            // filter type 0, none
+            scanlines.push(0);
+            scanlines.extend(row.iter().flatten());
+        }
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &ihdr);
+        write_chunk(&mut png, b"IDAT", &compress_to_vec_zlib(&scanlines, DEFLATE_LEVEL));
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+/// Appends a PNG chunk: length, type, data and the CRC of type and data
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// CRC-32 (ISO 3309) as used by PNG chunks
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::GeoTransform;
+
+    fn raster(width: usize, height: usize, value: impl Fn(usize, usize) -> f32) -> Raster<f32> {
+        let data = (0..width * height).map(|i| value(i % width, i / width)).collect();
+        Raster {
+            width,
+            height,
+            data,
+            transform: GeoTransform([1.0, 0.0, 0.0, 0.0, -1.0, height as f64]),
+            nodata: Some(f32::NAN),
+        }
+    }
+
+    #[test]
+    fn crc32_check_values() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn colormap_ends_and_middle() {
+        assert_eq!(colormap(0.0), [165, 0, 38, 255]);
+        assert_eq!(colormap(1.0), [0, 104, 55, 255]);
+        assert_eq!(colormap(0.5), [255, 255, 191, 255]);
+        // halfway between the third and fourth classes
+        assert_eq!(colormap(0.25), [249, 142, 82, 255]);
+        // saturates outside the range
+        assert_eq!(colormap(-0.7), colormap(0.0));
+        assert_eq!(colormap(3.0), colormap(1.0));
+    }
+
+    #[test]
+    fn downsamples_by_whole_factors() {
+        let shape = |width, height, max_dim| {
+            let image = render(&raster(width, height, |_, _| 0.5), None, max_dim).unwrap();
+            (image.width, image.height)
+        };
+        assert_eq!(shape(256, 100, 256), (256, 100));
+        assert_eq!(shape(512, 100, 256), (256, 50));
+        assert_eq!(shape(513, 100, 256), (171, 34));
+        assert_eq!(shape(10, 1000, 256), (3, 250));
+        assert!(render(&raster(0, 5, |_, _| 0.5), None, 256).is_err());
+        assert!(render(&raster(5, 5, |_, _| 0.5), None, 0).is_err());
+    }
+
+    #[test]
+    fn averages_valid_values_and_clears_empty_blocks() {
+        // 2x2 blocks: all NaN, one valid value, the mean of 0.0 and 1.0
+        let input = raster(6, 2, |col, row| match (col, row) {
+            (0..=1, _) => f32::NAN,
+            (2, 0) => 1.0,
+            (2..=3, _) => f32::NAN,
+            (4, _) => 0.0,
+            _ => 1.0,
+        });
+        let image = render(&input, None, 3).unwrap();
+        assert_eq!(image.pixels, [[0; 4], colormap(1.0), colormap(0.5)]);
+    }
+
+    #[test]
+    fn draws_the_outline() {
+        let aoi = Aoi::from_bbox([2.5, 2.5, 7.5, 7.5]);
+        let image = render(&raster(10, 10, |_, _| 0.5), Some(&aoi), 10).unwrap();
+        for row in 0..10 {
+            for col in 0..10 {
+                let inside = (2..=7).contains(&col) && (2..=7).contains(&row);
+                let edge = inside && (col == 2 || col == 7 || row == 2 || row == 7);
+                let expected = if edge { OUTLINE } else { colormap(0.5) };
+                assert_eq!(image.pixels[row * 10 + col], expected, "pixel ({}, {})", col, row);
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_png_chunks() {
+        let image = Image { width: 2, height: 1, pixels: vec![[1, 2, 3, 4], [5, 6, 7, 8]] };
+        let png = image.to_png();
+        assert_eq!(png[..8], PNG_SIGNATURE);
+
+        let mut chunks = Vec::new();
+        let mut at = 8;
+        while at < png.len() {
+            let len = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+            let body = &png[at + 4..at + 8 + len];
+            let crc = u32::from_be_bytes(png[at + 8 + len..at + 12 + len].try_into().unwrap());
+            assert_eq!(crc, crc32(body));
+            chunks.push((body[..4].to_vec(), body[4..].to_vec()));
+            at += 12 + len;
+        }
+        assert_eq!(at, png.len());
+
+        let kinds: Vec<_> = chunks.iter().map(|(kind, _)| kind.as_slice()).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        let scanlines = miniz_oxide::inflate::decompress_to_vec_zlib(&chunks[1].1).unwrap();
+        assert_eq!(scanlines, [0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(chunks[2].1.is_empty());
+        assert_eq!(png[png.len() - 4..], 0xae42_6082u32.to_be_bytes());
+    }
+}
@@ -0,0 +1,727 @@
+use crate::bands::BandAsset;
+use crate::cog::{fetch_range, ByteOrder, Cog, Compression, Ifd, Predictor};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Largest merged range request issued when fetching adjacent tiles
+const MAX_COALESCED_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Upper bound on a tile, compressed or decoded, guarding against corrupt byte
+/// counts and decompression bombs
+const MAX_TILE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Affine pixel-to-CRS transform `[a, b, c, d, e, f]`, in the STAC `proj:transform` order
+///
+/// `x = a * col + b * row + c` and `y = d * col + e * row + f`, where `(col, row)`
+/// addresses pixel corners, so `(0.5, 0.5)` is the center of the top-left pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoTransform(pub [f64; 6]);
+
+impl GeoTransform {
+    /// CRS coordinates of the pixel position `(col, row)`
+    pub fn apply(&self, col: f64, row: f64) -> (f64, f64) {
+        let [a, b, c, d, e, f] = self.0;
+        (a * col + b * row + c, d * col + e * row + f)
+    }
+
+    /// Pixel position of the CRS coordinates `(x, y)`, `None` for a degenerate transform
+    pub fn invert(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let [a, b, c, d, e, f] = self.0;
+        let det = a * e - b * d;
+        if det == 0.0 {
+            return None;
+        }
+        let (dx, dy) = (x - c, y - f);
+        Some(((e * dx - b * dy) / det, (a * dy - d * dx) / det))
+    }
+
+    /// Transform of the raster cut out by `window`
+    pub fn for_window(&self, window: &Window) -> Self {
+        let [a, b, c, d, e, f] = self.0;
+        let (col, row) = (window.col_off as f64, window.row_off as f64);
+        Self([a, b, c + a * col + b * row, d, e, f + d * col + e * row])
+    }
+}
+
+/// Axis-aligned rectangle in a raster's CRS
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bounds {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+/// Rectangle of pixels within a raster
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Window {
+    pub col_off: u64,
+    pub row_off: u64,
+    pub width: u64,
+    pub height: u64,
+}
+
+impl Window {
+    /// Smallest window of a `[rows, cols]` raster covering `bounds`
+    ///
+    /// Pixels partially inside `bounds` are included. Returns `None` when `bounds`
+    /// does not overlap the raster.
+    pub fn for_bounds(transform: &GeoTransform, shape: [u64; 2], bounds: &Bounds) -> Option<Self> {
+        // tolerance so that bounds on pixel edges do not pull in a neighbouring pixel
+        const EPSILON: f64 = 1e-9;
+
+        let corners = [
+            transform.invert(bounds.min_x, bounds.min_y)?,
+            transform.invert(bounds.min_x, bounds.max_y)?,
+            transform.invert(bounds.max_x, bounds.min_y)?,
+            transform.invert(bounds.max_x, bounds.max_y)?,
+        ];
+        let (mut min_col, mut min_row) = (f64::INFINITY, f64::INFINITY);
+        let (mut max_col, mut max_row) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for (col, row) in corners {
+            min_col = min_col.min(col);
+            min_row = min_row.min(row);
+            max_col = max_col.max(col);
+            max_row = max_row.max(row);
+        }
+
+        let [rows, cols] = shape;
+        let clamp = |v: f64, max: u64| v.clamp(0.0, max as f64) as u64;
+        let (col_start, col_end) =
+            (clamp((min_col + EPSILON).floor(), cols), clamp((max_col - EPSILON).ceil(), cols));
+        let (row_start, row_end) =
+            (clamp((min_row + EPSILON).floor(), rows), clamp((max_row - EPSILON).ceil(), rows));
+        if col_start >= col_end || row_start >= row_end {
+            return None;
+        }
+
+        Some(Self {
+            col_off: col_start,
+            row_off: row_start,
+            width: col_end - col_start,
+            height: row_end - row_start,
+        })
+    }
+}
+
+/// A dense, row-major single-band raster with its georeferencing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Raster<T> {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<T>,
+    pub transform: GeoTransform,
+    pub nodata: Option<T>,
+}
+
+impl<T: Copy> Raster<T> {
+    pub fn get(&self, col: usize, row: usize) -> T {
+        self.data[row * self.width + col]
+    }
+
+    /// Extent of the raster in its CRS
+    pub fn bounds(&self) -> Bounds {
+        let corners = [
+            self.transform.apply(0.0, 0.0),
+            self.transform.apply(self.width as f64, 0.0),
+            self.transform.apply(0.0, self.height as f64),
+            self.transform.apply(self.width as f64, self.height as f64),
+        ];
+        corners.iter().fold(
+            Bounds {
+                min_x: f64::INFINITY,
+                min_y: f64::INFINITY,
+                max_x: f64::NEG_INFINITY,
+                max_y: f64::NEG_INFINITY,
+            },
+            |b, &(x, y)| Bounds {
+                min_x: b.min_x.min(x),
+                min_y: b.min_y.min(y),
+                max_x: b.max_x.max(x),
+                max_y: b.max_y.max(y),
+            },
+        )
+    }
+
+    /// Resamples onto the `width` x `height` grid at `transform` by nearest neighbour
+    ///
+    /// Each target pixel takes the value of the source pixel containing its center;
+    /// target pixels outside the source are filled with the nodata value.
+    pub fn resample_nearest(&self, width: usize, height: usize, transform: GeoTransform) -> Self
+    where
+        T: Default,
+    {
+        let fill = self.nodata.unwrap_or_default();
+        let mut data = Vec::with_capacity(width * height);
+        for row in 0..height {
+            for col in 0..width {
+                let (x, y) = transform.apply(col as f64 + 0.5, row as f64 + 0.5);
+                let value = match self.transform.invert(x, y) {
+                    Some((c, r))
+                        if c >= 0.0
+                            && r >= 0.0
+                            && c < self.width as f64
+                            && r < self.height as f64 =>
+                    {
+                        self.get(c as usize, r as usize)
+                    }
+                    _ => fill,
+                };
+                data.push(value);
+            }
+        }
+        Self { width, height, data, transform, nodata: self.nodata }
+    }
+}
+
+/// Sample types that can be read from a COG
+pub trait Sample: Copy + Default + PartialEq {
+    /// `BitsPerSample` of the type
+    const BITS: u16;
+
+    /// `SampleFormat` of the type: 1 unsigned integer, 2 signed integer, 3 IEEE float
+    const FORMAT: u16;
+
+    /// Name of the type in messages, e.g. `uint16`
+    const NAME: &'static str;
+
+    fn read(byte_order: ByteOrder, bytes: &[u8]) -> Self;
+
+    /// Sum of the bit patterns, as the horizontal predictor adds samples
+    fn wrapping_add(self, other: Self) -> Self;
+
+    /// `value` as a sample, if it is exactly representable
+    fn from_f64(value: f64) -> Option<Self>;
+}
+
+impl Sample for u8 {
+    const BITS: u16 = 8;
+    const FORMAT: u16 = 1;
+    const NAME: &'static str = "uint8";
+
+    fn read(_: ByteOrder, bytes: &[u8]) -> Self {
+        bytes[0]
+    }
+
+    fn wrapping_add(self, other: Self) -> Self {
+        u8::wrapping_add(self, other)
+    }
+
+    fn from_f64(value: f64) -> Option<Self> {
+        (value.fract() == 0.0 && (0.0..=u8::MAX as f64).contains(&value)).then_some(value as u8)
+    }
+}
+
+impl Sample for u16 {
+    const BITS: u16 = 16;
+    const FORMAT: u16 = 1;
+    const NAME: &'static str = "uint16";
+
+    fn read(byte_order: ByteOrder, bytes: &[u8]) -> Self {
+        byte_order.u16(bytes)
+    }
+
+    fn wrapping_add(self, other: Self) -> Self {
+        u16::wrapping_add(self, other)
+    }
+
+    fn from_f64(value: f64) -> Option<Self> {
+        (value.fract() == 0.0 && (0.0..=u16::MAX as f64).contains(&value)).then_some(value as u16)
+    }
+}
+
+impl Sample for i16 {
+    const BITS: u16 = 16;
+    const FORMAT: u16 = 2;
+    const NAME: &'static str = "int16";
+
+    fn read(byte_order: ByteOrder, bytes: &[u8]) -> Self {
+        byte_order.u16(bytes) as i16
+    }
+
+    fn wrapping_add(self, other: Self) -> Self {
+        i16::wrapping_add(self, other)
+    }
+
+    fn from_f64(value: f64) -> Option<Self> {
+        let range = f64::from(i16::MIN)..=f64::from(i16::MAX);
+        (value.fract() == 0.0 && range.contains(&value)).then_some(value as i16)
+    }
+}
+
+impl Sample for f32 {
+    const BITS: u16 = 32;
+    const FORMAT: u16 = 3;
+    const NAME: &'static str = "float32";
+
+    fn read(byte_order: ByteOrder, bytes: &[u8]) -> Self {
+        f32::from_bits(byte_order.u32(bytes))
+    }
+
+    fn wrapping_add(self, other: Self) -> Self {
+        f32::from_bits(self.to_bits().wrapping_add(other.to_bits()))
+    }
+
+    fn from_f64(value: f64) -> Option<Self> {
+        (value.is_nan() || f64::from(value as f32) == value).then_some(value as f32)
+    }
+}
+
+/// Reads the pixels of `asset` covering `bounds`, given in the asset's CRS
+///
+/// Pixels are mapped with the asset's `proj:transform`, and the COG's dimensions
+/// are checked against its `proj:shape`. The asset's `raster:bands` nodata value
+/// takes precedence over the file's `GDAL_NODATA` tag.
+pub async fn read_band_window<T: Sample>(asset: &BandAsset, bounds: &Bounds) -> Result<Raster<T>> {
+    let cog = Cog::open(&asset.href).await?;
+    let image = cog.image();
+    if [image.height, image.width] != asset.shape {
+        return Err(anyhow::anyhow!(
+            "{} is {}x{} pixels but proj:shape is {:?}",
+            asset.href,
+            image.width,
+            image.height,
+            asset.shape
+        ));
+    }
+
+    let transform = GeoTransform(asset.transform);
+    let window = Window::for_bounds(&transform, asset.shape, bounds).ok_or_else(|| {
+        anyhow::anyhow!("{:?} does not intersect {} ({})", bounds, asset.key, asset.href)
+    })?;
+
+    let mut raster = read_window(&cog, &transform, &window).await?;
+    if let Some(nodata) = asset.nodata.and_then(T::from_f64) {
+        raster.nodata = Some(nodata);
+    }
+    Ok(raster)
+}
+
+/// Reads `window` from the full-resolution image of `cog`, a single-band raster of `T`
+///
+/// Only the tiles intersecting the window are fetched, with adjacent tiles merged
+/// into a single range request. Sparse tiles are filled with the nodata value.
+pub async fn read_window<T: Sample>(
+    cog: &Cog,
+    transform: &GeoTransform,
+    window: &Window,
+) -> Result<Raster<T>> {
+    let ifd = cog.image();
+    check_format::<T>(ifd, &cog.url)?;
+
+    let (tile_width, tile_height) = (ifd.tile_width, ifd.tile_height);
+    let (col_end, row_end) = (window.col_off + window.width, window.row_off + window.height);
+    if window.width == 0 || window.height == 0 || col_end > ifd.width || row_end > ifd.height {
+        return Err(anyhow::anyhow!("Window {:?} is outside {}", window, cog.url));
+    }
+
+    let mut tiles = Vec::new();
+    for tile_row in window.row_off / tile_height..=(row_end - 1) / tile_height {
+        for tile_col in window.col_off / tile_width..=(col_end - 1) / tile_width {
+            let (offset, len) = ifd.tile_range(tile_col, tile_row).ok_or_else(|| {
+                anyhow::anyhow!("Tile ({}, {}) missing from {}", tile_col, tile_row, cog.url)
+            })?;
+            if len > MAX_TILE_BYTES {
+                return Err(anyhow::anyhow!(
+                    "Tile ({}, {}) of {} is {} bytes, more than the {} supported",
+                    tile_col,
+                    tile_row,
+                    cog.url,
+                    len,
+                    MAX_TILE_BYTES
+                ));
+            }
+            tiles.push((tile_col, tile_row, offset, len));
+        }
+    }
+    let ranges: Vec<_> = tiles.iter().map(|&(_, _, offset, len)| (offset, len)).collect();
+    let payloads = fetch_ranges(&cog.url, &ranges).await?;
+
+    let nodata = ifd.nodata.and_then(T::from_f64);
+    let (width, height) = (window.width as usize, window.height as usize);
+    let mut data = vec![nodata.unwrap_or_default(); width * height];
+
+    for (&(tile_col, tile_row, _, len), payload) in tiles.iter().zip(payloads) {
+        if len == 0 {
+            continue;
+        }
+        let tile = decode_tile::<T>(ifd, cog.byte_order, &payload).map_err(|e| {
+            anyhow::anyhow!("Tile ({}, {}) of {}: {}", tile_col, tile_row, cog.url, e)
+        })?;
+
+        // overlap of the tile and the window, in image pixels
+        let (tile_x, tile_y) = (tile_col * tile_width, tile_row * tile_height);
+        let x0 = tile_x.max(window.col_off);
+        let x1 = (tile_x + tile_width).min(col_end);
+        let y0 = tile_y.max(window.row_off);
+        let y1 = (tile_y + tile_height).min(row_end);
+        let run = (x1 - x0) as usize;
+
+        for y in y0..y1 {
+            let src = ((y - tile_y) * tile_width + (x0 - tile_x)) as usize;
+            let dst = ((y - window.row_off) * window.width + (x0 - window.col_off)) as usize;
+            data[dst..dst + run].copy_from_slice(&tile[src..src + run]);
+        }
+    }
+
+    Ok(Raster { width, height, data, transform: transform.for_window(window), nodata })
+}
+
+fn check_format<T: Sample>(ifd: &Ifd, url: &str) -> Result<()> {
+    if ifd.bits_per_sample != T::BITS
+        || ifd.sample_format != T::FORMAT
+        || ifd.samples_per_pixel != 1
+    {
+        return Err(anyhow::anyhow!(
+            "{} is not a single-band {} raster ({} samples of {} bits, format {})",
+            url,
+            T::NAME,
+            ifd.samples_per_pixel,
+            ifd.bits_per_sample,
+            ifd.sample_format
+        ));
+    }
+    Ok(())
+}
+
+/// Fetches each `(offset, len)` range of `url`, merging ranges that are adjacent in the file
+async fn fetch_ranges(url: &str, ranges: &[(u64, u64)]) -> Result<Vec<Vec<u8>>> {
+    let mut payloads = vec![Vec::new(); ranges.len()];
+    for run in coalesce(ranges) {
+        let bytes = fetch_range(url, run.offset, run.len).await?;
+        if bytes.len() as u64 != run.len {
+            return Err(anyhow::anyhow!(
+                "Short read from {}: {} of {} bytes at {}",
+                url,
+                bytes.len(),
+                run.len,
+                run.offset
+            ));
+        }
+        for &i in &run.members {
+            let (offset, len) = ranges[i];
+            let at = (offset - run.offset) as usize;
+            payloads[i] = bytes[at..at + len as usize].to_vec();
+        }
+    }
+    Ok(payloads)
+}
+
+/// A range request covering one or more adjacent ranges
+#[derive(Debug, PartialEq)]
+struct Run {
+    offset: u64,
+    len: u64,
+    /// Indices of the ranges it covers, in file order
+    members: Vec<usize>,
+}
+
+/// Groups the non-empty `(offset, len)` ranges into runs of ranges that follow each
+/// other in the file, each at most [`MAX_COALESCED_BYTES`] long unless a single range
+/// is longer
+fn coalesce(ranges: &[(u64, u64)]) -> Vec<Run> {
+    let mut order: Vec<usize> = (0..ranges.len()).filter(|&i| ranges[i].1 > 0).collect();
+    order.sort_by_key(|&i| ranges[i].0);
+
+    let mut runs: Vec<Run> = Vec::new();
+    for i in order {
+        let (offset, len) = ranges[i];
+        match runs.last_mut() {
+            Some(run) if offset == run.offset + run.len && run.len + len <= MAX_COALESCED_BYTES => {
+                run.len += len;
+                run.members.push(i);
+            }
+            _ => runs.push(Run { offset, len, members: vec![i] }),
+        }
+    }
+    runs
+}
+
+/// Decompresses a tile and undoes its predictor
+///
+/// Decoding stops once the tile's `tile_width * tile_height` samples are out, so a
+/// corrupt stream cannot expand beyond the tile. The floating point predictor is
+/// undone on bytes, before the samples are assembled.
+pub(crate) fn decode_tile<T: Sample>(
+    ifd: &Ifd,
+    byte_order: ByteOrder,
+    payload: &[u8],
+) -> Result<Vec<T>> {
+    let sample_bytes = usize::from(T::BITS / 8);
+    let expected = ifd
+        .tile_width
+        .checked_mul(ifd.tile_height)
+        .and_then(|pixels| pixels.checked_mul(sample_bytes as u64))
+        .filter(|&bytes| bytes <= MAX_TILE_BYTES)
+        .ok_or_else(|| {
+            anyhow::anyhow!("{}x{} tiles are too large", ifd.tile_width, ifd.tile_height)
+        })? as usize;
+
+    let raw = match ifd.compression {
+        Compression::None => payload.to_vec(),
+        Compression::Deflate => {
+            miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(payload, expected)
+                .map_err(|e| anyhow::anyhow!("Deflate error: {:?}", e.status))?
+        }
+        Compression::Lzw => decode_lzw(payload, expected)?,
+        Compression::Other(code) => {
+            return Err(anyhow::anyhow!("Unsupported compression {}", code));
+        }
+    };
+
+    // LZW streams may carry trailing bytes past the last sample
+    let raw = raw
+        .get(..expected)
+        .ok_or_else(|| anyhow::anyhow!("Decoded {} bytes, expected {}", raw.len(), expected))?;
+
+    match ifd.predictor {
+        Predictor::None => {
+            Ok(raw.chunks_exact(sample_bytes).map(|b| T::read(byte_order, b)).collect())
+        }
+        Predictor::Horizontal => {
+            let mut samples: Vec<T> =
+                raw.chunks_exact(sample_bytes).map(|b| T::read(byte_order, b)).collect();
+            for row in samples.chunks_exact_mut(ifd.tile_width as usize) {
+                for i in 1..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - 1]);
+                }
+            }
+            Ok(samples)
+        }
+        Predictor::FloatingPoint if T::FORMAT == 3 => {
+            // each row holds the differenced bytes of its samples grouped by
+            // significance, most significant first, whatever the file's byte order
+            let width = ifd.tile_width as usize;
+            let mut samples = Vec::with_capacity(expected / sample_bytes);
+            let mut row = vec![0u8; width * sample_bytes];
+            let mut sample = vec![0u8; sample_bytes];
+            for encoded in raw.chunks_exact(row.len()) {
+                let mut sum = 0u8;
+                for (byte, &delta) in row.iter_mut().zip(encoded) {
+                    sum = sum.wrapping_add(delta);
+                    *byte = sum;
+                }
+                for i in 0..width {
+                    for (plane, byte) in sample.iter_mut().enumerate() {
+                        *byte = row[plane * width + i];
+                    }
+                    samples.push(T::read(ByteOrder::BigEndian, &sample));
+                }
+            }
+            Ok(samples)
+        }
+        other => Err(anyhow::anyhow!("Unsupported predictor {:?} for {}", other, T::NAME)),
+    }
+}
+
+/// Decodes at most `limit` bytes of a TIFF LZW stream
+fn decode_lzw(payload: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let mut decoder = weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8);
+    let mut out = vec![0; limit];
+    let (mut read, mut written) = (0, 0);
+    while written < limit {
+        let result = decoder.decode_bytes(&payload[read..], &mut out[written..]);
+        read += result.consumed_in;
+        written += result.consumed_out;
+        match result.status.map_err(|e| anyhow::anyhow!("LZW error: {}", e))? {
+            weezl::LzwStatus::Ok if result.consumed_in + result.consumed_out > 0 => {}
+            _ => break,
+        }
+    }
+    out.truncate(written);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10m grid of a Sentinel-2 tile, upper-left corner at (499980, 4200000)
+    const TRANSFORM: GeoTransform = GeoTransform([10.0, 0.0, 499_980.0, 0.0, -10.0, 4_200_000.0]);
+
+    fn bounds(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Bounds {
+        Bounds { min_x, min_y, max_x, max_y }
+    }
+
+    fn tile_ifd(tile_width: u64, tile_height: u64, compression: Compression) -> Ifd {
+        Ifd {
+            subfile_type: 0,
+            width: tile_width,
+            height: tile_height,
+            tile_width,
+            tile_height,
+            bits_per_sample: 16,
+            samples_per_pixel: 1,
+            sample_format: 1,
+            planar_configuration: 1,
+            compression,
+            predictor: Predictor::Horizontal,
+            tile_offsets: vec![0],
+            tile_byte_counts: vec![0],
+            pixel_scale: None,
+            tiepoints: Vec::new(),
+            model_transformation: None,
+            geo_keys: None,
+            nodata: None,
+        }
+    }
+
+    #[test]
+    fn window_for_bounds() {
+        let shape = [100, 200];
+        // exactly pixels 10..20 x 5..8, edges on pixel boundaries
+        let window = Window::for_bounds(
+            &TRANSFORM,
+            shape,
+            &bounds(500_080.0, 4_199_920.0, 500_180.0, 4_199_950.0),
+        );
+        assert_eq!(window, Some(Window { col_off: 10, row_off: 5, width: 10, height: 3 }));
+
+        // partially covered pixels are included
+        let window = Window::for_bounds(
+            &TRANSFORM,
+            shape,
+            &bounds(500_085.0, 4_199_915.0, 500_181.0, 4_199_949.0),
+        );
+        assert_eq!(window, Some(Window { col_off: 10, row_off: 5, width: 11, height: 4 }));
+    }
+
+    #[test]
+    fn window_clipped_to_raster() {
+        let shape = [100, 200];
+        let window = Window::for_bounds(
+            &TRANSFORM,
+            shape,
+            &bounds(499_000.0, 4_199_500.0, 500_000.0, 4_201_000.0),
+        );
+        assert_eq!(window, Some(Window { col_off: 0, row_off: 0, width: 2, height: 50 }));
+
+        let window = Window::for_bounds(
+            &TRANSFORM,
+            shape,
+            &bounds(501_900.0, 4_199_000.0, 510_000.0, 4_199_100.0),
+        );
+        assert_eq!(window, Some(Window { col_off: 192, row_off: 90, width: 8, height: 10 }));
+
+        // outside, or only touching the raster's edge
+        let outside = bounds(490_000.0, 4_199_000.0, 499_980.0, 4_199_500.0);
+        assert_eq!(Window::for_bounds(&TRANSFORM, shape, &outside), None);
+        let outside = bounds(510_000.0, 4_199_000.0, 511_000.0, 4_199_500.0);
+        assert_eq!(Window::for_bounds(&TRANSFORM, shape, &outside), None);
+        assert_eq!(Window::for_bounds(&GeoTransform([0.0; 6]), shape, &outside), None);
+    }
+
+    #[test]
+    fn resamples_by_nearest_pixel_center() {
+        // 2x2 raster of 20m pixels onto 4x4 of 10m pixels, shifted by one 10m pixel
+        let coarse = Raster {
+            width: 2,
+            height: 2,
+            data: vec![1u16, 2, 3, 4],
+            transform: GeoTransform([20.0, 0.0, 0.0, 0.0, -20.0, 40.0]),
+            nodata: Some(0),
+        };
+        let fine = coarse.resample_nearest(4, 4, GeoTransform([10.0, 0.0, 10.0, 0.0, -10.0, 40.0]));
+        assert_eq!(fine.data, [1, 2, 2, 0, 1, 2, 2, 0, 3, 4, 4, 0, 3, 4, 4, 0]);
+        assert_eq!((fine.width, fine.height, fine.nodata), (4, 4, Some(0)));
+
+        // without a nodata value, the type's default fills
+        let coarse = Raster { nodata: None, ..coarse };
+        let fine =
+            coarse.resample_nearest(1, 1, GeoTransform([10.0, 0.0, -50.0, 0.0, -10.0, 40.0]));
+        assert_eq!(fine.data, [0]);
+    }
+
+    #[test]
+    fn undoes_horizontal_predictor() {
+        // rows 10 12 15 11 and 65535 0 1 2, differenced and wrapping
+        let diffs: [u16; 8] = [10, 2, 3, 65532, 65535, 1, 1, 1];
+        let raw: Vec<u8> = diffs.iter().flat_map(|v| v.to_le_bytes()).collect();
+
+        let ifd = tile_ifd(4, 2, Compression::None);
+        let tile = decode_tile::<u16>(&ifd, ByteOrder::LittleEndian, &raw).unwrap();
+        assert_eq!(tile, [10, 12, 15, 11, 65535, 0, 1, 2]);
+
+        let deflated = miniz_oxide::deflate::compress_to_vec_zlib(&raw, 6);
+        let ifd = tile_ifd(4, 2, Compression::Deflate);
+        let tile = decode_tile::<u16>(&ifd, ByteOrder::LittleEndian, &deflated).unwrap();
+        assert_eq!(tile, [10, 12, 15, 11, 65535, 0, 1, 2]);
+
+        let lzw = weezl::encode::Encoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+            .encode(&raw)
+            .unwrap();
+        let ifd = tile_ifd(4, 2, Compression::Lzw);
+        let tile = decode_tile::<u16>(&ifd, ByteOrder::LittleEndian, &lzw).unwrap();
+        assert_eq!(tile, [10, 12, 15, 11, 65535, 0, 1, 2]);
+
+        let big_endian: Vec<u8> = diffs.iter().flat_map(|v| v.to_be_bytes()).collect();
+        let ifd = tile_ifd(4, 2, Compression::None);
+        let tile = decode_tile::<u16>(&ifd, ByteOrder::BigEndian, &big_endian).unwrap();
+        assert_eq!(tile, [10, 12, 15, 11, 65535, 0, 1, 2]);
+    }
+
+    #[test]
+    fn bounds_decoded_tiles() {
+        let bomb = miniz_oxide::deflate::compress_to_vec_zlib(&vec![0; 1 << 20], 6);
+        let ifd = tile_ifd(4, 2, Compression::Deflate);
+        assert!(decode_tile::<u16>(&ifd, ByteOrder::LittleEndian, &bomb).is_err());
+
+        let ifd = tile_ifd(4, 2, Compression::None);
+        assert!(decode_tile::<u16>(&ifd, ByteOrder::LittleEndian, &[0; 15]).is_err());
+
+        let ifd = tile_ifd(1 << 20, 1 << 20, Compression::None);
+        assert!(decode_tile::<u16>(&ifd, ByteOrder::LittleEndian, &[0; 16]).is_err());
+        let ifd = tile_ifd(u64::MAX, 2, Compression::None);
+        assert!(decode_tile::<u16>(&ifd, ByteOrder::LittleEndian, &[0; 16]).is_err());
+    }
+
+    #[test]
+    fn undoes_floating_point_predictor() {
+        // two rows of [1.0, -2.5]: planes 3f c0 | 80 20 | 00 00 | 00 00, then differenced
+        let ifd = Ifd {
+            bits_per_sample: 32,
+            sample_format: 3,
+            predictor: Predictor::FloatingPoint,
+            ..tile_ifd(2, 2, Compression::None)
+        };
+        let row = [0x3f, 0x81, 0xc0, 0xa0, 0xe0, 0, 0, 0];
+        let raw = [row, row].concat();
+        // the planes are big-endian whatever the file's byte order
+        for order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            assert_eq!(decode_tile::<f32>(&ifd, order, &raw).unwrap(), [1.0, -2.5, 1.0, -2.5]);
+        }
+
+        // integer samples cannot use it
+        let ifd = Ifd { bits_per_sample: 16, sample_format: 1, ..ifd };
+        let err = decode_tile::<u16>(&ifd, ByteOrder::LittleEndian, &[0; 8]).unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported predictor FloatingPoint for uint16");
+    }
+
+    #[test]
+    fn coalesces_adjacent_ranges() {
+        // out of file order, with a sparse tile and a gap
+        let ranges = [(300, 100), (100, 200), (0, 0), (510, 50), (400, 100)];
+        assert_eq!(
+            coalesce(&ranges),
+            [
+                Run { offset: 100, len: 400, members: vec![1, 0, 4] },
+                Run { offset: 510, len: 50, members: vec![3] },
+            ]
+        );
+
+        // runs are split where they would exceed the cap
+        let half = MAX_COALESCED_BYTES / 2;
+        let ranges = [(0, half), (half, half), (2 * half, 1), (2 * half + 1, MAX_COALESCED_BYTES)];
+        assert_eq!(
+            coalesce(&ranges),
+            [
+                Run { offset: 0, len: MAX_COALESCED_BYTES, members: vec![0, 1] },
+                Run { offset: 2 * half, len: 1, members: vec![2] },
+                Run { offset: 2 * half + 1, len: MAX_COALESCED_BYTES, members: vec![3] },
+            ]
+        );
+
+        assert_eq!(coalesce(&[]), []);
+        assert_eq!(coalesce(&[(10, 0)]), []);
+    }
+}
@@ -0,0 +1,392 @@
+use crate::aoi::Aoi;
+use crate::index::SpectralIndex;
+use crate::stac::{DatetimeInterval, QueryFilter, SearchRequest, SortBy};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Fixed-point scale of the on-chain `RegenQuery` coordinates
+pub const COORDINATE_SCALE: f64 = 1e7;
+
+/// Fixed-point scale of the on-chain `RegenQuery.maxCloudCover` (basis points)
+pub const CLOUD_COVER_SCALE: f64 = 100.0;
+
+/// Items per page requested in the modes that read every page
+pub const SERIES_PAGE_LIMIT: u32 = 100;
+
+/// A regen oracle request, decoded from the `RegenQuery` ABI struct in `ITypes.sol`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegenRequest {
+    /// `[min_lon, min_lat, max_lon, max_lat]` in WGS84 degrees; the envelope of
+    /// `geometry` when one is given
+    pub bbox: [f64; 4],
+    /// Parcel polygons, `None` to use the whole bbox
+    pub geometry: Option<Aoi>,
+    /// Start of the acquisition window, unix seconds
+    pub start_time: u64,
+    /// End of the acquisition window, unix seconds (inclusive)
+    pub end_time: u64,
+    /// Maximum scene cloud cover in percent
+    pub max_cloud_cover: f64,
+    /// STAC collection, `None` for the service default
+    pub collection: Option<String>,
+    pub outputs: Outputs,
+    pub index: SpectralIndex,
+    pub mode: Mode,
+    /// Baseline acquisition window compared against `start_time..=end_time` in change
+    /// detection mode, unix seconds
+    pub baseline: Option<(u64, u64)>,
+}
+
+/// What the oracle computes for a request, mirroring `RegenQuery.mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum Mode {
+    /// Statistics of the most recent scene the search returns
+    #[default]
+    Latest = 0,
+    /// Statistics of every scene in the datetime interval, one observation per date
+    TimeSeries = 1,
+    /// Composites of a baseline and a monitoring period, and their difference
+    ChangeDetection = 2,
+    /// Composite of the scenes of one MGRS tile in the datetime interval
+    Composite = 3,
+}
+
+impl TryFrom<u8> for Mode {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Mode::Latest),
+            1 => Ok(Mode::TimeSeries),
+            2 => Ok(Mode::ChangeDetection),
+            3 => Ok(Mode::Composite),
+            other => Err(anyhow::anyhow!("Unknown request mode {}", other)),
+        }
+    }
+}
+
+/// Bitmask of the artifacts a request asks for, mirroring `RegenQuery.outputs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outputs(pub u32);
+
+impl Outputs {
+    /// Upload the scene's rasters of the index's input bands to IPFS
+    pub const BANDS: u32 = 1 << 0;
+
+    pub fn contains(&self, flag: u32) -> bool {
+        self.0 & flag == flag
+    }
+}
+
+impl RegenRequest {
+    /// Rejects requests that cannot describe a valid search
+    pub fn validate(&self) -> Result<()> {
+        let [min_lon, min_lat, max_lon, max_lat] = self.bbox;
+        if !(-180.0..=180.0).contains(&min_lon) || !(-180.0..=180.0).contains(&max_lon) {
+            return Err(anyhow::anyhow!("Longitude out of range in bbox {:?}", self.bbox));
+        }
+        if !(-90.0..=90.0).contains(&min_lat) || !(-90.0..=90.0).contains(&max_lat) {
+            return Err(anyhow::anyhow!("Latitude out of range in bbox {:?}", self.bbox));
+        }
+        if min_lon >= max_lon || min_lat >= max_lat {
+            return Err(anyhow::anyhow!("Empty bbox {:?}", self.bbox));
+        }
+        if self.start_time > self.end_time {
+            return Err(anyhow::anyhow!(
+                "Start time {} is after end time {}",
+                self.start_time,
+                self.end_time
+            ));
+        }
+        match (self.mode, self.baseline) {
+            (Mode::ChangeDetection, None) => {
+                return Err(anyhow::anyhow!("Change detection needs a baseline period"))
+            }
+            (Mode::ChangeDetection, Some((start, end))) if start > end => {
+                return Err(anyhow::anyhow!("Baseline start {} is after its end {}", start, end))
+            }
+            _ => {}
+        }
+        if !(0.0..=100.0).contains(&self.max_cloud_cover) {
+            return Err(anyhow::anyhow!("Cloud cover {}% out of range", self.max_cloud_cover));
+        }
+        Ok(())
+    }
+
+    /// The area statistics are computed over
+    pub fn aoi(&self) -> Aoi {
+        self.geometry.clone().unwrap_or_else(|| Aoi::from_bbox(self.bbox))
+    }
+
+    /// Builds the STAC search for this request
+    ///
+    /// Parcels are searched with `intersects`, so scenes that only overlap the
+    /// envelope are left out; otherwise the search uses `bbox`. In [`Mode::Latest`]
+    /// the server sorts by datetime, then item id, newest first, and returns a single
+    /// item, so nothing else is fetched.
+    pub fn to_search(&self, default_collection: &str) -> SearchRequest {
+        self.search_between(default_collection, self.start_time, self.end_time)
+    }
+
+    /// Builds the STAC search for the baseline period, if the request has one
+    pub fn to_baseline_search(&self, default_collection: &str) -> Option<SearchRequest> {
+        let (start, end) = self.baseline?;
+        Some(self.search_between(default_collection, start, end))
+    }
+
+    fn search_between(&self, default_collection: &str, start: u64, end: u64) -> SearchRequest {
+        let collection = self.collection.as_deref().unwrap_or(default_collection);
+        let mut query = BTreeMap::new();
+        query.insert(
+            "eo:cloud_cover".to_string(),
+            QueryFilter { lte: Some(self.max_cloud_cover), ..Default::default() },
+        );
+
+        SearchRequest {
+            collections: vec![collection.to_string()],
+            bbox: if self.geometry.is_some() { None } else { Some(self.bbox) },
+            intersects: self.geometry.as_ref().map(Aoi::to_geojson),
+            datetime: Some(DatetimeInterval {
+                start: Some(rfc3339(start)),
+                end: Some(rfc3339(end)),
+            }),
+            limit: match self.mode {
+                Mode::Latest => Some(1),
+                Mode::TimeSeries | Mode::ChangeDetection | Mode::Composite => {
+                    Some(SERIES_PAGE_LIMIT)
+                }
+            },
+            query,
+            sortby: match self.mode {
+                Mode::Latest => vec![SortBy::desc("properties.datetime"), SortBy::desc("id")],
+                Mode::TimeSeries | Mode::ChangeDetection | Mode::Composite => Vec::new(),
+            },
+        }
+    }
+}
+
+/// Formats unix seconds as an RFC 3339 UTC timestamp, e.g. `2024-06-01T00:00:00Z`
+pub fn rfc3339(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // civil-from-days, see <https://howardhinnant.github.io/date_algorithms.html>
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+/// Parses an RFC 3339 timestamp into unix seconds, truncating fractional seconds
+///
+/// Dates that do not exist, such as February 29th of a common year, are rejected.
+pub fn parse_rfc3339(value: &str) -> Result<u64> {
+    let invalid = || anyhow::anyhow!("Invalid RFC 3339 timestamp: {}", value);
+    let num = |s: &str| match s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse::<i64>().map_err(|_| invalid()),
+        false => Err(invalid()),
+    };
+
+    let (date, time) = value.split_once(['T', 't']).ok_or_else(invalid)?;
+    let mut date_parts = date.splitn(3, '-');
+    let mut next_date = || date_parts.next().ok_or_else(invalid).and_then(num);
+    let (year, month, day) = (next_date()?, next_date()?, next_date()?);
+
+    let (clock, offset) = match time.strip_suffix(['Z', 'z']) {
+        Some(clock) => (clock, 0),
+        None => {
+            let split = time.rfind(['+', '-']).ok_or_else(invalid)?;
+            let (clock, zone) = time.split_at(split);
+            let (hours, minutes) = zone[1..].split_once(':').ok_or_else(invalid)?;
+            let (hours, minutes) = (num(hours)?, num(minutes)?);
+            if hours > 23 || minutes > 59 {
+                return Err(invalid());
+            }
+            let offset = hours
+                .checked_mul(3_600)
+                .and_then(|h| h.checked_add(minutes * 60))
+                .ok_or_else(invalid)?;
+            (clock, if zone.starts_with('-') { -offset } else { offset })
+        }
+    };
+    let clock = match clock.split_once('.') {
+        Some((clock, fraction)) if !fraction.is_empty() => num(fraction).map(|_| clock)?,
+        Some(_) => return Err(invalid()),
+        None => clock,
+    };
+    let mut clock_parts = clock.splitn(3, ':');
+    let mut next_clock = || clock_parts.next().ok_or_else(invalid).and_then(num);
+    let (hour, minute, second) = (next_clock()?, next_clock()?, next_clock()?);
+
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    // a second of 60 is a leap second, counted as the first second of the next minute
+    if !(1..=12).contains(&month)
+        || !(1..=month_days).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return Err(invalid());
+    }
+
+    // days-from-civil, the inverse of the algorithm used by `rfc3339`
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    // the year is unbounded digits, so the sums are checked
+    let secs = era
+        .checked_mul(146_097)
+        .and_then(|d| d.checked_add(doe - 719_468))
+        .and_then(|days| days.checked_mul(86_400))
+        .and_then(|s| s.checked_add(hour * 3_600 + minute * 60 + second))
+        .and_then(|s| s.checked_sub(offset))
+        .ok_or_else(invalid)?;
+    u64::try_from(secs).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(start_time: u64, end_time: u64) -> RegenRequest {
+        RegenRequest {
+            bbox: [-122.52, 37.70, -122.35, 37.83],
+            geometry: None,
+            start_time,
+            end_time,
+            max_cloud_cover: 10.0,
+            collection: None,
+            outputs: Outputs(0),
+            index: SpectralIndex::Ndvi,
+            mode: Mode::Latest,
+            baseline: None,
+        }
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(1_719_515_053), "2024-06-27T19:04:13Z");
+        assert_eq!(rfc3339(1_709_164_800), "2024-02-29T00:00:00Z");
+        assert_eq!(rfc3339(1_709_164_800 - 1), "2024-02-28T23:59:59Z");
+        assert_eq!(rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(rfc3339(1_677_628_800 - 1), "2023-02-28T23:59:59Z");
+        assert_eq!(rfc3339(1_677_628_800), "2023-03-01T00:00:00Z");
+        assert_eq!(rfc3339(4_107_542_400 - 1), "2100-02-28T23:59:59Z");
+        assert_eq!(rfc3339(1_735_603_200 + 86_399), "2024-12-31T23:59:59Z");
+        assert_eq!(rfc3339(1_735_603_200 + 86_400), "2025-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z").unwrap(), 0);
+        assert_eq!(parse_rfc3339("2024-06-27T19:04:13.624000Z").unwrap(), 1_719_515_053);
+        assert_eq!(parse_rfc3339("2024-06-27t19:04:13z").unwrap(), 1_719_515_053);
+        assert_eq!(parse_rfc3339("2024-06-27T21:04:13+02:00").unwrap(), 1_719_515_053);
+        assert_eq!(parse_rfc3339("2024-06-27T14:34:13.9-04:30").unwrap(), 1_719_515_053);
+        assert_eq!(parse_rfc3339("2024-06-28T00:00:00+04:55").unwrap(), 1_719_515_100);
+        assert_eq!(parse_rfc3339("2024-02-29T00:00:00Z").unwrap(), 1_709_164_800);
+        assert_eq!(parse_rfc3339("2000-02-29T00:00:00Z").unwrap(), 951_782_400);
+        assert_eq!(parse_rfc3339("2016-12-31T23:59:60Z").unwrap(), 1_483_228_800);
+        // the widest offsets, and one that brings the instant back to the epoch
+        assert_eq!(parse_rfc3339("2024-06-28T18:43:13+23:39").unwrap(), 1_719_515_053);
+        assert_eq!(parse_rfc3339("2024-06-26T19:05:13-23:59").unwrap(), 1_719_515_053);
+        assert_eq!(parse_rfc3339("1970-01-01T05:30:00+05:30").unwrap(), 0);
+    }
+
+    #[test]
+    fn round_trips() {
+        for secs in [0, 59, 86_399, 951_782_400, 1_709_164_800, 1_719_515_053, 4_107_542_399] {
+            assert_eq!(parse_rfc3339(&rfc3339(secs)).unwrap(), secs);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_timestamps() {
+        for value in [
+            "",
+            "2024-06-27",
+            "2024-06-27T19:04Z",
+            "2024-06-27T19:04:13",
+            "2024-06-27 19:04:13Z",
+            "2024-6-+7T19:04:13Z",
+            "2024-13-01T00:00:00Z",
+            "2024-00-01T00:00:00Z",
+            "2024-04-31T00:00:00Z",
+            "2023-02-29T00:00:00Z",
+            "2100-02-29T00:00:00Z",
+            "2024-06-27T24:00:00Z",
+            "2024-06-27T19:60:00Z",
+            "2024-06-27T19:04:13.Z",
+            "2024-06-27T19:04:13.5xZ",
+            "2024-06-27T19:04:13+0200",
+            "1969-12-31T23:59:59Z",
+            // zone offsets out of range
+            "2024-06-27T19:04:13+99:99",
+            "2024-06-27T19:04:13+24:00",
+            "2024-06-27T19:04:13-05:60",
+            "2024-06-27T19:04:13+9999999999999999999:00",
+            // years whose seconds overflow
+            "99999999999999-01-01T00:00:00Z",
+            "9223372036854775807-01-01T00:00:00Z",
+        ] {
+            assert!(parse_rfc3339(value).is_err(), "accepted {:?}", value);
+        }
+    }
+
+    #[test]
+    fn validates_time_ranges() {
+        assert!(request(1_717_200_000, 1_719_791_999).validate().is_ok());
+        assert!(request(1_717_200_000, 1_717_200_000).validate().is_ok());
+        let err = request(1_719_791_999, 1_717_200_000).validate().unwrap_err();
+        assert_eq!(err.to_string(), "Start time 1719791999 is after end time 1717200000");
+
+        let mut change = request(1_717_200_000, 1_719_791_999);
+        change.mode = Mode::ChangeDetection;
+        assert!(change.validate().is_err());
+        change.baseline = Some((1_688_169_600, 1_685_577_600));
+        assert!(change.validate().is_err());
+        change.baseline = Some((1_685_577_600, 1_688_169_600));
+        assert!(change.validate().is_ok());
+    }
+
+    #[test]
+    fn validates_bbox_and_cloud_cover() {
+        let mut req = request(0, 1);
+        req.bbox = [-122.35, 37.70, -122.52, 37.83];
+        assert!(req.validate().is_err());
+        req.bbox = [-190.0, 37.70, -122.35, 37.83];
+        assert!(req.validate().is_err());
+        req.bbox = [-122.52, 37.70, -122.35, 91.0];
+        assert!(req.validate().is_err());
+        let mut req = request(0, 1);
+        req.max_cloud_cover = 100.5;
+        assert!(req.validate().is_err());
+    }
+}
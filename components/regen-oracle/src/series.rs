@@ -0,0 +1,295 @@
+use crate::analysis::Analysis;
+use crate::aoi::Coverage;
+use crate::index::{SpectralIndex, Stats};
+use crate::ipfs;
+use crate::metadata::{self, COMPONENT_VERSION};
+use crate::pinner::Pinner;
+use crate::request::{parse_rfc3339, rfc3339};
+use crate::stac::{Item, SearchRequest};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Header of the CSV rendering, one column per [`Observation`] field
+pub const CSV_HEADER: &str = "date,datetime,item_id,cloud_cover,masked_fraction,mean,median,\
+stddev,min,max,valid_pixels,total_pixels,valid_fraction";
+
+/// Index statistics of one acquisition date
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Observation {
+    /// Acquisition date, `YYYY-MM-DD` in UTC
+    pub date: String,
+    pub datetime: String,
+    pub item_id: String,
+    /// Scene cloud cover in percent
+    pub cloud_cover: Option<f64>,
+    /// Fraction of the AOI's pixels removed by the SCL mask
+    pub masked_fraction: f64,
+    /// `None` when no pixel survived masking
+    pub stats: Option<Stats>,
+}
+
+/// A scene left out of the series because it could not be analysed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Skipped {
+    pub datetime: String,
+    pub item_id: String,
+    /// The error that stopped the analysis
+    pub reason: String,
+}
+
+impl Skipped {
+    pub fn new(item: &Item, reason: impl std::fmt::Display) -> Self {
+        Self {
+            datetime: item.properties.datetime.clone(),
+            item_id: item.id.clone(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// Per-date index statistics over a datetime interval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeSeries {
+    pub version: String,
+    pub query: SearchRequest,
+    pub index: SpectralIndex,
+    pub coverage: Coverage,
+    /// Number of scenes analysed, before keeping one per date
+    pub scenes: u64,
+    /// Ordered by date
+    pub observations: Vec<Observation>,
+    /// Scenes that could not be analysed, ordered by datetime then item id
+    #[serde(default)]
+    pub skipped: Vec<Skipped>,
+    /// Path of the CSV rendering within the bundle, set once it is uploaded
+    pub csv: Option<String>,
+}
+
+impl Observation {
+    pub fn new(item: &Item, analysis: &Analysis) -> Result<Self> {
+        let timestamp = parse_rfc3339(&item.properties.datetime)?;
+        Ok(Self {
+            date: rfc3339(timestamp)[..10].to_string(),
+            datetime: item.properties.datetime.clone(),
+            item_id: item.id.clone(),
+            cloud_cover: item.properties.cloud_cover,
+            masked_fraction: analysis.mask.masked_fraction,
+            stats: analysis.stats.clone(),
+        })
+    }
+
+    fn valid_pixels(&self) -> u64 {
+        self.stats.as_ref().map_or(0, |stats| stats.valid_pixels)
+    }
+}
+
+impl TimeSeries {
+    /// Builds the series from the observations of every analysed scene
+    ///
+    /// Several scenes can share a date, e.g. when the AOI straddles two MGRS tiles
+    /// or a granule was reprocessed. The one with the most valid pixels is kept and
+    /// ties go to the lowest item id, so the series does not depend on the order
+    /// the STAC API returned the items in. Skipped scenes are listed apart, whether
+    /// or not another scene covers their date.
+    pub fn new(
+        query: SearchRequest,
+        index: SpectralIndex,
+        coverage: Coverage,
+        mut observations: Vec<Observation>,
+        mut skipped: Vec<Skipped>,
+    ) -> Self {
+        let scenes = observations.len() as u64;
+        observations.sort_by(|a, b| {
+            a.date
+                .cmp(&b.date)
+                .then(b.valid_pixels().cmp(&a.valid_pixels()))
+                .then_with(|| a.item_id.cmp(&b.item_id))
+        });
+        observations.dedup_by(|later, kept| later.date == kept.date);
+        skipped.sort_by(|a, b| a.datetime.cmp(&b.datetime).then_with(|| a.item_id.cmp(&b.item_id)));
+
+        Self {
+            version: COMPONENT_VERSION.to_string(),
+            query,
+            index,
+            coverage,
+            scenes,
+            observations,
+            skipped,
+            csv: None,
+        }
+    }
+
+    /// The most recent observation
+    pub fn latest(&self) -> Option<&Observation> {
+        self.observations.last()
+    }
+
+    /// Name of the bundle directory, e.g. `ndvi_2024-06-02_2024-06-27_series`
+    pub fn bundle_name(&self) -> String {
+        let date = |o: Option<&Observation>| o.map_or("none", |o| o.date.as_str()).to_string();
+        format!("{}_{}_{}_series", self.index, date(self.observations.first()), date(self.latest()))
+    }
+
+    /// Renders the observations as CSV under [`CSV_HEADER`]
+    ///
+    /// Numbers use Rust's shortest round-trip formatting and missing values are
+    /// left empty, so the bytes are the same for every operator.
+    pub fn to_csv(&self) -> String {
+        let opt = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+        let mut csv = format!("{}\n", CSV_HEADER);
+        for o in &self.observations {
+            let stats = o.stats.as_ref();
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                o.date,
+                o.datetime,
+                o.item_id,
+                opt(o.cloud_cover),
+                o.masked_fraction,
+                opt(stats.map(|s| s.mean)),
+                opt(stats.map(|s| s.median)),
+                opt(stats.map(|s| s.stddev)),
+                opt(stats.map(|s| s.min)),
+                opt(stats.map(|s| s.max)),
+                stats.map(|s| s.valid_pixels.to_string()).unwrap_or_default(),
+                stats.map(|s| s.total_pixels.to_string()).unwrap_or_default(),
+                opt(stats.map(|s| s.valid_fraction)),
+            );
+        }
+        csv
+    }
+
+    /// Uploads the canonical JSON document and the CSV it links to as one directory
+    ///
+    /// Returns the JSON document's `ipfs://CID/series.json` URI, CID being the directory's.
+    pub async fn upload(&mut self, pinner: &Pinner) -> Result<String> {
+        let csv = self.to_csv();
+        self.csv = Some("series.csv".to_string());
+        let json = metadata::canonical_json(self)?;
+        let files = [("series.json", json.as_bytes()), ("series.csv", csv.as_bytes())];
+        let root = ipfs::upload_directory_to_ipfs(&self.bundle_name(), &files, pinner).await?;
+        Ok(ipfs::get_ipfs_url(&root, Some("series.json")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stac::DatetimeInterval;
+    use std::collections::BTreeMap;
+
+    fn stats(mean: f64, valid_pixels: u64) -> Stats {
+        Stats {
+            mean,
+            median: mean,
+            stddev: 0.0,
+            min: mean,
+            max: mean,
+            valid_pixels,
+            total_pixels: 100,
+            valid_fraction: valid_pixels as f64 / 100.0,
+        }
+    }
+
+    fn observation(datetime: &str, item_id: &str, stats: Option<Stats>) -> Observation {
+        Observation {
+            date: datetime[..10].to_string(),
+            datetime: datetime.to_string(),
+            item_id: item_id.to_string(),
+            cloud_cover: Some(1.5),
+            masked_fraction: 0.25,
+            stats,
+        }
+    }
+
+    fn series(observations: Vec<Observation>, skipped: Vec<Skipped>) -> TimeSeries {
+        let query = SearchRequest {
+            collections: vec!["sentinel-2-l2a".to_string()],
+            bbox: Some([-122.52, 37.70, -122.35, 37.83]),
+            intersects: None,
+            datetime: Some(DatetimeInterval { start: None, end: None }),
+            limit: Some(100),
+            query: BTreeMap::new(),
+            sortby: Vec::new(),
+        };
+        TimeSeries::new(query, SpectralIndex::Ndvi, Coverage::Center, observations, skipped)
+    }
+
+    #[test]
+    fn keeps_one_observation_per_date() {
+        let observations = vec![
+            observation("2024-06-27T19:04:13Z", "S2B_10SFG", Some(stats(0.2, 50))),
+            observation("2024-06-02T19:04:20Z", "S2A_10SEG", Some(stats(0.3, 80))),
+            observation("2024-06-27T19:04:09Z", "S2B_10SEG", Some(stats(0.4, 90))),
+            observation("2024-06-02T19:04:30Z", "S2A_10SFG", None),
+            observation("2024-06-12T19:04:30Z", "S2A_10SFG", Some(stats(0.5, 90))),
+            observation("2024-06-12T19:04:20Z", "S2A_10SEG", Some(stats(0.6, 90))),
+        ];
+        let kept = |series: &TimeSeries| -> Vec<String> {
+            series.observations.iter().map(|o| format!("{} {}", o.date, o.item_id)).collect()
+        };
+
+        let forward = series(observations.clone(), Vec::new());
+        assert_eq!(forward.scenes, 6);
+        // most valid pixels, then lowest item id on ties
+        assert_eq!(
+            kept(&forward),
+            ["2024-06-02 S2A_10SEG", "2024-06-12 S2A_10SEG", "2024-06-27 S2B_10SEG"]
+        );
+        assert_eq!(forward.latest().map(|o| o.item_id.as_str()), Some("S2B_10SEG"));
+
+        let reversed = series(observations.into_iter().rev().collect(), Vec::new());
+        assert_eq!(reversed, forward);
+        assert_eq!(series(Vec::new(), Vec::new()).latest(), None);
+    }
+
+    #[test]
+    fn lists_skipped_scenes_apart() {
+        let skipped = |datetime: &str, item_id: &str| Skipped {
+            datetime: datetime.to_string(),
+            item_id: item_id.to_string(),
+            reason: "Range request failed".to_string(),
+        };
+        let series = series(
+            vec![observation("2024-06-02T19:04:20Z", "S2A_10SEG", Some(stats(0.3, 80)))],
+            vec![
+                skipped("2024-06-12T19:04:20Z", "S2A_10SEG"),
+                skipped("2024-06-02T19:04:20Z", "S2A_10SFG"),
+            ],
+        );
+        assert_eq!(series.scenes, 1);
+        assert_eq!(series.observations.len(), 1);
+        let ids: Vec<_> = series.skipped.iter().map(|s| s.item_id.as_str()).collect();
+        assert_eq!(ids, ["S2A_10SFG", "S2A_10SEG"]);
+
+        let json = serde_json::to_value(&series).unwrap();
+        assert_eq!(json["skipped"][0]["reason"], "Range request failed");
+    }
+
+    #[test]
+    fn renders_csv() {
+        let series = series(
+            vec![
+                observation("2024-06-02T19:04:20Z", "S2A_10SEG", Some(stats(0.1, 80))),
+                Observation {
+                    cloud_cover: None,
+                    ..observation("2024-06-12T19:04:20Z", "S2A_10SEG", None)
+                },
+            ],
+            Vec::new(),
+        );
+        assert_eq!(
+            series.to_csv(),
+            format!(
+                "{}\n\
+                 2024-06-02,2024-06-02T19:04:20Z,S2A_10SEG,1.5,0.25,0.1,0.1,0,0.1,0.1,80,100,0.8\n\
+                 2024-06-12,2024-06-12T19:04:20Z,S2A_10SEG,,0.25,,,,,,,,\n",
+                CSV_HEADER
+            )
+        );
+        assert_eq!(CSV_HEADER.split(',').count(), 13);
+    }
+}
@@ -0,0 +1,558 @@
+use crate::request::parse_rfc3339;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use wstd::http::{IntoBody, Request};
+use wstd::io::AsyncRead;
+
+/// Earth Search v1 item search endpoint
+pub const DEFAULT_STAC_API_URL: &str = "https://earth-search.aws.element84.com/v1/search";
+
+/// Sentinel-2 Level-2A (surface reflectance) collection on Earth Search
+pub const DEFAULT_COLLECTION: &str = "sentinel-2-l2a";
+
+/// Body of a STAC API `POST /search` request
+///
+/// Mirrors the curl example in `plan.md`:
+/// ```json
+/// {
+///   "collections": ["sentinel-2-l2a"],
+///   "bbox": [-122.52, 37.70, -122.35, 37.83],
+///   "datetime": "2024-06-01T00:00:00Z/2024-06-30T23:59:59Z",
+///   "limit": 2,
+///   "query": { "eo:cloud_cover": { "lt": 10 } }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchRequest {
+    pub collections: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<[f64; 4]>,
+    /// GeoJSON geometry the items must intersect; STAC APIs reject it together with `bbox`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intersects: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<DatetimeInterval>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// Property filters from the STAC API query extension, keyed by property name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub query: BTreeMap<String, QueryFilter>,
+    /// Result order from the STAC API sort extension, the server's default when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sortby: Vec<SortBy>,
+}
+
+/// One key of a STAC API sort extension `sortby`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SortBy {
+    /// Item field, e.g. `properties.datetime` or `id`
+    pub field: String,
+    pub direction: SortDirection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl SortBy {
+    pub fn desc(field: &str) -> Self {
+        Self { field: field.to_string(), direction: SortDirection::Desc }
+    }
+}
+
+/// RFC 3339 datetime interval, serialized as `start/end`
+///
+/// Either side may be open, which STAC writes as `..`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct DatetimeInterval {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+impl From<DatetimeInterval> for String {
+    fn from(interval: DatetimeInterval) -> Self {
+        format!(
+            "{}/{}",
+            interval.start.as_deref().unwrap_or(".."),
+            interval.end.as_deref().unwrap_or("..")
+        )
+    }
+}
+
+impl TryFrom<String> for DatetimeInterval {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        let bound = |s: &str| match s {
+            "" | ".." => None,
+            s => Some(s.to_string()),
+        };
+        match value.split_once('/') {
+            Some((start, end)) => Ok(Self { start: bound(start), end: bound(end) }),
+            // A single instant is an interval that starts and ends at the same time
+            None if !value.is_empty() => Ok(Self { start: bound(&value), end: bound(&value) }),
+            None => Err("empty datetime interval".to_string()),
+        }
+    }
+}
+
+/// Comparison operators from the STAC API query extension
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eq: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub neq: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lte: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gte: Option<f64>,
+    #[serde(default, rename = "in", skip_serializing_if = "Option::is_none")]
+    pub in_: Option<Vec<serde_json::Value>>,
+}
+
+/// GeoJSON FeatureCollection returned by `POST /search`
+/// Modeled on `example-response.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemCollection {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub features: Vec<Item>,
+    #[serde(default)]
+    pub links: Vec<Link>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Context>,
+    #[serde(rename = "numberMatched", default, skip_serializing_if = "Option::is_none")]
+    pub number_matched: Option<u64>,
+    #[serde(rename = "numberReturned", default, skip_serializing_if = "Option::is_none")]
+    pub number_returned: Option<u64>,
+}
+
+/// A single STAC item (one Sentinel-2 granule)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    pub properties: ItemProperties,
+    /// GeoJSON footprint in WGS84
+    pub geometry: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<Vec<f64>>,
+    #[serde(default)]
+    pub assets: BTreeMap<String, Asset>,
+    #[serde(default)]
+    pub links: Vec<Link>,
+}
+
+/// Item properties used by the oracle; everything else is kept in `extra`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemProperties {
+    pub datetime: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constellation: Option<String>,
+    #[serde(rename = "eo:cloud_cover", default, skip_serializing_if = "Option::is_none")]
+    pub cloud_cover: Option<f64>,
+    #[serde(rename = "proj:epsg", default, skip_serializing_if = "Option::is_none")]
+    pub epsg: Option<u32>,
+    #[serde(rename = "grid:code", default, skip_serializing_if = "Option::is_none")]
+    pub grid_code: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// A downloadable file attached to an item, e.g. a single-band COG
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Asset {
+    pub href: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gsd: Option<f64>,
+    #[serde(rename = "eo:bands", default, skip_serializing_if = "Vec::is_empty")]
+    pub eo_bands: Vec<EoBand>,
+    #[serde(rename = "proj:shape", default, skip_serializing_if = "Option::is_none")]
+    pub proj_shape: Option<Vec<u64>>,
+    #[serde(rename = "proj:transform", default, skip_serializing_if = "Option::is_none")]
+    pub proj_transform: Option<Vec<f64>>,
+    #[serde(rename = "raster:bands", default, skip_serializing_if = "Vec::is_empty")]
+    pub raster_bands: Vec<RasterBand>,
+}
+
+/// Entry of the `eo:bands` asset field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EoBand {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub common_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub center_wavelength: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_width_half_max: Option<f64>,
+}
+
+/// Entry of the `raster:bands` asset field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RasterBand {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nodata: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bits_per_sample: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spatial_resolution: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+}
+
+/// Hypermedia link; `next` links carry the request for the following page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Link {
+    pub rel: String,
+    pub href: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge: Option<bool>,
+}
+
+/// Result counts from the STAC API context extension
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Context {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub returned: Option<u64>,
+}
+
+/// Caps on how far [`search_all`] follows `next` links
+///
+/// Operators must see the same item list, so the search stops at a fixed point
+/// in the server's result order rather than when a time or size budget runs out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageLimits {
+    pub max_pages: usize,
+    pub max_items: usize,
+}
+
+impl ItemCollection {
+    /// The `rel: next` link pointing at the following page, if any
+    pub fn next_link(&self) -> Option<&Link> {
+        self.links.iter().find(|link| link.rel == "next")
+    }
+}
+
+/// Sends a STAC item search to `api_url` and returns the parsed FeatureCollection
+pub async fn search(api_url: &str, request: &SearchRequest) -> Result<ItemCollection> {
+    post_search(api_url, &serde_json::to_value(request)?).await
+}
+
+/// Runs a STAC item search and follows `next` links, returning the items sorted
+///
+/// `POST` links carry the request for the following page in `body`: with
+/// `merge: true` its fields are merged into the previous request, otherwise it
+/// replaces it, as Earth Search's `next` token bodies do. Other links are fetched
+/// with `GET`. The search stops after `limits.max_pages` pages or once
+/// `limits.max_items` items have been collected, keeping the first items in the
+/// server's order. Items repeated across pages are dropped and the rest sorted by
+/// [`sort_items`].
+pub async fn search_all(
+    api_url: &str,
+    request: &SearchRequest,
+    limits: PageLimits,
+) -> Result<Vec<Item>> {
+    let mut body = serde_json::to_value(request)?;
+    let mut page = post_search(api_url, &body).await?;
+    let mut collector = Collector::new(limits);
+    while let Some(next) = collector.add(page) {
+        page = match next_body(&next, &body) {
+            Some(next_body) => {
+                body = next_body;
+                post_search(&next.href, &body).await?
+            }
+            None => get_search(&next.href).await?,
+        };
+    }
+    Ok(collector.finish())
+}
+
+/// Items gathered by [`search_all`], page by page
+struct Collector {
+    limits: PageLimits,
+    pages: usize,
+    items: Vec<Item>,
+    seen: BTreeSet<String>,
+}
+
+impl Collector {
+    fn new(limits: PageLimits) -> Self {
+        Self { limits, pages: 0, items: Vec::new(), seen: BTreeSet::new() }
+    }
+
+    /// Adds the items of `page` not seen before, returning its `next` link if the
+    /// search should go on
+    fn add(&mut self, mut page: ItemCollection) -> Option<Link> {
+        self.pages += 1;
+        let remaining = self.limits.max_items.saturating_sub(self.items.len());
+        let returned = page.features.len();
+        let seen = &mut self.seen;
+        self.items.extend(
+            page.features.drain(..).filter(|item| seen.insert(item.id.clone())).take(remaining),
+        );
+
+        let next = page.next_link()?;
+        if self.pages >= self.limits.max_pages
+            || self.items.len() >= self.limits.max_items
+            || returned == 0
+        {
+            println!(
+                "stopping STAC search after {} pages and {} items",
+                self.pages,
+                self.items.len()
+            );
+            return None;
+        }
+        Some(next.clone())
+    }
+
+    /// The items, sorted by [`sort_items`]
+    fn finish(mut self) -> Vec<Item> {
+        sort_items(&mut self.items);
+        self.items
+    }
+}
+
+/// Body of the `POST` request `link` asks for, given the body of the previous request
+///
+/// `None` when the link is to be fetched with `GET`.
+fn next_body(link: &Link, previous: &serde_json::Value) -> Option<serde_json::Value> {
+    match (link.method.as_deref(), &link.body) {
+        (Some(method), Some(body)) if method.eq_ignore_ascii_case("POST") => {
+            Some(match (link.merge.unwrap_or(false), previous, body) {
+                (true, serde_json::Value::Object(previous), serde_json::Value::Object(fields)) => {
+                    let mut merged = previous.clone();
+                    merged.extend(fields.clone());
+                    serde_json::Value::Object(merged)
+                }
+                _ => body.clone(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Sorts items by acquisition time, then id, so the order does not depend on the server
+///
+/// Times are compared as instants, falling back to the raw string for values
+/// that do not parse.
+pub fn sort_items(items: &mut [Item]) {
+    items.sort_by_cached_key(|item| {
+        let datetime = &item.properties.datetime;
+        (parse_rfc3339(datetime).ok(), datetime.clone(), item.id.clone())
+    });
+}
+
+async fn post_search(url: &str, body: &serde_json::Value) -> Result<ItemCollection> {
+    let request = Request::post(url)
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/geo+json")
+        .body(serde_json::to_vec(body)?.into_body())?;
+    read_search_response(request).await
+}
+
+async fn get_search(url: &str) -> Result<ItemCollection> {
+    let request =
+        Request::get(url).header("Accept", "application/geo+json").body(wstd::io::empty())?;
+    read_search_response(request).await
+}
+
+async fn read_search_response<B: wstd::http::Body>(request: Request<B>) -> Result<ItemCollection> {
+    let mut response = wstd::http::Client::new().send(request).await?;
+
+    let mut body_buf = Vec::new();
+    response.body_mut().read_to_end(&mut body_buf).await?;
+
+    if !response.status().is_success() {
+        let error_body = std::str::from_utf8(&body_buf).unwrap_or("unable to read error body");
+        return Err(anyhow::anyhow!(
+            "STAC search failed. Status: {:?}, Body: {}",
+            response.status(),
+            error_body
+        ));
+    }
+
+    serde_json::from_slice(&body_buf)
+        .map_err(|e| anyhow::anyhow!("Failed to parse STAC search response: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(id: &str, datetime: &str) -> Item {
+        serde_json::from_value(json!({
+            "id": id,
+            "properties": { "datetime": datetime },
+            "geometry": null
+        }))
+        .unwrap()
+    }
+
+    fn page(items: &[(&str, &str)], next: Option<serde_json::Value>) -> ItemCollection {
+        ItemCollection {
+            kind: "FeatureCollection".to_string(),
+            features: items.iter().map(|&(id, datetime)| item(id, datetime)).collect(),
+            links: next.into_iter().map(|link| serde_json::from_value(link).unwrap()).collect(),
+            context: None,
+            number_matched: None,
+            number_returned: None,
+        }
+    }
+
+    fn next() -> Option<serde_json::Value> {
+        Some(json!({ "rel": "next", "href": "https://example.com/search?page=2" }))
+    }
+
+    fn ids(items: &[Item]) -> Vec<&str> {
+        items.iter().map(|item| item.id.as_str()).collect()
+    }
+
+    const LIMITS: PageLimits = PageLimits { max_pages: 10, max_items: 500 };
+
+    #[test]
+    fn merges_next_bodies() {
+        let previous = json!({ "collections": ["sentinel-2-l2a"], "limit": 100 });
+
+        let merge: Link = serde_json::from_value(json!({
+            "rel": "next", "href": "https://example.com/search", "method": "POST",
+            "merge": true, "body": { "next": "token" }
+        }))
+        .unwrap();
+        assert_eq!(
+            next_body(&merge, &previous),
+            Some(json!({ "collections": ["sentinel-2-l2a"], "limit": 100, "next": "token" }))
+        );
+
+        let replace = Link { merge: None, ..merge.clone() };
+        assert_eq!(next_body(&replace, &previous), Some(json!({ "next": "token" })));
+
+        let lowercase = Link { method: Some("post".to_string()), ..merge.clone() };
+        assert!(next_body(&lowercase, &previous).is_some());
+
+        let get = Link { method: Some("GET".to_string()), ..merge.clone() };
+        assert_eq!(next_body(&get, &previous), None);
+        let no_method = Link { method: None, ..merge.clone() };
+        assert_eq!(next_body(&no_method, &previous), None);
+        let no_body = Link { body: None, ..merge };
+        assert_eq!(next_body(&no_body, &previous), None);
+    }
+
+    #[test]
+    fn follows_pages_until_the_last() {
+        let mut collector = Collector::new(LIMITS);
+        assert!(collector.add(page(&[("b", "2024-06-02T00:00:00Z")], next())).is_some());
+        assert!(collector.add(page(&[("a", "2024-06-01T00:00:00Z")], None)).is_none());
+        assert_eq!(ids(&collector.finish()), ["a", "b"]);
+
+        // an empty page ends the search even with a next link
+        let mut collector = Collector::new(LIMITS);
+        assert!(collector.add(page(&[], next())).is_none());
+    }
+
+    #[test]
+    fn caps_pages_and_items() {
+        let limits = PageLimits { max_pages: 2, max_items: 500 };
+        let mut collector = Collector::new(limits);
+        assert!(collector.add(page(&[("a", "2024-06-01T00:00:00Z")], next())).is_some());
+        assert!(collector.add(page(&[("b", "2024-06-02T00:00:00Z")], next())).is_none());
+        assert_eq!(ids(&collector.finish()), ["a", "b"]);
+
+        // the first items in the server's order are kept, then sorted
+        let limits = PageLimits { max_pages: 10, max_items: 3 };
+        let mut collector = Collector::new(limits);
+        let first = [("d", "2024-06-04T00:00:00Z"), ("c", "2024-06-03T00:00:00Z")];
+        assert!(collector.add(page(&first, next())).is_some());
+        let second = [("a", "2024-06-01T00:00:00Z"), ("b", "2024-06-02T00:00:00Z")];
+        assert!(collector.add(page(&second, next())).is_none());
+        assert_eq!(ids(&collector.finish()), ["a", "c", "d"]);
+    }
+
+    #[test]
+    fn drops_repeated_items() {
+        let mut collector = Collector::new(LIMITS);
+        let first = [("a", "2024-06-01T00:00:00Z"), ("b", "2024-06-02T00:00:00Z")];
+        assert!(collector.add(page(&first, next())).is_some());
+        let second = [("b", "2024-06-02T00:00:00Z"), ("c", "2024-06-03T00:00:00Z")];
+        assert!(collector.add(page(&second, None)).is_none());
+        assert_eq!(ids(&collector.finish()), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn sorts_by_instant_then_id() {
+        let mut items = vec![
+            item("late", "2024-06-01T12:00:00+02:00"),
+            item("b", "2024-06-01T09:00:00Z"),
+            item("a", "2024-06-01T09:00:00.5Z"),
+            item("early", "2024-06-01T08:00:00Z"),
+            item("unparsed", "June 1st"),
+        ];
+        sort_items(&mut items);
+        // 12:00+02:00 is 10:00Z; fractional seconds are truncated, so `a` ties with `b`
+        assert_eq!(ids(&items), ["unparsed", "early", "a", "b", "late"]);
+    }
+
+    #[test]
+    fn serializes_sortby() {
+        let request = SearchRequest {
+            collections: vec!["sentinel-2-l2a".to_string()],
+            bbox: None,
+            intersects: None,
+            datetime: None,
+            limit: Some(1),
+            query: BTreeMap::new(),
+            sortby: vec![SortBy::desc("properties.datetime"), SortBy::desc("id")],
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({
+                "collections": ["sentinel-2-l2a"],
+                "limit": 1,
+                "sortby": [
+                    { "field": "properties.datetime", "direction": "desc" },
+                    { "field": "id", "direction": "desc" }
+                ]
+            })
+        );
+    }
+}
@@ -1,6 +1,10 @@
+use crate::aoi::Aoi;
 use crate::bindings::wavs::worker::layer_types::{
     TriggerData, TriggerDataEthContractEvent, WasmResponse,
 };
+use crate::index::SpectralIndex;
+use crate::output::{ChangeOutput, RegenOutput};
+use crate::request::{Mode, Outputs, RegenRequest, CLOUD_COVER_SCALE, COORDINATE_SCALE};
 use alloy_sol_types::SolValue;
 use anyhow::Result;
 use wavs_wasi_utils::decode_event_log_data;
@@ -24,22 +28,69 @@ pub enum Destination {
 /// # Returns
 /// A tuple containing:
 /// * `u64` - Trigger ID for tracking the request
-/// * `Vec<u8>` - The actual data payload
+/// * `RegenRequest` - The decoded and validated request
 /// * `Destination` - Where the processed result should be sent
 ///
 /// # Implementation Details
 /// Handles two types of triggers:
 /// 1. EthContractEvent - Decodes Ethereum event logs using the NewTrigger ABI
-/// 2. Raw - Used for direct CLI testing with no encoding
-pub fn decode_trigger_event(trigger_data: TriggerData) -> Result<(u64, Vec<u8>, Destination)> {
-    match trigger_data {
+/// 2. Raw - Used for direct CLI testing, the payload is the ABI encoded `RegenQuery` alone
+pub fn decode_trigger_event(trigger_data: TriggerData) -> Result<(u64, RegenRequest, Destination)> {
+    let (trigger_id, data, dest) = match trigger_data {
         TriggerData::EthContractEvent(TriggerDataEthContractEvent { log, .. }) => {
             let event: solidity::NewTrigger = decode_event_log_data!(log)?;
             let trigger_info = solidity::TriggerInfo::abi_decode(&event._triggerInfo)?;
-            Ok((trigger_info.triggerId, trigger_info.data.to_vec(), Destination::Ethereum))
+            (trigger_info.triggerId, trigger_info.data.to_vec(), Destination::Ethereum)
         }
-        TriggerData::Raw(data) => Ok((0, data.clone(), Destination::CliOutput)),
-        _ => Err(anyhow::anyhow!("Unsupported trigger data type")),
+        TriggerData::Raw(data) => (0, data, Destination::CliOutput),
+        _ => return Err(anyhow::anyhow!("Unsupported trigger data type")),
+    };
+
+    let query = solidity::RegenQuery::abi_decode(&data)
+        .map_err(|e| anyhow::anyhow!("Failed to decode RegenQuery: {}", e))?;
+    let request = RegenRequest::try_from(query)?;
+    request.validate()?;
+
+    Ok((trigger_id, request, dest))
+}
+
+impl TryFrom<solidity::RegenQuery> for RegenRequest {
+    type Error = anyhow::Error;
+
+    /// Converts the fixed-point query; a non-empty `geometry` replaces the bbox fields
+    fn try_from(query: solidity::RegenQuery) -> Result<Self> {
+        let degrees = |v: i32| f64::from(v) / COORDINATE_SCALE;
+        let geometry = match query.geometry.trim() {
+            "" => None,
+            text => Some(Aoi::parse(text).map_err(|e| anyhow::anyhow!("Invalid geometry: {}", e))?),
+        };
+        let bbox = match &geometry {
+            Some(aoi) => aoi.bbox(),
+            None => [
+                degrees(query.minLon),
+                degrees(query.minLat),
+                degrees(query.maxLon),
+                degrees(query.maxLat),
+            ],
+        };
+        Ok(Self {
+            bbox,
+            geometry,
+            start_time: query.startTime,
+            end_time: query.endTime,
+            max_cloud_cover: f64::from(query.maxCloudCover) / CLOUD_COVER_SCALE,
+            collection: Some(query.collection).filter(|c| !c.is_empty()),
+            outputs: Outputs(query.outputs),
+            index: match query.index.trim() {
+                "" => SpectralIndex::default(),
+                name => name.parse()?,
+            },
+            mode: Mode::try_from(query.mode)?,
+            baseline: match (query.baselineStart, query.baselineEnd) {
+                (0, 0) => None,
+                period => Some(period),
+            },
+        })
     }
 }
 
@@ -47,17 +98,29 @@ pub fn decode_trigger_event(trigger_data: TriggerData) -> Result<(u64, Vec<u8>,
 ///
 /// # Arguments
 /// * `trigger_id` - The ID of the original trigger request
-/// * `output` - The data to be encoded, must implement AsRef<[u8]>
+/// * `output` - The oracle result, encoded as a `RegenResult` struct
 ///
 /// # Returns
-/// ABI encoded bytes ready for submission to Ethereum
-pub fn encode_trigger_output(trigger_id: u64, output: impl AsRef<[u8]>) -> WasmResponse {
+/// ABI encoded `DataWithId` bytes ready for submission to Ethereum
+pub fn encode_trigger_output(trigger_id: u64, output: &RegenOutput) -> WasmResponse {
+    let change = output.change.as_ref();
+    let result = solidity::RegenResult {
+        cid: output.cid.clone(),
+        itemId: output.item_id.clone(),
+        sceneTimestamp: output.scene_timestamp,
+        cloudCover: output.cloud_cover_bps(),
+        index: output.index.to_string(),
+        meanIndex: output.mean_index_fixed(),
+        meanDelta: change.map_or(0, ChangeOutput::mean_delta_fixed),
+        improved: change.map_or(0, |c| ChangeOutput::bps(c.improved_fraction)),
+        degraded: change.map_or(0, |c| ChangeOutput::bps(c.degraded_fraction)),
+        unchanged: change.map_or(0, |c| ChangeOutput::bps(c.unchanged_fraction)),
+        status: output.status as u8,
+    };
+
     WasmResponse {
-        payload: solidity::DataWithId {
-            triggerId: trigger_id,
-            data: output.as_ref().to_vec().into(),
-        }
-        .abi_encode(),
+        payload: solidity::DataWithId { triggerId: trigger_id, data: result.abi_encode().into() }
+            .abi_encode(),
         ordering: None,
     }
 }
@@ -71,6 +134,8 @@ pub fn encode_trigger_output(trigger_id: u64, output: impl AsRef<[u8]>) -> WasmR
 /// - NewTrigger event
 /// - TriggerInfo struct
 /// - DataWithId struct
+/// - RegenQuery struct
+/// - RegenResult struct
 ///
 /// Documentation:
 /// - <https://docs.rs/alloy-sol-macro/latest/alloy_sol_macro/macro.sol.html>
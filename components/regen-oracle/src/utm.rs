@@ -0,0 +1,249 @@
+use anyhow::Result;
+
+/// WGS84 semi-major axis, metres
+const WGS84_A: f64 = 6_378_137.0;
+
+/// WGS84 flattening
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+
+/// UTM scale factor on the central meridian
+const K0: f64 = 0.9996;
+
+const FALSE_EASTING: f64 = 500_000.0;
+
+/// False northing of the southern hemisphere zones
+const FALSE_NORTHING_SOUTH: f64 = 10_000_000.0;
+
+/// Mean Earth radius, metres, for the scale factor approximation
+const MEAN_RADIUS: f64 = 6_371_008.8;
+
+/// A WGS84 UTM zone, EPSG 326xx (north) or 327xx (south)
+///
+/// Projects with Krüger's series for the transverse Mercator to sixth order in the
+/// third flattening, as in Karney (2011), "Transverse Mercator with an accuracy of a
+/// few nanometers". Within a zone this is accurate to well under a millimetre.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Utm {
+    /// Zone number, 1 to 60
+    pub zone: u8,
+    pub north: bool,
+}
+
+/// Series coefficients derived from the ellipsoid
+struct Series {
+    /// Eccentricity
+    e: f64,
+    /// Rectifying radius scaled by `K0`
+    k0_a: f64,
+    alpha: [f64; 6],
+    beta: [f64; 6],
+}
+
+impl Series {
+    fn wgs84() -> Self {
+        let f = WGS84_F;
+        let n = f / (2.0 - f);
+        let (n2, n3, n4, n5, n6) = (n * n, n.powi(3), n.powi(4), n.powi(5), n.powi(6));
+
+        let a = WGS84_A / (1.0 + n) * (1.0 + n2 / 4.0 + n4 / 64.0 + n6 / 256.0);
+        let alpha = [
+            n / 2.0 - 2.0 * n2 / 3.0 + 5.0 * n3 / 16.0 + 41.0 * n4 / 180.0 - 127.0 * n5 / 288.0
+                + 7891.0 * n6 / 37800.0,
+            13.0 * n2 / 48.0 - 3.0 * n3 / 5.0 + 557.0 * n4 / 1440.0 + 281.0 * n5 / 630.0
+                - 1983433.0 * n6 / 1935360.0,
+            61.0 * n3 / 240.0 - 103.0 * n4 / 140.0
+                + 15061.0 * n5 / 26880.0
+                + 167603.0 * n6 / 181440.0,
+            49561.0 * n4 / 161280.0 - 179.0 * n5 / 168.0 + 6601661.0 * n6 / 7257600.0,
+            34729.0 * n5 / 80640.0 - 3418889.0 * n6 / 1995840.0,
+            212378941.0 * n6 / 319334400.0,
+        ];
+        let beta = [
+            n / 2.0 - 2.0 * n2 / 3.0 + 37.0 * n3 / 96.0 - n4 / 360.0 - 81.0 * n5 / 512.0
+                + 96199.0 * n6 / 604800.0,
+            n2 / 48.0 + n3 / 15.0 - 437.0 * n4 / 1440.0 + 46.0 * n5 / 105.0
+                - 1118711.0 * n6 / 3870720.0,
+            17.0 * n3 / 480.0 - 37.0 * n4 / 840.0 - 209.0 * n5 / 4480.0 + 5569.0 * n6 / 90720.0,
+            4397.0 * n4 / 161280.0 - 11.0 * n5 / 504.0 - 830251.0 * n6 / 7257600.0,
+            4583.0 * n5 / 161280.0 - 108847.0 * n6 / 3991680.0,
+            20648693.0 * n6 / 638668800.0,
+        ];
+
+        Self { e: (f * (2.0 - f)).sqrt(), k0_a: K0 * a, alpha, beta }
+    }
+
+    /// Conformal latitude's tangent from the geodetic latitude's tangent
+    fn tau_prime(&self, tau: f64) -> f64 {
+        let sigma = (self.e * (self.e * tau / (1.0 + tau * tau).sqrt()).atanh()).sinh();
+        tau * (1.0 + sigma * sigma).sqrt() - sigma * (1.0 + tau * tau).sqrt()
+    }
+
+    /// Inverse of [`Series::tau_prime`] by Newton's method
+    fn tau(&self, tau_prime: f64) -> f64 {
+        let e2m = 1.0 - self.e * self.e;
+        let mut tau = tau_prime;
+        for _ in 0..10 {
+            let tau_i = self.tau_prime(tau);
+            let delta = (tau_prime - tau_i) / (1.0 + tau_i * tau_i).sqrt()
+                * (1.0 + e2m * tau * tau)
+                / (e2m * (1.0 + tau * tau).sqrt());
+            tau += delta;
+            if delta.abs() < 1e-14 * tau.abs().max(1.0) {
+                break;
+            }
+        }
+        tau
+    }
+}
+
+impl Utm {
+    /// The zone of a WGS84 UTM EPSG code
+    pub fn from_epsg(epsg: u32) -> Result<Self> {
+        let (north, zone) = match epsg {
+            32601..=32660 => (true, epsg - 32600),
+            32701..=32760 => (false, epsg - 32700),
+            _ => return Err(anyhow::anyhow!("EPSG:{} is not a WGS84 UTM zone", epsg)),
+        };
+        Ok(Self { zone: zone as u8, north })
+    }
+
+    pub fn epsg(&self) -> u32 {
+        (if self.north { 32600 } else { 32700 }) + u32::from(self.zone)
+    }
+
+    /// Longitude of the zone's central meridian, degrees
+    pub fn central_meridian(&self) -> f64 {
+        f64::from(self.zone) * 6.0 - 183.0
+    }
+
+    fn false_northing(&self) -> f64 {
+        if self.north {
+            0.0
+        } else {
+            FALSE_NORTHING_SOUTH
+        }
+    }
+
+    /// Grid scale factor at `easting`
+    ///
+    /// Uses the spherical approximation `k0 (1 + x^2 / 2R^2)`, with `x` the distance
+    /// from the central meridian, which is within a few parts per million across a zone.
+    pub fn scale_factor(&self, easting: f64) -> f64 {
+        let x = (easting - FALSE_EASTING) / K0;
+        K0 * (1.0 + x * x / (2.0 * MEAN_RADIUS * MEAN_RADIUS))
+    }
+
+    /// Projects WGS84 `(lon, lat)` degrees to `(easting, northing)` metres
+    pub fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let s = Series::wgs84();
+        let lambda = (lon - self.central_meridian()).to_radians();
+        let tau_prime = s.tau_prime(lat.to_radians().tan());
+
+        let xi_prime = tau_prime.atan2(lambda.cos());
+        let eta_prime =
+            (lambda.sin() / (tau_prime * tau_prime + lambda.cos().powi(2)).sqrt()).asinh();
+
+        let (mut xi, mut eta) = (xi_prime, eta_prime);
+        for (j, alpha) in s.alpha.iter().enumerate() {
+            let k = 2.0 * (j + 1) as f64;
+            xi += alpha * (k * xi_prime).sin() * (k * eta_prime).cosh();
+            eta += alpha * (k * xi_prime).cos() * (k * eta_prime).sinh();
+        }
+
+        (FALSE_EASTING + s.k0_a * eta, self.false_northing() + s.k0_a * xi)
+    }
+
+    /// Unprojects `(easting, northing)` metres to WGS84 `(lon, lat)` degrees
+    pub fn inverse(&self, easting: f64, northing: f64) -> (f64, f64) {
+        let s = Series::wgs84();
+        let xi = (northing - self.false_northing()) / s.k0_a;
+        let eta = (easting - FALSE_EASTING) / s.k0_a;
+
+        let (mut xi_prime, mut eta_prime) = (xi, eta);
+        for (j, beta) in s.beta.iter().enumerate() {
+            let k = 2.0 * (j + 1) as f64;
+            xi_prime -= beta * (k * xi).sin() * (k * eta).cosh();
+            eta_prime -= beta * (k * xi).cos() * (k * eta).sinh();
+        }
+
+        let tau_prime = xi_prime.sin() / (eta_prime.sinh().powi(2) + xi_prime.cos().powi(2)).sqrt();
+        let lambda = eta_prime.sinh().atan2(xi_prime.cos());
+
+        (self.central_meridian() + lambda.to_degrees(), s.tau(tau_prime).atan().to_degrees())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64), tolerance: f64) {
+        assert!(
+            (actual.0 - expected.0).abs() < tolerance && (actual.1 - expected.1).abs() < tolerance,
+            "{:?} differs from {:?} by more than {}",
+            actual,
+            expected,
+            tolerance
+        );
+    }
+
+    #[test]
+    fn epsg_codes() {
+        assert_eq!(Utm::from_epsg(32610).unwrap(), Utm { zone: 10, north: true });
+        assert_eq!(Utm::from_epsg(32756).unwrap(), Utm { zone: 56, north: false });
+        assert_eq!(Utm::from_epsg(32610).unwrap().central_meridian(), -123.0);
+        assert_eq!(Utm { zone: 1, north: false }.epsg(), 32701);
+        assert!(Utm::from_epsg(4326).is_err());
+        assert!(Utm::from_epsg(32661).is_err());
+    }
+
+    #[test]
+    fn forward_reference_points() {
+        // GeoConvert documentation example, 33.3N 44.4E -> 38n 444140.54 3684706.36
+        let zone38 = Utm::from_epsg(32638).unwrap();
+        assert_close(zone38.forward(44.4, 33.3), (444_140.54, 3_684_706.36), 0.005);
+
+        // zone edges on the equator, 3 degrees either side of the central meridian
+        let zone31 = Utm::from_epsg(32631).unwrap();
+        assert_close(zone31.forward(0.0, 0.0), (166_021.443_081, 0.0), 1e-4);
+        assert_close(zone31.forward(6.0, 0.0), (833_978.556_919, 0.0), 1e-4);
+
+        // on the central meridian only the northing varies
+        let zone13 = Utm::from_epsg(32613).unwrap();
+        assert_close(zone13.forward(-105.0, 40.0), (500_000.0, 4_427_757.219), 0.005);
+
+        // CN Tower, Toronto, 17T
+        let zone17 = Utm::from_epsg(32617).unwrap();
+        assert_close(zone17.forward(-79.387_139, 43.642_567), (630_084.30, 4_833_438.59), 0.05);
+
+        // Sydney, 56H, with the southern false northing
+        let zone56s = Utm::from_epsg(32756).unwrap();
+        assert_close(zone56s.forward(151.2093, -33.8688), (334_368.63, 6_250_948.35), 0.05);
+    }
+
+    #[test]
+    fn example_item_origin() {
+        // `proj:transform` of the example item places its upper-left corner at
+        // (499980, 4200000) in EPSG:32610, just west of the -123 central meridian
+        let zone10 = Utm::from_epsg(32610).unwrap();
+        let (lon, lat) = zone10.inverse(499_980.0, 4_200_000.0);
+        assert!((lon - -123.000_227_635).abs() < 1e-8, "lon {}", lon);
+        assert!((lat - 37.947_589_572).abs() < 1e-8, "lat {}", lat);
+    }
+
+    #[test]
+    fn round_trip() {
+        for epsg in [32610, 32631, 32756, 32701] {
+            let utm = Utm::from_epsg(epsg).unwrap();
+            let lat_sign = if utm.north { 1.0 } else { -1.0 };
+            for dlon in [-3.0, -1.5, 0.0, 0.7, 3.0] {
+                for lat in [0.5, 15.0, 37.8, 60.0, 80.0] {
+                    let (lon, lat) = (utm.central_meridian() + dlon, lat * lat_sign);
+                    let (x, y) = utm.forward(lon, lat);
+                    let (lon2, lat2) = utm.inverse(x, y);
+                    assert!((lon - lon2).abs() < 1e-9 && (lat - lat2).abs() < 1e-9);
+                }
+            }
+        }
+    }
+}
@@ -26,8 +26,16 @@ contract ShowResult is Common {
             console.log("Trigger ID:", triggerId, " is not valid");
         }
 
-        bytes memory triggerData = submit.getData(triggerIdTyped);
-        console.log("Data:", string(triggerData));
+        ITypes.RegenResult memory result = submit.getResult(triggerIdTyped);
+        console.log("CID:", result.cid);
+        console.log("Item:", result.itemId);
+        console.log("Scene timestamp:", result.sceneTimestamp);
+        console.log("Cloud cover (bps):", result.cloudCover);
+        console.log("Index:", result.index);
+        console.log("Mean index (1e6):", vm.toString(result.meanIndex));
+        console.log("Status:", result.status);
+        console.log("Mean delta (1e6):", vm.toString(result.meanDelta));
+        console.log("Improved / degraded / unchanged (bps):", result.improved, result.degraded, result.unchanged);
     }
 
 
@@ -8,11 +8,14 @@ import {console} from "forge-std/console.sol";
 
 /// @dev Script to add a new trigger
 contract Trigger is Common {
-    function run(string calldata serviceTriggerAddr, string calldata coinMarketCapID) public {
+    /// @param regenQuery ABI encoded ITypes.RegenQuery
+    function run(string calldata serviceTriggerAddr, bytes calldata regenQuery) public {
         vm.startBroadcast(_privateKey);
         SimpleTrigger trigger = SimpleTrigger(vm.parseAddress(serviceTriggerAddr));
 
-        trigger.addTrigger(abi.encodePacked(coinMarketCapID));
+        // Reverts early on malformed requests instead of failing inside the component
+        abi.decode(regenQuery, (ITypes.RegenQuery));
+        trigger.addTrigger(regenQuery);
         ITypes.TriggerId triggerId = trigger.nextTriggerId();
         console.log("TriggerId", ITypes.TriggerId.unwrap(triggerId));
         vm.stopBroadcast();
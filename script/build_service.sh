@@ -76,8 +76,8 @@ echo "Component ID: ${COMPONENT_ID}"
 
 $BASE_CMD workflow component --id ${COMPONENT_ID} permissions --http-hosts '*' --file-system true > /dev/null
 $BASE_CMD workflow component --id ${COMPONENT_ID} time-limit --seconds 30 > /dev/null
-$BASE_CMD workflow component --id ${COMPONENT_ID} env --values WAVS_ENV_SOME_SECRET > /dev/null
-$BASE_CMD workflow component --id ${COMPONENT_ID} config --values 'key=value,key2=value2' > /dev/null
+$BASE_CMD workflow component --id ${COMPONENT_ID} env --values WAVS_ENV_IPFS_PINNER,WAVS_ENV_LIGHTHOUSE_API_URL,WAVS_ENV_PINATA_API_URL,WAVS_ENV_KUBO_API_URL,WAVS_ENV_IPFS_CID_VERSION,WAVS_ENV_LIGHTHOUSE_API_KEY,WAVS_ENV_PINATA_JWT,WAVS_ENV_KUBO_API_AUTH > /dev/null
+$BASE_CMD workflow component --id ${COMPONENT_ID} config --values 'stac_api_url=https://earth-search.aws.element84.com/v1/search,collection=sentinel-2-l2a' > /dev/null
 
 $BASE_CMD manager set-ethereum ${SUBMIT_CHAIN} `cast --to-checksum ${SERVICE_MANAGER_ADDRESS}`
 $BASE_CMD validate > /dev/null
@@ -46,4 +46,13 @@ contract SimpleSubmit is ITypes, IWavsServiceHandler {
     function getData(TriggerId _triggerId) external view returns (bytes memory _data) {
         _data = _datas[_triggerId];
     }
+
+    /**
+     * @notice Get the decoded regen oracle result for a trigger
+     * @param _triggerId The identifier of the trigger
+     * @return _result The result submitted by the operators
+     */
+    function getResult(TriggerId _triggerId) external view returns (RegenResult memory _result) {
+        _result = abi.decode(_datas[_triggerId], (RegenResult));
+    }
 }
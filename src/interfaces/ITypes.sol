@@ -24,6 +24,80 @@ interface ITypes {
         bytes data;
     }
 
+    /**
+     * @notice Regen oracle request, ABI encoded as the trigger data
+     * @dev Coordinates are WGS84 degrees scaled by 1e7. Bits of `outputs`:
+     *      0x1 = upload the scene's rasters of the index's input bands to IPFS
+     * @param minLon Western bound of the area of interest
+     * @param minLat Southern bound of the area of interest
+     * @param maxLon Eastern bound of the area of interest
+     * @param maxLat Northern bound of the area of interest
+     * @param startTime Start of the acquisition window (unix seconds)
+     * @param endTime End of the acquisition window (unix seconds, inclusive)
+     * @param maxCloudCover Maximum scene cloud cover in basis points (10000 = 100%)
+     * @param collection STAC collection id, empty for the service default
+     * @param outputs Bitmask of requested outputs
+     * @param geometry GeoJSON Polygon, MultiPolygon or Feature in WGS84 outlining the
+     *        parcel(s), empty to use the bbox. When set, the bbox fields are ignored.
+     * @param index Spectral index to compute: ndvi, evi, savi, ndwi, ndmi, nbr or ndre;
+     *        empty for ndvi
+     * @param mode 0 = statistics of the most recent matching scene, 1 = time series of every
+     *        scene in the acquisition window, uploaded as JSON and CSV, 2 = change between the
+     *        composites of the baseline window and the acquisition window, 3 = composite of the
+     *        acquisition window
+     * @param baselineStart Start of the baseline window in mode 2 (unix seconds)
+     * @param baselineEnd End of the baseline window in mode 2 (unix seconds, inclusive)
+     */
+    struct RegenQuery {
+        int32 minLon;
+        int32 minLat;
+        int32 maxLon;
+        int32 maxLat;
+        uint64 startTime;
+        uint64 endTime;
+        uint16 maxCloudCover;
+        string collection;
+        uint32 outputs;
+        string geometry;
+        string index;
+        uint8 mode;
+        uint64 baselineStart;
+        uint64 baselineEnd;
+    }
+
+    /**
+     * @notice Regen oracle result, ABI encoded as the DataWithId data
+     * @dev Status codes: 0 = ok, 1 = no scene matched the query, 2 = the index could not be computed
+     * @param cid CID of the IPFS directory holding the result bundle: metadata.json with the
+     *        index raster, preview and band sources in mode 0, series.json and series.csv in
+     *        mode 1, change.json and the delta raster in mode 2, composite.json and the
+     *        composite and count rasters in mode 3. In modes 1 to 3 the scene fields describe
+     *        the latest scene of the acquisition window.
+     * @param itemId STAC item id of the selected scene
+     * @param sceneTimestamp Acquisition time of the scene (unix seconds)
+     * @param cloudCover Scene cloud cover in basis points (10000 = 100%)
+     * @param index Spectral index that was computed
+     * @param meanIndex Mean index value over the area of interest scaled by 1e6 (1e6 = 1.0)
+     * @param status Result status code
+     * @param meanDelta Mode 2: mean index change, monitoring minus baseline, scaled by 1e6
+     * @param improved Mode 2: fraction of AOI pixels whose index rose above the gain threshold (bps)
+     * @param degraded Mode 2: fraction of AOI pixels whose index fell below the loss threshold (bps)
+     * @param unchanged Mode 2: fraction of AOI pixels within both thresholds (bps)
+     */
+    struct RegenResult {
+        string cid;
+        string itemId;
+        uint64 sceneTimestamp;
+        uint16 cloudCover;
+        string index;
+        int32 meanIndex;
+        uint8 status;
+        int32 meanDelta;
+        uint16 improved;
+        uint16 degraded;
+        uint16 unchanged;
+    }
+
     /**
      * @notice Event emitted when a new trigger is created
      * @param _triggerInfo Encoded TriggerInfo struct